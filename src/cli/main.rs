@@ -3,6 +3,7 @@
 #![doc(hidden)]  // Don't interfere with lib docs.
 
 extern crate rayon;
+extern crate walkdir;
 extern crate structopt;
 
 extern crate serde;
@@ -37,6 +38,7 @@ fn main() {
 
     let mut context = Context::default();
     context.dm_context.set_print_severity(Some(dm::Severity::Error));
+    context.define_overrides = opt.define_overrides();
     rayon::ThreadPoolBuilder::new()
         .num_threads(opt.jobs)
         .build_global()
@@ -56,30 +58,43 @@ struct Context {
     exit_status: AtomicIsize,
     parallel: bool,
     procs: bool,
+    /// Defines to set or remove before preprocessing.
+    define_overrides: Vec<dm::preprocessor::DefineOverride>,
+    /// Whether stdout is reserved for machine-readable output.
+    data_on_stdout: bool,
 }
 
 impl Context {
-    fn objtree(&mut self, opt: &Opt) {
-        let environment = match opt.environment {
+    fn environment(&self, opt: &Opt) -> std::path::PathBuf {
+        match opt.environment {
             Some(ref env) => env.into(),
             None => match dm::detect_environment_default() {
                 Ok(Some(found)) => found,
                 _ => dm::DEFAULT_ENV.into(),
             },
-        };
-        println!("parsing {}", environment.display());
+        }
+    }
+
+    fn objtree(&mut self, opt: &Opt) {
+        let environment = self.environment(opt);
+        if self.data_on_stdout {
+            eprintln!("parsing {}", environment.display());
+        } else {
+            println!("parsing {}", environment.display());
+        }
 
         if let Some(parent) = environment.parent() {
             self.icon_cache.set_icons_root(&parent);
         }
 
-        let pp = match dm::preprocessor::Preprocessor::new(&self.dm_context, environment) {
+        let mut pp = match dm::preprocessor::Preprocessor::new(&self.dm_context, environment) {
             Ok(pp) => pp,
             Err(e) => {
                 eprintln!("i/o error opening environment:\n{}", e);
                 std::process::exit(1);
             }
         };
+        pp.set_define_overrides(&self.define_overrides);
         let indents = dm::indents::IndentProcessor::new(&self.dm_context, pp);
         let mut parser = dm::parser::Parser::new(&self.dm_context, indents);
         if self.procs {
@@ -103,6 +118,14 @@ struct Opt {
     #[structopt(short="v", long="verbose")]
     verbose: bool,
 
+    /// Define a macro before preprocessing, as NAME or NAME=VALUE.
+    #[structopt(short="D", number_of_values=1)]
+    defines: Vec<String>,
+
+    /// Undefine a builtin macro before preprocessing.
+    #[structopt(short="U", number_of_values=1)]
+    undefines: Vec<String>,
+
     /// Set the number of threads to be used for parallel execution when
     /// possible. A value of 0 will select automatically, and 1 will be serial.
     #[structopt(long="jobs", default_value="1")]
@@ -112,6 +135,15 @@ struct Opt {
     command: Command,
 }
 
+impl Opt {
+    fn define_overrides(&self) -> Vec<dm::preprocessor::DefineOverride> {
+        use dm::preprocessor::DefineOverride;
+        self.defines.iter().map(|d| DefineOverride::define(d))
+            .chain(self.undefines.iter().map(|u| DefineOverride::undefine(u)))
+            .collect()
+    }
+}
+
 // ----------------------------------------------------------------------------
 // Subcommands
 
@@ -133,6 +165,11 @@ enum Command {
         /// Check proc bodies as well as the object tree.
         #[structopt(long="procs")]
         procs: bool,
+        /// Also check under a configuration of comma-separated NAME or
+        /// NAME=VALUE to define and !NAME to undefine, and report only
+        /// diagnostics which differ between configurations. May be repeated.
+        #[structopt(long="config", number_of_values=1)]
+        configs: Vec<String>,
     },
     /// Build minimaps of the specified maps.
     #[structopt(name = "minimap")]
@@ -184,6 +221,51 @@ enum Command {
         /// The list of maps to show info on.
         files: Vec<String>,
     },
+    /// Show the preprocessed contents of a file, marking macro expansions.
+    #[structopt(name="preprocess")]
+    Preprocess {
+        /// Omit the expansion markers.
+        #[structopt(long="no-markers")]
+        no_markers: bool,
+
+        /// The file to preprocess.
+        file: String,
+    },
+    /// Find types in the object tree matching a query.
+    ///
+    /// Clauses are separated by spaces and must all match: "/obj/item" for
+    /// a type and its subtypes, "path:text" for path substrings, "var:name"
+    /// for var presence, "name>value" (or =, !=, <, <=, >=) for var values,
+    /// "proc:name" for proc presence, and "defines:name" for types defining
+    /// or overriding a proc. Prefix a clause with "!" to negate it.
+    #[structopt(name="query")]
+    Query {
+        /// Output as JSON.
+        #[structopt(short="j", long="json")]
+        json: bool,
+
+        /// The query clauses.
+        query: Vec<String>,
+    },
+    /// Export the entire object tree as JSON.
+    #[structopt(name="export-objtree")]
+    ExportObjtree {
+        /// The output file, or standard output if omitted.
+        #[structopt(short="o")]
+        output: Option<String>,
+
+        /// Indent the output for readability.
+        #[structopt(long="pretty")]
+        pretty: bool,
+    },
+    /// List .dm and .dmm files which the environment never includes, and
+    /// includes in the environment which are out of DreamMaker's order.
+    #[structopt(name="includes")]
+    Includes {
+        /// Also write the include graph in Graphviz DOT format to this file.
+        #[structopt(long="dot")]
+        dot: Option<String>,
+    },
 }
 
 fn run(opt: &Opt, command: &Command, context: &mut Context) {
@@ -224,7 +306,7 @@ fn run(opt: &Opt, command: &Command, context: &mut Context) {
             }
         },
         // --------------------------------------------------------------------
-        Command::Check { ref severity, procs } => {
+        Command::Check { ref severity, procs, ref configs } => {
             let severity = match severity.as_str() {
                 "error" => dm::Severity::Error,
                 "warning" => dm::Severity::Warning,
@@ -233,6 +315,37 @@ fn run(opt: &Opt, command: &Command, context: &mut Context) {
             };
             context.dm_context.set_print_severity(Some(severity));
             context.procs = procs;
+            if !configs.is_empty() {
+                use dm::multiconfig::{Configuration, diagnose_configurations};
+
+                let environment = context.environment(opt);
+                let mut configurations = vec![Configuration::parse("")];
+                configurations.extend(configs.iter().map(|spec| Configuration::parse(spec)));
+                eprintln!("comparing {} configurations of {}", configurations.len(), environment.display());
+                diagnose_configurations(&context.dm_context, &context.define_overrides, &configurations, |run_context, defines| {
+                    let mut pp = match dm::preprocessor::Preprocessor::new(run_context, environment.clone()) {
+                        Ok(pp) => pp,
+                        Err(e) => {
+                            eprintln!("i/o error opening environment:\n{}", e);
+                            std::process::exit(1);
+                        }
+                    };
+                    pp.set_define_overrides(defines);
+                    let indents = dm::indents::IndentProcessor::new(run_context, pp);
+                    let mut parser = dm::parser::Parser::new(run_context, indents);
+                    if procs {
+                        parser.enable_procs();
+                    }
+                    parser.parse_object_tree();
+                });
+                *context.exit_status.get_mut() = context
+                    .dm_context
+                    .errors()
+                    .iter()
+                    .filter(|e| e.severity() <= severity)
+                    .count() as isize;
+                return;
+            }
             context.objtree(opt);
             *context.exit_status.get_mut() = context
                 .dm_context
@@ -431,6 +544,231 @@ fn run(opt: &Opt, command: &Command, context: &mut Context) {
             output_json(&report);
         },
         // --------------------------------------------------------------------
+        Command::Preprocess {
+            no_markers, ref file,
+        } => {
+            use dm::lexer::{Token, Punctuation};
+
+            let mut pp = match dm::preprocessor::Preprocessor::new(&context.dm_context, file.into()) {
+                Ok(pp) => pp,
+                Err(e) => {
+                    eprintln!("i/o error opening {}:\n{}", file, e);
+                    std::process::exit(1);
+                }
+            };
+            pp.set_define_overrides(&context.define_overrides);
+            if !no_markers {
+                pp.enable_expansion_trace();
+            }
+
+            let mut seen = 0;
+            let mut prev = None;
+            while let Some(tok) = pp.next() {
+                if let Some(trace) = pp.expansion_trace() {
+                    for expansion in &trace[seen..] {
+                        if prev.is_some() {
+                            println!();
+                        }
+                        println!(
+                            "{:indent$}/* {}:{}: {} */",
+                            "",
+                            context.dm_context.file_path(expansion.location.file).display(),
+                            expansion.location.line,
+                            expansion,
+                            indent = 2 * expansion.depth(trace),
+                        );
+                        prev = None;
+                    }
+                    seen = trace.len();
+                }
+
+                match tok.token {
+                    Token::Punct(Punctuation::Newline) => {
+                        if prev.is_some() {
+                            println!();
+                        }
+                        prev = None;
+                    }
+                    Token::DocComment(_) => {}
+                    other => {
+                        if let Some(ref prev) = prev {
+                            if other.separate_from(prev) {
+                                print!(" ");
+                            }
+                        }
+                        print!("{}", other);
+                        prev = Some(other);
+                    }
+                }
+            }
+            if prev.is_some() {
+                println!();
+            }
+            *context.exit_status.get_mut() = context
+                .dm_context
+                .errors()
+                .iter()
+                .filter(|e| e.severity() <= dm::Severity::Error)
+                .count() as isize;
+        },
+        // --------------------------------------------------------------------
+        Command::Query {
+            json, ref query,
+        } => {
+            let query: dm::objtree::TypeQuery = match query.join(" ").parse() {
+                Ok(query) => query,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            context.data_on_stdout = json;
+            context.objtree(opt);
+            let var_names = query.var_names();
+
+            #[derive(Serialize)]
+            struct Type<'a> {
+                path: &'a str,
+                file: std::path::PathBuf,
+                line: u32,
+                vars: HashMap<&'a str, String>,
+            }
+
+            let mut report = Vec::new();
+            for ty in context.objtree.query(&query) {
+                let mut vars = HashMap::new();
+                for &name in var_names.iter() {
+                    let value = match ty.get_value(name) {
+                        Some(&dm::objtree::VarValue { constant: Some(ref constant), .. }) => constant.to_string(),
+                        Some(_) => "(non-constant)".to_owned(),
+                        None => "(none)".to_owned(),
+                    };
+                    vars.insert(name, value);
+                }
+                report.push(Type {
+                    path: ty.get().pretty_path(),
+                    file: context.dm_context.file_path(ty.location.file),
+                    line: ty.location.line,
+                    vars,
+                });
+            }
+
+            if json {
+                output_json(&report);
+            } else {
+                let mut rows = vec![{
+                    let mut header = vec!["path".to_owned(), "location".to_owned()];
+                    header.extend(var_names.iter().map(|&name| name.to_owned()));
+                    header
+                }];
+                for ty in report.iter() {
+                    let mut row = vec![ty.path.to_owned(), format!("{}:{}", ty.file.display(), ty.line)];
+                    row.extend(var_names.iter().map(|name| ty.vars[name].clone()));
+                    rows.push(row);
+                }
+                let mut widths = vec![0; rows[0].len()];
+                for row in rows.iter() {
+                    for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                        *width = std::cmp::max(*width, cell.len());
+                    }
+                }
+                for row in rows.iter() {
+                    let mut line = String::new();
+                    for (cell, &width) in row.iter().zip(widths.iter()) {
+                        line.push_str(&format!("{:width$}  ", cell, width = width));
+                    }
+                    println!("{}", line.trim_end());
+                }
+                eprintln!("{} matching types", report.len());
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::ExportObjtree {
+            ref output, pretty,
+        } => {
+            context.data_on_stdout = output.is_none();
+            context.objtree(opt);
+            let result = match output {
+                Some(path) => std::fs::File::create(path)
+                    .map(std::io::BufWriter::new)
+                    .and_then(|file| objtree_json::write_json(&context.dm_context, &context.objtree, pretty, file)),
+                None => {
+                    let stdout = std::io::stdout();
+                    let result = objtree_json::write_json(&context.dm_context, &context.objtree, pretty, stdout.lock());
+                    println!();
+                    result
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("i/o error writing object tree:\n{}", e);
+                std::process::exit(1);
+            }
+        },
+        // --------------------------------------------------------------------
+        Command::Includes {
+            ref dot,
+        } => {
+            let environment = context.environment(opt);
+            let mut pp = match dm::preprocessor::Preprocessor::new(&context.dm_context, environment.clone()) {
+                Ok(pp) => pp,
+                Err(e) => {
+                    eprintln!("i/o error opening {}:\n{}", environment.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            pp.set_define_overrides(&context.define_overrides);
+            pp.by_ref().for_each(drop);
+            let graph = pp.include_graph();
+
+            let root = graph.environment_dir();
+            let mut files = Vec::new();
+            let walker = walkdir::WalkDir::new(root).into_iter().filter_entry(|entry| {
+                // Skip hidden directories such as `.git`.
+                entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+            });
+            for entry in walker {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        eprintln!("i/o error listing files:\n{}", e);
+                        std::process::exit(1);
+                    }
+                };
+                if entry.file_type().is_file() {
+                    files.push(entry.path().strip_prefix(root).unwrap_or_else(|_| entry.path()).to_owned());
+                }
+            }
+            let unincluded = graph.unincluded_files(files);
+            for path in unincluded.iter() {
+                println!("not included: {}", path.display());
+            }
+
+            let misordered = graph.misordered_includes();
+            for (previous, inclusion) in misordered.iter() {
+                println!(
+                    "{}:{}: {} should come before {}",
+                    context.dm_context.file_path(inclusion.location.file).display(),
+                    inclusion.location.line,
+                    inclusion.path.display(),
+                    previous.path.display(),
+                );
+            }
+
+            if let Some(dot) = dot {
+                let result = std::fs::File::create(dot)
+                    .map(std::io::BufWriter::new)
+                    .and_then(|file| graph.write_dot(&context.dm_context, file));
+                if let Err(e) = result {
+                    eprintln!("i/o error writing include graph:\n{}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            if !unincluded.is_empty() || !misordered.is_empty() {
+                context.exit_status.fetch_add(1, Ordering::Relaxed);
+            }
+        },
+        // --------------------------------------------------------------------
     }
 }
 
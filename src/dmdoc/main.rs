@@ -17,6 +17,7 @@ use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 
 use dm::docs::*;
+use dm::preprocessor::DefineOverride;
 
 use markdown::DocBlock;
 
@@ -40,6 +41,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut environment = None;
     let mut output_path = "dmdoc".to_owned();
     let mut modules_path = "code".to_owned();
+    let mut define_overrides = Vec::new();
 
     let mut args = std::env::args();
     let _ = args.next();  // skip executable name
@@ -53,6 +55,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             output_path = args.next().expect("must specify a value for --output");
         } else if arg == "--modules" {
             modules_path = args.next().expect("must specify a value for --modules");
+        } else if let Some(result) = DefineOverride::parse_arg(&arg, &mut args) {
+            define_overrides.push(result?);
         } else {
             return Err(format!("unknown argument: {}", arg).into());
         }
@@ -109,6 +113,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut context = dm::Context::default();
     context.set_print_severity(Some(dm::Severity::Error));
     let mut pp = dm::preprocessor::Preprocessor::new(&context, environment.clone())?;
+    pp.set_define_overrides(&define_overrides);
     let (objtree, module_docs) = {
         let indents = dm::indents::IndentProcessor::new(&context, &mut pp);
         let parser = dm::parser::Parser::new(&context, indents);
@@ -0,0 +1,248 @@
+//! A graph of which procs call which, built from the same static typing used
+//! to check proc calls.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+
+use dm::{Context, Location};
+use dm::objtree::{ObjectTree, ProcRef, TypeRef};
+
+use crate::AnalyzeObjectTree;
+
+/// How a call site selects the proc it calls.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CallKind {
+    /// A call which always reaches exactly the named proc, such as `.()`,
+    /// `new`, or an operator overload.
+    Static,
+    /// A `..()` call to the parent proc.
+    Parent,
+    /// A call which may reach the named proc or any override of it on a
+    /// subtype of the static type.
+    Virtual,
+}
+
+impl CallKind {
+    /// Whether calls of this kind never reach an override.
+    pub fn is_exact(self) -> bool {
+        self != CallKind::Virtual
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CallKind::Static => "static",
+            CallKind::Parent => "parent",
+            CallKind::Virtual => "virtual",
+        }
+    }
+}
+
+/// A single resolved edge in the call graph.
+#[derive(Debug, Copy, Clone)]
+pub struct Call<'o> {
+    pub caller: ProcRef<'o>,
+    pub callee: ProcRef<'o>,
+    pub kind: CallKind,
+    /// Whether the callee is only reached by dispatch to an override, rather
+    /// than being the proc the call site resolves to statically.
+    pub is_override: bool,
+    pub location: Location,
+}
+
+/// A call site, as found by the analysis. Overrides which a virtual call
+/// may reach are resolved only when the graph is queried.
+#[derive(Debug, Copy, Clone)]
+struct CallSite<'o> {
+    call: Call<'o>,
+    /// The static type the call was resolved against.
+    src: TypeRef<'o>,
+}
+
+/// The procs which each proc may call, and the procs which may call it.
+#[derive(Default)]
+pub struct CallGraph<'o> {
+    sites: Vec<CallSite<'o>>,
+    by_caller: HashMap<ProcRef<'o>, Vec<usize>>,
+    by_callee: HashMap<ProcRef<'o>, Vec<usize>>,
+    /// Virtual call sites by the name of the proc called.
+    virtual_by_name: HashMap<String, Vec<usize>>,
+}
+
+impl<'o> CallGraph<'o> {
+    /// Build the call graph for every proc in the object tree.
+    ///
+    /// This runs the full proc analysis, so any diagnostics it finds are
+    /// registered with the context as well.
+    pub fn build(context: &'o Context, objtree: &'o ObjectTree) -> CallGraph<'o> {
+        let mut analyzer = AnalyzeObjectTree::new(context, objtree);
+        analyzer.enable_call_graph();
+
+        objtree.root().recurse(&mut |ty| {
+            for proc in ty.iter_self_procs() {
+                if let dm::objtree::Code::Present(ref code) = proc.get().code {
+                    analyzer.gather_settings(proc, code);
+                }
+            }
+        });
+
+        objtree.root().recurse(&mut |ty| {
+            for proc in ty.iter_self_procs() {
+                if let dm::objtree::Code::Present(ref code) = proc.get().code {
+                    analyzer.check_proc(proc, code);
+                }
+            }
+        });
+
+        analyzer.into_call_graph().unwrap_or_default()
+    }
+
+    /// Record a call from `caller` to `callee`, which was resolved against
+    /// the static type `src`.
+    pub(crate) fn add_call(&mut self, caller: ProcRef<'o>, src: TypeRef<'o>, callee: ProcRef<'o>, kind: CallKind, location: Location) {
+        let idx = self.sites.len();
+        self.by_caller.entry(caller).or_default().push(idx);
+        self.by_callee.entry(callee).or_default().push(idx);
+        if kind == CallKind::Virtual {
+            self.virtual_by_name.entry(callee.name().to_owned()).or_default().push(idx);
+        }
+        self.sites.push(CallSite {
+            call: Call { caller, callee, kind, is_override: false, location },
+            src,
+        });
+    }
+
+    /// The calls found, in the order they were found, each to the proc its
+    /// call site resolves to statically.
+    pub fn calls<'a>(&'a self) -> impl Iterator<Item=&'a Call<'o>> + 'a {
+        self.sites.iter().map(|site| &site.call)
+    }
+
+    /// The calls made by the given proc, including to every override a
+    /// virtual call may reach.
+    pub fn callees(&self, proc: ProcRef<'o>) -> Vec<Call<'o>> {
+        let mut calls = Vec::new();
+        for &idx in self.by_caller.get(&proc).map_or(&[][..], |v| &v[..]) {
+            self.resolve(&self.sites[idx], &mut calls);
+        }
+        calls
+    }
+
+    /// The calls which may reach the given proc, directly or by dispatch to
+    /// it as an override.
+    pub fn callers(&self, proc: ProcRef<'o>) -> Vec<Call<'o>> {
+        let mut calls: Vec<Call<'o>> = self.by_callee.get(&proc)
+            .map_or(&[][..], |v| &v[..])
+            .iter()
+            .map(|&idx| self.sites[idx].call)
+            .collect();
+
+        // Only the last definition of a proc on a type is dispatched to.
+        let ty = proc.ty();
+        if ty.get_proc(proc.name()) != Some(proc) {
+            return calls;
+        }
+        for &idx in self.virtual_by_name.get(proc.name()).map_or(&[][..], |v| &v[..]) {
+            let site = &self.sites[idx];
+            if site.call.callee != proc && ty.is_subtype_of(&site.src) {
+                calls.push(Call { callee: proc, is_override: true, .. site.call });
+            }
+        }
+        calls
+    }
+
+    /// Push the static call of a site, and each override it may reach.
+    fn resolve(&self, site: &CallSite<'o>, calls: &mut Vec<Call<'o>>) {
+        calls.push(site.call);
+        if site.call.kind != CallKind::Virtual {
+            return;
+        }
+        let callee = site.call.callee;
+        let mut seen = HashSet::new();
+        seen.insert(callee);
+        site.src.recurse(&mut |ty| {
+            if !ty.procs.contains_key(callee.name()) {
+                return;
+            }
+            if let Some(target) = ty.get_proc(callee.name()) {
+                if seen.insert(target) {
+                    calls.push(Call { callee: target, is_override: true, .. site.call });
+                }
+            }
+        });
+    }
+
+    /// Every call, including to the overrides virtual calls may reach.
+    fn all_calls(&self) -> Vec<Call<'o>> {
+        let mut calls = Vec::new();
+        for site in self.sites.iter() {
+            self.resolve(site, &mut calls);
+        }
+        calls
+    }
+
+    /// The distinct edges of the given calls, sorted for stable output.
+    fn edges(calls: &[Call<'o>]) -> BTreeSet<(String, String, CallKind, bool)> {
+        calls.iter()
+            .map(|call| (call.caller.to_string(), call.callee.to_string(), call.kind, call.is_override))
+            .collect()
+    }
+
+    /// Write the graph in Graphviz DOT format. Each pair of procs appears
+    /// once per kind of call between them.
+    pub fn write_dot<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "digraph calls {{")?;
+        for (caller, callee, kind, is_override) in CallGraph::edges(&self.all_calls()) {
+            let style = match (kind, is_override) {
+                (_, true) => " [style=dashed]",
+                (CallKind::Parent, _) => " [style=bold]",
+                _ => "",
+            };
+            writeln!(w, "    {:?} -> {:?}{};", caller, callee, style)?;
+        }
+        writeln!(w, "}}")
+    }
+
+    /// Write the graph as JSON: a list of edges, each with every location it
+    /// is called from.
+    pub fn write_json<W: Write>(&self, context: &Context, w: W) -> io::Result<()> {
+        #[derive(Serialize)]
+        struct Edge {
+            caller: String,
+            callee: String,
+            kind: &'static str,
+            is_override: bool,
+            locations: Vec<CallLocation>,
+        }
+
+        #[derive(Serialize)]
+        struct CallLocation {
+            file: String,
+            line: u32,
+            column: u16,
+        }
+
+        let calls = self.all_calls();
+        let mut edges: Vec<Edge> = Vec::new();
+        let mut index = HashMap::new();
+        for (caller, callee, kind, is_override) in CallGraph::edges(&calls) {
+            index.insert((caller.clone(), callee.clone(), kind, is_override), edges.len());
+            edges.push(Edge {
+                caller,
+                callee,
+                kind: kind.name(),
+                is_override,
+                locations: Vec::new(),
+            });
+        }
+        for call in calls.iter() {
+            let key = (call.caller.to_string(), call.callee.to_string(), call.kind, call.is_override);
+            edges[index[&key]].locations.push(CallLocation {
+                file: context.file_path(call.location.file).display().to_string(),
+                line: call.location.line,
+                column: call.location.column,
+            });
+        }
+
+        serde_json::to_writer(w, &edges).map_err(io::Error::from)
+    }
+}
@@ -0,0 +1,88 @@
+//! Facts found by the static typing which editors can show inline.
+
+use dm::{Context, FileId, Location};
+use dm::ast::{Expression, Term};
+use dm::objtree::{ObjectTree, ProcRef, TypeRef};
+
+use crate::AnalyzeObjectTree;
+
+/// A single inline hint.
+#[derive(Debug, Clone)]
+pub enum Hint<'o> {
+    /// A positional argument, starting at `location`, is passed as the named
+    /// parameter.
+    Argument {
+        location: Location,
+        parameter: &'o str,
+    },
+    /// A local var declared without a type has a value of a known type.
+    ///
+    /// The location is that of the statement declaring the var, which for
+    /// loop variables is the start of the loop.
+    LocalType {
+        location: Location,
+        name: &'o str,
+        ty: TypeRef<'o>,
+    },
+}
+
+/// Find the procs defined in the given file whose code may fall within the
+/// given lines. Each proc is taken to run until the next one defined in the
+/// file.
+pub fn procs_in_lines(objtree: &ObjectTree, file: FileId, first: u32, last: u32) -> Vec<ProcRef> {
+    let mut procs = Vec::new();
+    objtree.root().recurse(&mut |ty| {
+        procs.extend(ty.iter_self_procs().filter(|proc| proc.get().location.file == file));
+    });
+    procs.sort_by_key(|proc| proc.get().location);
+
+    let mut result = Vec::new();
+    for (i, proc) in procs.iter().enumerate() {
+        let start = proc.get().location.line;
+        let end = procs.get(i + 1).map_or(u32::max_value(), |next| next.get().location.line);
+        if start <= last && end >= first {
+            result.push(*proc);
+        }
+    }
+    result
+}
+
+/// Collect the hints for each of the given procs, in order.
+///
+/// Diagnostics found by the analysis are registered with the context, so
+/// callers will usually want to pass a scratch one.
+pub fn collect<'o>(context: &'o Context, objtree: &'o ObjectTree, procs: &[ProcRef<'o>]) -> Vec<Vec<Hint<'o>>> {
+    let mut analyzer = AnalyzeObjectTree::new(context, objtree);
+    procs.iter().map(|&proc| {
+        analyzer.hints = Some(Vec::new());
+        if let dm::objtree::Code::Present(ref code) = proc.get().code {
+            analyzer.check_proc(proc, code);
+        }
+        analyzer.hints.take().unwrap_or_default()
+    }).collect()
+}
+
+/// Find the parameter which the positional argument at `idx` is passed as.
+///
+/// Overrides which don't repeat their parameter list get the names from
+/// their parent.
+pub(crate) fn parameter_name<'o>(proc: ProcRef<'o>, idx: usize, argument: &Expression) -> Option<&'o str> {
+    let mut next = Some(proc);
+    while let Some(proc) = next {
+        let parameters = &proc.get().parameters;
+        if !parameters.is_empty() {
+            let name = &parameters.get(idx)?.name;
+            // Passing `foo` as `foo` needs no explanation.
+            let same_name = match argument.as_term() {
+                Some(Term::Ident(ident)) => ident == name,
+                _ => false,
+            };
+            if name == "..." || same_name {
+                return None;
+            }
+            return Some(name);
+        }
+        next = proc.parent_proc();
+    }
+    None
+}
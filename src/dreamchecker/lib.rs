@@ -2,9 +2,12 @@
 //! DreamMaker.
 #![allow(dead_code, unused_variables)]
 #[macro_use] extern crate guard;
+extern crate serde;
+extern crate serde_json;
+#[macro_use] extern crate serde_derive;
 
 extern crate dreammaker as dm;
-use dm::{Context, DMError, Location, Severity};
+use dm::{Context, DMError, Fix, Location, Severity};
 use dm::objtree::{ObjectTree, TypeRef, ProcRef};
 use dm::constants::{Constant, ConstFn};
 use dm::ast::*;
@@ -14,6 +17,12 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 mod type_expr;
 use type_expr::TypeExpr;
 
+pub mod call_graph;
+use call_graph::{CallGraph, CallKind};
+
+pub mod hints;
+use hints::Hint;
+
 // ----------------------------------------------------------------------------
 // Helper structures
 
@@ -169,7 +178,17 @@ pub struct Analysis<'o> {
     static_ty: StaticType<'o>,
     aset: AssumptionSet<'o>,
     value: Option<Constant>,
-    fix_hint: Option<(Location, String)>,
+    fix_hint: Option<FixHint>,
+}
+
+/// Where the type of an analysis could be made more specific.
+#[derive(Debug, Clone)]
+struct FixHint {
+    location: Location,
+    description: String,
+    /// The name of the var declared at `location`, if it is that var's type
+    /// which could be.
+    var_name: Option<String>,
 }
 
 impl<'o> Analysis<'o> {
@@ -195,6 +214,17 @@ impl<'o> Analysis<'o> {
         Analysis::from(StaticType::Type(ty))
     }
 
+    /// The type of this value, if either its static type or the assumptions
+    /// about it say.
+    fn known_type(&self) -> Option<TypeRef<'o>> {
+        self.static_ty.basic_type().or_else(|| {
+            self.aset.set.iter().filter_map(|assumption| match *assumption {
+                Assumption::IsType(true, ty) => Some(ty),
+                _ => None,
+            }).next()
+        })
+    }
+
     fn from_value(objtree: &'o ObjectTree, value: Constant, type_hint: Option<TypeRef<'o>>) -> Analysis<'o> {
         Analysis {
             static_ty: StaticType::None,
@@ -206,7 +236,21 @@ impl<'o> Analysis<'o> {
 
     fn with_fix_hint<S: Into<String>>(mut self, location: Location, desc: S) -> Self {
         if location != Location::default() {
-            self.fix_hint = Some((location, desc.into()));
+            self.fix_hint = Some(FixHint {
+                location,
+                description: desc.into(),
+                var_name: None,
+            });
+        }
+        self
+    }
+
+    /// Hint that the var of the given name, declared at the given location,
+    /// could be given a type.
+    fn with_var_fix_hint(mut self, location: Location, name: &str) -> Self {
+        self = self.with_fix_hint(location, "add additional type info here");
+        if let Some(hint) = self.fix_hint.as_mut() {
+            hint.var_name = Some(name.to_owned());
         }
         self
     }
@@ -218,8 +262,8 @@ trait WithFixHint {
 
 impl WithFixHint for DMError {
     fn with_fix_hint(mut self, analysis: &Analysis) -> Self {
-        if let Some((loc, desc)) = analysis.fix_hint.clone() {
-            self.add_note(loc, desc);
+        if let Some(ref hint) = analysis.fix_hint {
+            self.add_note(hint.location, hint.description.clone());
         }
         self
     }
@@ -373,6 +417,10 @@ pub struct AnalyzeObjectTree<'o> {
     must_not_override: ProcDirective<'o>,
     // Debug(ProcRef) -> KwargInfo
     used_kwargs: BTreeMap<String, KwargInfo>,
+    // only collected when asked for
+    call_graph: Option<CallGraph<'o>>,
+    // only collected when asked for
+    hints: Option<Vec<Hint<'o>>>,
 }
 
 impl<'o> AnalyzeObjectTree<'o> {
@@ -387,9 +435,27 @@ impl<'o> AnalyzeObjectTree<'o> {
             must_call_parent: ProcDirective::new("SpacemanDMM_should_call_parent", true),
             must_not_override: ProcDirective::new("SpacemanDMM_should_not_override", false),
             used_kwargs: Default::default(),
+            call_graph: None,
+            hints: None,
+        }
+    }
+
+    /// Record the calls found by `check_proc` in a call graph.
+    pub fn enable_call_graph(&mut self) {
+        if self.call_graph.is_none() {
+            self.call_graph = Some(Default::default());
         }
     }
 
+    /// The calls found by `check_proc` so far, if the call graph is enabled.
+    pub fn call_graph(&self) -> Option<&CallGraph<'o>> {
+        self.call_graph.as_ref()
+    }
+
+    pub fn into_call_graph(self) -> Option<CallGraph<'o>> {
+        self.call_graph
+    }
+
     pub fn check_proc(&mut self, proc: ProcRef<'o>, code: &'o [Spanned<Statement>]) {
         AnalyzeProc::new(self, self.context, self.objtree, proc).run(code)
     }
@@ -488,6 +554,7 @@ impl<'o> AnalyzeObjectTree<'o> {
                 error.add_note(bad_override.location, format!("{} is missing \"{}\"",
                     child_procname,
                     bad_override.missing.join("\", \"")));
+                error.add_fix(Fix::AddParameters(bad_override.location, bad_override.missing.clone()));
                 missing.extend(bad_override.missing.iter());
             }
 
@@ -671,6 +738,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     if must {
                         error(self.proc_ref.location, format!("proc never calls parent, required by {}", proc))
                             .with_note(location, "required by this must_call_parent annotation")
+                            .with_fix(Fix::CallParent)
                             .register(self.context);
                     }
                 }
@@ -725,11 +793,15 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 self.visit_block(block);
             },
             Statement::ForList { in_list, block, var_type, name, .. } => {
+                let mut keys = StaticType::None;
                 if let Some(in_list) = in_list {
-                    self.visit_expression(location, in_list, None);
+                    keys = self.visit_expression(location, in_list, None).static_ty.strip_list();
                 }
                 if let Some(var_type) = var_type {
                     self.visit_var(location, var_type, name, None);
+                    if var_type.type_path.is_empty() {
+                        self.hint_local_type(location, name, keys.basic_type());
+                    }
                 }
                 self.visit_block(block);
             },
@@ -800,6 +872,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Statement::Goto(_) => {},
             Statement::Label { name: _, block } => self.visit_block(block),
             Statement::Del(expr) => { self.visit_expression(location, expr, None); },
+            Statement::Invalid => {},
         }
     }
 
@@ -807,7 +880,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         self.visit_var(location, &var.var_type, &var.name, var.value.as_ref())
     }
 
-    fn visit_var(&mut self, location: Location, var_type: &VarType, name: &str, value: Option<&'o Expression>) {
+    fn visit_var(&mut self, location: Location, var_type: &VarType, name: &'o str, value: Option<&'o Expression>) {
         // Calculate type hint
         let static_type = self.env.static_type(location, &var_type.type_path);
 
@@ -816,6 +889,9 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Some(ref expr) => self.visit_expression(location, expr, static_type.basic_type()),
             None => Analysis::null(),
         };
+        if var_type.type_path.is_empty() {
+            self.hint_local_type(location, name, analysis.known_type());
+        }
         analysis.static_ty = static_type;
 
         // Save var to locals
@@ -830,29 +906,33 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 } else {
                     None
                 };
-                let mut ty = self.visit_term(term.location, &term.elem, base_type_hint);
+                let mut ty = self.visit_term(term, base_type_hint);
                 for each in follow.iter() {
-                    ty = self.visit_follow(each.location, ty, &each.elem);
+                    ty = self.visit_follow(ty, each);
                 }
                 for each in unary.iter().rev() {
-                    ty = self.visit_unary(ty, each, location);
+                    ty = self.visit_unary(ty, &each.elem, each.location);
                 }
                 ty
             },
             Expression::BinaryOp { op: BinaryOp::In, lhs, rhs } => {
                 // check for incorrect/ambiguous in statements
+                let span = lhs.span();
+                let location = span.start;
                 match &**lhs {
                     Expression::Base { unary, term, follow } => {
                         if unary.len() > 0 {
-                            error(location, format!("ambiguous `{}` on left side of an `in`", unary[0].name()))
+                            error(location, format!("ambiguous `{}` on left side of an `in`", unary[0].elem.name()))
+                                .with_end(span.end)
                                 .set_severity(Severity::Warning)
-                                .with_note(location, format!("add parentheses to fix: `{}`", unary[0].around("(a in b)")))
-                                .with_note(location, format!("add parentheses to disambiguate: `({}) in b`", unary[0].around("a")))
+                                .with_note(location, format!("add parentheses to fix: `{}`", unary[0].elem.around("(a in b)")))
+                                .with_note(location, format!("add parentheses to disambiguate: `({}) in b`", unary[0].elem.around("a")))
                                 .register(self.context);
                         }
                     },
                     Expression::BinaryOp { op, lhs, rhs } => {
                         error(location, format!("ambiguous `{}` on left side of an `in`", op))
+                            .with_end(span.end)
                             .set_severity(Severity::Warning)
                             .with_note(location, format!("add parentheses to fix: `a {} (b in c)`", op))
                             .with_note(location, format!("add parentheses to disambiguate: `(a {} b) in c`", op))
@@ -860,6 +940,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     },
                     Expression::AssignOp { op, lhs, rhs } => {
                         error(location, format!("ambiguous `{}` on left side of an `in`", op))
+                            .with_end(span.end)
                             .set_severity(Severity::Warning)
                             .with_note(location, format!("add parentheses to fix: `a {} (b in c)`", op))
                             .with_note(location, format!("add parentheses to disambiguate: `(a {} b) in c`", op))
@@ -867,6 +948,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     },
                     Expression::TernaryOp { cond, if_, else_ } => {
                         error(location, format!("ambiguous ternary on left side of an `in`"))
+                            .with_end(span.end)
                             .with_note(location, "add parentheses to fix: `a ? b : (c in d)`")
                             .with_note(location, "add parentheses to disambiguate: `(a ? b : c) in d`")
                             .set_severity(Severity::Warning)
@@ -904,8 +986,9 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         }
     }
 
-    fn visit_term(&mut self, location: Location, term: &'o Term, type_hint: Option<TypeRef<'o>>) -> Analysis<'o> {
-        match term {
+    fn visit_term(&mut self, spanned: &'o Spanned<Term>, type_hint: Option<TypeRef<'o>>) -> Analysis<'o> {
+        let location = spanned.location;
+        match &spanned.elem {
             Term::Null => Analysis::null(),
             Term::Int(number) => Analysis::from_value(self.objtree, Constant::from(*number), type_hint),
             Term::Float(number) => Analysis::from_value(self.objtree, Constant::from(*number), type_hint),
@@ -916,13 +999,15 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Term::Ident(unscoped_name) => {
                 if let Some(var) = self.local_vars.get(unscoped_name) {
                     return var.analysis.clone()
-                        .with_fix_hint(var.location, "add additional type info here")
+                        .with_var_fix_hint(var.location, unscoped_name)
                 }
                 if let Some(decl) = self.ty.get_var_declaration(unscoped_name) {
                     self.static_type(location, &decl.var_type.type_path)
-                        .with_fix_hint(decl.location, "add additional type info here")
+                        .with_var_fix_hint(decl.location, unscoped_name)
                 } else {
                     error(location, format!("undefined var: {:?}", unscoped_name))
+                        .with_end(spanned.end)
+                        .with_fix(Fix::DeclareVar(unscoped_name.to_owned()))
                         .register(self.context);
                     Analysis::empty()
                 }
@@ -941,6 +1026,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     }
                 } else {
                     error(location, format!("failed to resolve path {}", FormatTypePath(&prefab.path)))
+                        .with_end(spanned.end)
                         .register(self.context);
                     Analysis::empty()
                 }
@@ -957,7 +1043,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             Term::Call(unscoped_name, args) => {
                 let src = self.ty;
                 if let Some(proc) = self.ty.get_proc(unscoped_name) {
-                    self.visit_call(location, src, proc, args, false)
+                    self.visit_call(location, src, proc, args, CallKind::Virtual)
                 } else if unscoped_name == "SpacemanDMM_unlint" {
                     // Escape hatch for cases like `src` in macros used in
                     // global procs.
@@ -970,6 +1056,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     Analysis::empty()
                 } else {
                     error(location, format!("undefined proc: {:?} on {}", unscoped_name, self.ty))
+                        .with_end(spanned.end)
                         .register(self.context);
                     Analysis::empty()
                 }
@@ -978,7 +1065,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 let src = self.ty;
                 let proc = self.proc_ref;
                 // Self calls are exact, and won't ever call an override.
-                self.visit_call(location, src, proc, args, true)
+                self.visit_call(location, src, proc, args, CallKind::Static)
             },
             Term::ParentCall(args) => {
                 self.calls_parent = true;
@@ -986,9 +1073,10 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                     // TODO: if args are empty, call w/ same args
                     let src = self.ty;
                     // Parent calls are exact, and won't ever call an override.
-                    self.visit_call(location, src, proc, args, true)
+                    self.visit_call(location, src, proc, args, CallKind::Parent)
                 } else {
                     error(location, format!("proc has no parent: {}", self.proc_ref))
+                        .with_end(spanned.end)
                         .register(self.context);
                     Analysis::empty()
                 }
@@ -1027,7 +1115,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                             args.as_ref().map_or(&[], |v| &v[..]),
                             // New calls are exact: `new /datum()` will always call
                             // `/datum/New()` and never an override.
-                            true);
+                            CallKind::Static);
                     } else if typepath.path != "/list" {
                         error(location, format!("couldn't find {}/proc/New", typepath.path))
                             .register(self.context);
@@ -1103,12 +1191,15 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         }
     }
 
-    fn visit_follow(&mut self, location: Location, lhs: Analysis<'o>, rhs: &'o Follow) -> Analysis<'o> {
-        match rhs {
+    fn visit_follow(&mut self, lhs: Analysis<'o>, spanned: &'o Spanned<Follow>) -> Analysis<'o> {
+        let location = spanned.location;
+        match &spanned.elem {
             Follow::Field(IndexKind::Colon, _) => Analysis::empty(),
             Follow::Field(IndexKind::SafeColon, _) => Analysis::empty(),
+            Follow::Field(IndexKind::Scope, _) => Analysis::empty(),
             Follow::Call(IndexKind::Colon, _, args) |
-            Follow::Call(IndexKind::SafeColon, _, args) => {
+            Follow::Call(IndexKind::SafeColon, _, args) |
+            Follow::Call(IndexKind::Scope, _, args) => {
                 // No analysis yet, but be sure to visit the arguments
                 for arg in args {
                     let mut argument_value = arg;
@@ -1133,8 +1224,8 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 match lhs.static_ty {
                     StaticType::List { keys, .. } => {
                         let mut res = Analysis::from(*keys);
-                        if let Some((loc, _)) = lhs.fix_hint {
-                            res.fix_hint = Some((loc, "add a type annotation after /list here".to_owned()))
+                        if let Some(ref hint) = lhs.fix_hint {
+                            res = res.with_fix_hint(hint.location, "add a type annotation after /list here");
                         }
                         res
                     },
@@ -1145,34 +1236,44 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 if let Some(ty) = lhs.static_ty.basic_type() {
                     if let Some(decl) = ty.get_var_declaration(name) {
                         self.static_type(location, &decl.var_type.type_path)
-                            .with_fix_hint(decl.location, "add additional type info here")
+                            .with_var_fix_hint(decl.location, name)
                     } else {
                         error(location, format!("undefined field: {:?} on {}", name, ty))
+                            .with_end(spanned.end)
                             .register(self.context);
                         Analysis::empty()
                     }
                 } else {
-                    error(location, format!("field access requires static type: {:?}", name))
+                    let mut error = error(location, format!("field access requires static type: {:?}", name))
+                        .with_end(spanned.end)
                         .set_severity(Severity::Warning)
-                        .with_fix_hint(&lhs)
-                        .register(self.context);
+                        .with_fix_hint(&lhs);
+                    if let Some(fix) = self.var_type_fix(&lhs, name, false) {
+                        error.add_fix(fix);
+                    }
+                    error.register(self.context);
                     Analysis::empty()
                 }
             },
             Follow::Call(kind, name, arguments) => {
                 if let Some(ty) = lhs.static_ty.basic_type() {
                     if let Some(proc) = ty.get_proc(name) {
-                        self.visit_call(location, ty, proc, arguments, false)
+                        self.visit_call(location, ty, proc, arguments, CallKind::Virtual)
                     } else {
                         error(location, format!("undefined proc: {:?} on {}", name, ty))
+                            .with_end(spanned.end)
                             .register(self.context);
                         Analysis::empty()
                     }
                 } else {
-                    error(location, format!("proc call requires static type: {:?}", name))
+                    let mut error = error(location, format!("proc call requires static type: {:?}", name))
+                        .with_end(spanned.end)
                         .set_severity(Severity::Warning)
-                        .with_fix_hint(&lhs)
-                        .register(self.context);
+                        .with_fix_hint(&lhs);
+                    if let Some(fix) = self.var_type_fix(&lhs, name, true) {
+                        error.add_fix(fix);
+                    }
+                    error.register(self.context);
                     Analysis::empty()
                 }
             },
@@ -1189,7 +1290,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
             StaticType::Type(typeref) => {
                 // Its been overloaded, assume they really know they want to do this
                 if let Some(proc) = typeref.get_proc(&format!("operator{}",operator)) {
-                    return self.visit_call(location, typeref, proc, &[], true)
+                    return self.visit_call(location, typeref, proc, &[], CallKind::Static)
                 }
                 typeerror = typeref.get().pretty_path();
             },
@@ -1221,7 +1322,11 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         Analysis::empty()
     }
 
-    fn visit_call(&mut self, location: Location, src: TypeRef<'o>, proc: ProcRef, args: &'o [Expression], is_exact: bool) -> Analysis<'o> {
+    fn visit_call(&mut self, location: Location, src: TypeRef<'o>, proc: ProcRef<'o>, args: &'o [Expression], kind: CallKind) -> Analysis<'o> {
+        if let Some(call_graph) = self.env.call_graph.as_mut() {
+            call_graph.add_call(self.proc_ref, src, proc, kind, location);
+        }
+
         // identify and register kwargs used
         let mut any_kwargs_yet = false;
 
@@ -1253,7 +1358,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                                 }
                             });
                             error.register(self.context);
-                        } else if !is_exact {
+                        } else if !kind.is_exact() {
                             // If it does, mark it as "used".
                             // Format with src/proc/foo here, rather than the
                             // type the proc actually appears on, so that
@@ -1289,6 +1394,7 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
                 param_name_map.insert(kw.as_str(), analysis);
             } else {
                 param_idx_map.insert(param_idx, analysis);
+                self.hint_argument(proc, param_idx, argument_value);
                 param_idx += 1;
             }
         }
@@ -1316,6 +1422,51 @@ impl<'o, 's> AnalyzeProc<'o, 's> {
         }
     }
 
+    /// Offer to give the untyped var an analysis came from the type which
+    /// declares the var or proc named `member`, if only one type does.
+    fn var_type_fix(&self, analysis: &Analysis<'o>, member: &str, is_proc: bool) -> Option<Fix> {
+        let hint = analysis.fix_hint.as_ref()?;
+        let var_name = hint.var_name.as_ref()?;
+        match analysis.static_ty {
+            StaticType::None => {}
+            _ => return None,
+        }
+        let mut declaring = Vec::new();
+        self.objtree.root().recurse(&mut |ty| {
+            let declared = if is_proc {
+                ty.procs.get(member).map_or(false, |proc| proc.declaration.is_some())
+            } else {
+                ty.vars.get(member).map_or(false, |var| var.declaration.is_some())
+            };
+            if declared && !ty.is_root() {
+                declaring.push(ty);
+            }
+        });
+        if declaring.len() != 1 {
+            return None;
+        }
+        Some(Fix::SetVarType(hint.location, var_name.clone(), declaring[0].path.clone()))
+    }
+
+    fn hint_argument(&mut self, proc: ProcRef<'o>, idx: usize, argument: &'o Expression) {
+        if let Some(hints) = self.env.hints.as_mut() {
+            if let Some(parameter) = hints::parameter_name(proc, idx, argument) {
+                hints.push(Hint::Argument {
+                    location: argument.span().start,
+                    parameter,
+                });
+            }
+        }
+    }
+
+    fn hint_local_type(&mut self, location: Location, name: &'o str, ty: Option<TypeRef<'o>>) {
+        if let Some(hints) = self.env.hints.as_mut() {
+            if let Some(ty) = ty {
+                hints.push(Hint::LocalType { location, name, ty });
+            }
+        }
+    }
+
     fn visit_arguments(&mut self, location: Location, args: &'o [Expression]) {
         for arg in args {
             let mut argument_value = arg;
@@ -6,6 +6,8 @@ extern crate dreamchecker;
 
 use dm::Context;
 use dm::objtree::Code;
+use dm::preprocessor::DefineOverride;
+use dm::multiconfig::{Configuration, diagnose_configurations};
 
 use dreamchecker::*;
 
@@ -15,6 +17,10 @@ use dreamchecker::*;
 fn main() {
     // command-line args
     let mut environment = None;
+    let mut byond_version = None;
+    let mut call_graph_output = None;
+    let mut define_overrides = Vec::new();
+    let mut configurations = Vec::new();
 
     let mut args = std::env::args();
     let _ = args.next();  // skip executable name
@@ -31,6 +37,28 @@ fn main() {
             return;
         } else if arg == "-e" {
             environment = Some(args.next().expect("must specify a value for -e"));
+        } else if arg == "--byond-version" {
+            let value = args.next().expect("must specify a value for --byond-version");
+            match value.parse::<dm::ByondVersion>() {
+                Ok(version) => byond_version = Some(version),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+        } else if arg == "--call-graph" {
+            call_graph_output = Some(args.next().expect("must specify a value for --call-graph"));
+        } else if let Some(result) = DefineOverride::parse_arg(&arg, &mut args) {
+            match result {
+                Ok(define) => define_overrides.push(define),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+        } else if arg == "--config" {
+            let spec = args.next().expect("must specify a value for --config");
+            configurations.push(Configuration::parse(&spec));
         } else {
             eprintln!("unknown argument: {}", arg);
             return;
@@ -47,10 +75,39 @@ fn main() {
 
     let mut context = Context::default();
     context.set_print_severity(Some(PRINT_SEVERITY));
+    if let Some(version) = byond_version {
+        context.set_byond_version(version);
+    }
+
+    if !configurations.is_empty() {
+        // Compare against the configuration with no extra defines.
+        configurations.insert(0, Configuration::parse(""));
+        println!("============================================================");
+        println!("Comparing {} configurations of {}...\n", configurations.len(), dme.display());
+        let errors = diagnose_configurations(&context, &define_overrides, &configurations, |run_context, defines| {
+            let mut pp = dm::preprocessor::Preprocessor::new(run_context, dme.clone())
+                .expect("i/o error opening .dme");
+            pp.set_define_overrides(defines);
+            pp.enable_unnecessary_lints();
+            pp.enable_macro_condition_lints();
+            let indents = dm::indents::IndentProcessor::new(run_context, pp);
+            let mut parser = dm::parser::Parser::new(run_context, indents);
+            parser.enable_procs();
+            let tree = parser.parse_object_tree();
+            check_var_defs(&tree, run_context);
+            run(run_context, &tree);
+        });
+        println!("============================================================");
+        println!("Found {} diagnostics which differ between configurations", errors);
+        std::process::exit(if errors > 0 { 1 } else { 0 });
+    }
+
     println!("============================================================");
     println!("Parsing {}...\n", dme.display());
-    let pp = dm::preprocessor::Preprocessor::new(&context, dme)
+    let mut pp = dm::preprocessor::Preprocessor::new(&context, dme)
         .expect("i/o error opening .dme");
+    pp.set_define_overrides(&define_overrides);
+    pp.enable_unnecessary_lints();
     let indents = dm::indents::IndentProcessor::new(&context, pp);
     let mut parser = dm::parser::Parser::new(&context, indents);
     parser.enable_procs();
@@ -63,6 +120,9 @@ fn main() {
     let mut builtin = 0;
 
     let mut analyzer = AnalyzeObjectTree::new(&context, &tree);
+    if call_graph_output.is_some() {
+        analyzer.enable_call_graph();
+    }
 
     println!("============================================================");
     println!("Gathering proc settings...\n");
@@ -101,6 +161,39 @@ fn main() {
     });
     analyzer.finish_check_kwargs();
 
+    if let (Some(output), Some(call_graph)) = (call_graph_output, analyzer.call_graph()) {
+        println!("============================================================");
+        println!("Writing call graph to {}...\n", output);
+        let result = std::fs::File::create(&output)
+            .map(std::io::BufWriter::new)
+            .and_then(|file| if output.ends_with(".json") {
+                call_graph.write_json(&context, file)
+            } else {
+                call_graph.write_dot(file)
+            });
+        if let Err(e) = result {
+            eprintln!("i/o error writing call graph:\n{}", e);
+        }
+    }
+
+    // Unused macros and constant conditions are only hints, so they are
+    // listed separately and do not affect the exit status.
+    {
+        let errors = context.errors();
+        let unnecessary: Vec<_> = errors.iter()
+            .filter(|each| each.is_unnecessary() && each.severity() > PRINT_SEVERITY)
+            .collect();
+        if !unnecessary.is_empty() {
+            println!("============================================================");
+            println!("Unnecessary preprocessor code...\n");
+            for error in unnecessary.iter() {
+                context.pretty_print_error_nocolor(&mut std::io::stderr(), error)
+                    .expect("error writing to stderr");
+            }
+            println!("Found {} unused macros or constant conditions\n", unnecessary.len());
+        }
+    }
+
     println!("============================================================");
     let errors = context.errors().iter().filter(|each| each.severity() <= PRINT_SEVERITY).count();
     println!("Found {} diagnostics", errors);
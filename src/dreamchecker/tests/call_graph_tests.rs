@@ -0,0 +1,137 @@
+extern crate dreammaker as dm;
+extern crate dreamchecker;
+extern crate serde_json;
+
+use dm::objtree::{ObjectTree, ProcRef};
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dreamchecker::AnalyzeObjectTree;
+use dreamchecker::call_graph::{Call, CallGraph, CallKind};
+
+const CODE: &str = r#"
+/datum/thing
+    proc/act()
+    proc/helper()
+/datum/thing/sub
+    act()
+        ..()
+/datum/user
+    proc/use(datum/thing/T)
+        T.act()
+    proc/again()
+        .()
+"#;
+
+fn parse(context: &dm::Context) -> ObjectTree {
+    let pp = Preprocessor::from_buffer(context, "call_graph_tests.rs".into(), CODE);
+    let mut parser = Parser::new(context, IndentProcessor::new(context, pp));
+    parser.enable_procs();
+    parser.parse_object_tree()
+}
+
+fn proc<'o>(tree: &'o ObjectTree, ty: &str, name: &str) -> ProcRef<'o> {
+    tree.expect(ty).get_proc(name).expect("missing proc")
+}
+
+fn edges(calls: Vec<Call>) -> Vec<(String, String, CallKind, bool)> {
+    let mut edges: Vec<_> = calls.into_iter()
+        .map(|call| (call.caller.to_string(), call.callee.to_string(), call.kind, call.is_override))
+        .collect();
+    edges.sort();
+    edges
+}
+
+fn edge(caller: &str, callee: &str, kind: CallKind, is_override: bool) -> (String, String, CallKind, bool) {
+    (caller.to_owned(), callee.to_owned(), kind, is_override)
+}
+
+#[test]
+fn resolves_calls() {
+    let context = dm::Context::default();
+    let tree = parse(&context);
+    let graph = CallGraph::build(&context, &tree);
+
+    // A static self call.
+    assert_eq!(edges(graph.callees(proc(&tree, "/datum/user", "again"))), vec![
+        edge("/datum/user/proc/again", "/datum/user/proc/again", CallKind::Static, false),
+    ]);
+
+    // A parent call.
+    assert_eq!(edges(graph.callees(proc(&tree, "/datum/thing/sub", "act"))), vec![
+        edge("/datum/thing/sub/proc/act", "/datum/thing/proc/act", CallKind::Parent, false),
+    ]);
+
+    // A virtual call reaches the proc it names and each override.
+    assert_eq!(edges(graph.callees(proc(&tree, "/datum/user", "use"))), vec![
+        edge("/datum/user/proc/use", "/datum/thing/proc/act", CallKind::Virtual, false),
+        edge("/datum/user/proc/use", "/datum/thing/sub/proc/act", CallKind::Virtual, true),
+    ]);
+    assert_eq!(edges(graph.callers(proc(&tree, "/datum/thing/sub", "act"))), vec![
+        edge("/datum/user/proc/use", "/datum/thing/sub/proc/act", CallKind::Virtual, true),
+    ]);
+    assert_eq!(edges(graph.callers(proc(&tree, "/datum/thing", "act"))), vec![
+        edge("/datum/thing/sub/proc/act", "/datum/thing/proc/act", CallKind::Parent, false),
+        edge("/datum/user/proc/use", "/datum/thing/proc/act", CallKind::Virtual, false),
+    ]);
+    assert!(graph.callers(proc(&tree, "/datum/thing", "helper")).is_empty());
+
+    // Only the call sites themselves are stored.
+    assert_eq!(graph.calls().count(), 3);
+}
+
+#[test]
+fn only_built_when_enabled() {
+    let context = dm::Context::default();
+    let tree = parse(&context);
+    let mut analyzer = AnalyzeObjectTree::new(&context, &tree);
+    let code = match proc(&tree, "/datum/user", "use").get().code {
+        dm::objtree::Code::Present(ref code) => code,
+        _ => panic!("missing code"),
+    };
+    analyzer.check_proc(proc(&tree, "/datum/user", "use"), code);
+    assert!(analyzer.call_graph().is_none());
+}
+
+#[test]
+fn export_dot() {
+    let context = dm::Context::default();
+    let tree = parse(&context);
+    let graph = CallGraph::build(&context, &tree);
+
+    let mut dot = Vec::new();
+    graph.write_dot(&mut dot).unwrap();
+    assert_eq!(String::from_utf8(dot).unwrap(), r#"digraph calls {
+    "/datum/thing/sub/proc/act" -> "/datum/thing/proc/act" [style=bold];
+    "/datum/user/proc/again" -> "/datum/user/proc/again";
+    "/datum/user/proc/use" -> "/datum/thing/proc/act";
+    "/datum/user/proc/use" -> "/datum/thing/sub/proc/act" [style=dashed];
+}
+"#);
+}
+
+#[test]
+fn export_json() {
+    let context = dm::Context::default();
+    let tree = parse(&context);
+    let graph = CallGraph::build(&context, &tree);
+
+    let mut json = Vec::new();
+    graph.write_json(&context, &mut json).unwrap();
+    let edges: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    let edges = edges.as_array().unwrap();
+    assert_eq!(edges.len(), 4);
+
+    let virtual_override = edges.iter()
+        .find(|edge| edge["callee"] == "/datum/thing/sub/proc/act" && edge["caller"] == "/datum/user/proc/use")
+        .unwrap();
+    assert_eq!(virtual_override["kind"], "virtual");
+    assert_eq!(virtual_override["is_override"], true);
+    let locations = virtual_override["locations"].as_array().unwrap();
+    assert_eq!(locations.len(), 1);
+    assert_eq!(locations[0]["line"], 10);
+
+    let parent = edges.iter().find(|edge| edge["kind"] == "parent").unwrap();
+    assert_eq!(parent["caller"], "/datum/thing/sub/proc/act");
+    assert_eq!(parent["is_override"], false);
+}
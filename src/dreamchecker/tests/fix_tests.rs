@@ -0,0 +1,54 @@
+extern crate dreammaker as dm;
+extern crate dreamchecker;
+
+use dm::Fix;
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+const CODE: &str = r#"
+/datum/base/proc/act(a)
+    set SpacemanDMM_should_call_parent = 1
+    return a
+/datum/base/child/act(a)
+    return 2
+/datum/base/proc/hit(x, power)
+    return x
+/datum/base/child/hit(x)
+    return
+/proc/caller()
+    var/datum/base/B = new
+    B.hit(power = 2)
+    counter = 5
+    var/found = locate(/datum/base)
+    found.act(1)
+"#;
+
+#[test]
+fn diagnostics_carry_fixes() {
+    let context = dm::Context::default();
+    let pp = Preprocessor::from_buffer(&context, "fix_tests.rs".into(), CODE);
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, pp));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    dreamchecker::run(&context, &tree);
+
+    let errors = context.errors();
+    let mut fixes: Vec<_> = errors.iter()
+        .flat_map(|error| error.fixes().iter().map(move |fix| (error.location().line, fix.clone())))
+        .collect();
+    fixes.sort_by_key(|&(line, _)| line);
+    let override_location = tree.expect("/datum/base/child").get_proc("hit").unwrap().location;
+    let found_location = errors.iter()
+        .find(|error| error.description() == "proc call requires static type: \"act\"")
+        .and_then(|error| error.notes().first())
+        .map(|note| note.location())
+        .unwrap();
+    assert_eq!(found_location.line, 15);
+    assert_eq!(fixes, vec![
+        (5, Fix::CallParent),
+        (7, Fix::AddParameters(override_location, vec!["power".to_owned()])),
+        (14, Fix::DeclareVar("counter".to_owned())),
+        (16, Fix::SetVarType(found_location, "found".to_owned(), "/datum/base".to_owned())),
+    ]);
+}
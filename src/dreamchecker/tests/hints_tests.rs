@@ -0,0 +1,43 @@
+extern crate dreammaker as dm;
+extern crate dreamchecker;
+
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dreamchecker::hints::{self, Hint};
+
+const CODE: &str = r#"
+/datum/thing/proc/take(amount)
+    return amount
+/datum/thing/proc/first()
+    take(1)
+/datum/thing/proc/second()
+    var/T = new /datum/thing
+    return T
+"#;
+
+#[test]
+fn hints_for_procs_in_range() {
+    let context = dm::Context::default();
+    let pp = Preprocessor::from_buffer(&context, "hints_tests.rs".into(), CODE);
+    let file = context.get_file("hints_tests.rs".as_ref()).unwrap();
+    let mut parser = Parser::new(&context, IndentProcessor::new(&context, pp));
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+
+    let names = |procs: &[dm::objtree::ProcRef]| procs.iter().map(|proc| proc.name().to_owned()).collect::<Vec<_>>();
+    assert_eq!(names(&hints::procs_in_lines(&tree, file, 5, 5)), vec!["first"]);
+    assert_eq!(names(&hints::procs_in_lines(&tree, file, 3, 7)), vec!["take", "first", "second"]);
+
+    let procs = hints::procs_in_lines(&tree, file, 5, 8);
+    let found = hints::collect(&context, &tree, &procs);
+    assert_eq!(found.len(), 2);
+    match found[0][..] {
+        [Hint::Argument { location, parameter }] => assert_eq!((location.line, parameter), (5, "amount")),
+        ref other => panic!("unexpected hints: {:?}", other),
+    }
+    match found[1][..] {
+        [Hint::LocalType { name, ty, .. }] => assert_eq!((name, &ty.path[..]), ("T", "/datum/thing")),
+        ref other => panic!("unexpected hints: {:?}", other),
+    }
+}
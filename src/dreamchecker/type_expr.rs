@@ -113,7 +113,7 @@ impl<'o> TypeExprCompiler<'o> {
         match expr {
             Expression::Base { unary, term, follow } => {
                 if let Some(op) = unary.first() {
-                    return Err(DMError::new(location, format!("type expr: bad unary {}", op.name())));
+                    return Err(DMError::new(op.location, format!("type expr: bad unary {}", op.elem.name())));
                 }
 
                 let mut ty = self.visit_term(term.location, &term.elem)?;
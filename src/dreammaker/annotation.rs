@@ -17,6 +17,7 @@ pub enum Annotation {
     ProcHeader(Vec<String>, usize),
     ProcBody(Vec<String>, usize),
     LocalVarScope(VarType, String),
+    Conditional(bool),  // an #if branch, and whether it was taken
 
     // local information about a specific token
     UnscopedCall(String),
@@ -3,6 +3,7 @@
 //! Most AST types can be pretty-printed using the `Display` trait.
 use std::fmt;
 use std::iter::FromIterator;
+use std::ops::Range;
 
 use linked_hash_map::LinkedHashMap;
 
@@ -10,8 +11,10 @@ use crate::error::Location;
 
 #[derive(Copy, Clone, Eq, Debug)]
 pub struct Spanned<T> {
-    // TODO: add a Span type and use it here
+    /// The start of the element.
     pub location: Location,
+    /// The location just past the end of the element.
+    pub end: Location,
     pub elem: T,
 }
 
@@ -24,7 +27,16 @@ impl<T: PartialEq> PartialEq for Spanned<T> {
 
 impl<T> Spanned<T> {
     pub fn new(location: Location, elem: T) -> Spanned<T> {
-        Spanned { location, elem }
+        Spanned { location, end: location, elem }
+    }
+
+    pub fn with_end(location: Location, end: Location, elem: T) -> Spanned<T> {
+        Spanned { location, end, elem }
+    }
+
+    /// The source range covered by this element.
+    pub fn span(&self) -> Range<Location> {
+        self.location..self.end
     }
 }
 
@@ -331,7 +343,7 @@ pub enum Expression {
     /// then its follows, then its unary operators in reverse order.
     Base {
         /// The unary operations applied to this value, in reverse order.
-        unary: Vec<UnaryOp>,
+        unary: Vec<Spanned<UnaryOp>>,
         /// The term of the expression.
         term: Spanned<Term>,
         /// The follow operations applied to this value.
@@ -367,6 +379,33 @@ pub enum Expression {
 }
 
 impl Expression {
+    /// The source range covered by this expression.
+    ///
+    /// Expressions built other than by the parser may have empty spans.
+    pub fn span(&self) -> Range<Location> {
+        match self {
+            Expression::Base { unary, term, follow } => {
+                let mut span = term.span();
+                for each in unary.iter() {
+                    span.start = std::cmp::min(span.start, each.location);
+                    span.end = std::cmp::max(span.end, each.end);
+                }
+                if let Some(last) = follow.last() {
+                    span.end = std::cmp::max(span.end, last.end);
+                }
+                span
+            },
+            Expression::BinaryOp { lhs, rhs, .. } |
+            Expression::AssignOp { lhs, rhs, .. } => lhs.span().start..rhs.span().end,
+            Expression::TernaryOp { cond, else_, .. } => cond.span().start..else_.span().end,
+        }
+    }
+
+    /// The start of this expression.
+    pub fn location(&self) -> Location {
+        self.span().start
+    }
+
     /// If this expression consists of a single term, return it.
     pub fn as_term(&self) -> Option<&Term> {
         match *self {
@@ -491,6 +530,8 @@ pub enum IndexKind {
     SafeDot,
     /// `a?:b`
     SafeColon,
+    /// `a::b`
+    Scope,
 }
 
 impl IndexKind {
@@ -500,6 +541,7 @@ impl IndexKind {
             IndexKind::Colon => ":",
             IndexKind::SafeDot => "?.",
             IndexKind::SafeColon => "?:",
+            IndexKind::Scope => "::",
         }
     }
 }
@@ -845,6 +887,9 @@ pub enum Statement {
         block: Block,
     },
     Del(Expression),
+    /// A statement which failed to parse. The error has already been
+    /// reported, and parsing resumed at the next statement.
+    Invalid,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -897,3 +942,551 @@ pub const KNOWN_SETTING_NAMES: &[&str] = &[
     // undocumented
     "waitfor",
 ];
+
+// ----------------------------------------------------------------------------
+// Visitors
+
+/// A read-only visitor over the AST.
+///
+/// Each method defaults to calling the matching `walk_*` function, which
+/// visits the node's children. Override only the methods for the nodes of
+/// interest, and call the `walk_*` function to continue into children.
+pub trait Visitor<'ast> {
+    fn visit_block(&mut self, block: &'ast [Spanned<Statement>]) {
+        walk_block(self, block)
+    }
+
+    fn visit_statement(&mut self, location: Location, statement: &'ast Statement) {
+        walk_statement(self, location, statement)
+    }
+
+    fn visit_var_statement(&mut self, location: Location, var: &'ast VarStatement) {
+        walk_var_statement(self, location, var)
+    }
+
+    fn visit_case(&mut self, location: Location, case: &'ast Case) {
+        walk_case(self, location, case)
+    }
+
+    fn visit_expression(&mut self, location: Location, expression: &'ast Expression) {
+        walk_expression(self, location, expression)
+    }
+
+    fn visit_term(&mut self, location: Location, term: &'ast Term) {
+        walk_term(self, location, term)
+    }
+
+    fn visit_follow(&mut self, location: Location, follow: &'ast Follow) {
+        walk_follow(self, location, follow)
+    }
+
+    fn visit_index_or_field(&mut self, location: Location, index_or_field: &'ast IndexOrField) {
+        walk_index_or_field(self, location, index_or_field)
+    }
+
+    fn visit_new_type(&mut self, location: Location, new_type: &'ast NewType) {
+        walk_new_type(self, location, new_type)
+    }
+
+    fn visit_prefab(&mut self, location: Location, prefab: &'ast Prefab) {
+        walk_prefab(self, location, prefab)
+    }
+
+    fn visit_arguments(&mut self, location: Location, arguments: &'ast [Expression]) {
+        walk_arguments(self, location, arguments)
+    }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, block: &'ast [Spanned<Statement>]) {
+    for statement in block.iter() {
+        v.visit_statement(statement.location, &statement.elem);
+    }
+}
+
+pub fn walk_statement<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, statement: &'ast Statement) {
+    match statement {
+        Statement::Expr(expr) |
+        Statement::Throw(expr) |
+        Statement::Del(expr) => v.visit_expression(location, expr),
+        Statement::Return(expr) => {
+            if let Some(expr) = expr {
+                v.visit_expression(location, expr);
+            }
+        },
+        Statement::While { condition, block } => {
+            v.visit_expression(location, condition);
+            v.visit_block(block);
+        },
+        Statement::DoWhile { block, condition } => {
+            v.visit_block(block);
+            v.visit_expression(location, condition);
+        },
+        Statement::If { arms, else_arm } => {
+            for (condition, block) in arms.iter() {
+                v.visit_expression(location, condition);
+                v.visit_block(block);
+            }
+            if let Some(else_arm) = else_arm {
+                v.visit_block(else_arm);
+            }
+        },
+        Statement::ForLoop { init, test, inc, block } => {
+            if let Some(init) = init {
+                v.visit_statement(location, init);
+            }
+            if let Some(test) = test {
+                v.visit_expression(location, test);
+            }
+            if let Some(inc) = inc {
+                v.visit_statement(location, inc);
+            }
+            v.visit_block(block);
+        },
+        Statement::ForList { in_list, block, .. } => {
+            if let Some(in_list) = in_list {
+                v.visit_expression(location, in_list);
+            }
+            v.visit_block(block);
+        },
+        Statement::ForRange { start, end, step, block, .. } => {
+            v.visit_expression(location, start);
+            v.visit_expression(location, end);
+            if let Some(step) = step {
+                v.visit_expression(location, step);
+            }
+            v.visit_block(block);
+        },
+        Statement::Var(var) => v.visit_var_statement(location, var),
+        Statement::Vars(vars) => {
+            for var in vars.iter() {
+                v.visit_var_statement(location, var);
+            }
+        },
+        Statement::Setting { value, .. } => v.visit_expression(location, value),
+        Statement::Spawn { delay, block } => {
+            if let Some(delay) = delay {
+                v.visit_expression(location, delay);
+            }
+            v.visit_block(block);
+        },
+        Statement::Switch { input, cases, default } => {
+            v.visit_expression(location, input);
+            for (case, block) in cases.iter() {
+                for each in case.iter() {
+                    v.visit_case(location, each);
+                }
+                v.visit_block(block);
+            }
+            if let Some(default) = default {
+                v.visit_block(default);
+            }
+        },
+        Statement::TryCatch { try_block, catch_block, .. } => {
+            v.visit_block(try_block);
+            v.visit_block(catch_block);
+        },
+        Statement::Label { block, .. } => v.visit_block(block),
+        Statement::Continue(_) |
+        Statement::Break(_) |
+        Statement::Goto(_) |
+        Statement::Invalid => {},
+    }
+}
+
+pub fn walk_var_statement<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, var: &'ast VarStatement) {
+    if let Some(value) = &var.value {
+        v.visit_expression(location, value);
+    }
+}
+
+pub fn walk_case<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, case: &'ast Case) {
+    match case {
+        Case::Exact(expr) => v.visit_expression(location, expr),
+        Case::Range(start, end) => {
+            v.visit_expression(location, start);
+            v.visit_expression(location, end);
+        },
+    }
+}
+
+pub fn walk_expression<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, expression: &'ast Expression) {
+    match expression {
+        Expression::Base { term, follow, .. } => {
+            v.visit_term(term.location, &term.elem);
+            for each in follow.iter() {
+                v.visit_follow(each.location, &each.elem);
+            }
+        },
+        Expression::BinaryOp { lhs, rhs, .. } |
+        Expression::AssignOp { lhs, rhs, .. } => {
+            v.visit_expression(location, lhs);
+            v.visit_expression(location, rhs);
+        },
+        Expression::TernaryOp { cond, if_, else_ } => {
+            v.visit_expression(location, cond);
+            v.visit_expression(location, if_);
+            v.visit_expression(location, else_);
+        },
+    }
+}
+
+pub fn walk_term<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, term: &'ast Term) {
+    match term {
+        Term::Null |
+        Term::Int(_) |
+        Term::Float(_) |
+        Term::Ident(_) |
+        Term::String(_) |
+        Term::Resource(_) |
+        Term::As(_) => {},
+        Term::Expr(expr) => v.visit_expression(location, expr),
+        Term::Prefab(prefab) => v.visit_prefab(location, prefab),
+        Term::InterpString(_, parts) => {
+            for (expr, _) in parts.iter() {
+                if let Some(expr) = expr {
+                    v.visit_expression(location, expr);
+                }
+            }
+        },
+        Term::Call(_, args) |
+        Term::SelfCall(args) |
+        Term::ParentCall(args) |
+        Term::List(args) => v.visit_arguments(location, args),
+        Term::New { type_, args } => {
+            v.visit_new_type(location, type_);
+            if let Some(args) = args {
+                v.visit_arguments(location, args);
+            }
+        },
+        Term::Input { args, in_list, .. } |
+        Term::Locate { args, in_list } => {
+            v.visit_arguments(location, args);
+            if let Some(in_list) = in_list {
+                v.visit_expression(location, in_list);
+            }
+        },
+        Term::Pick(choices) => {
+            for (weight, choice) in choices.iter() {
+                if let Some(weight) = weight {
+                    v.visit_expression(location, weight);
+                }
+                v.visit_expression(location, choice);
+            }
+        },
+        Term::DynamicCall(lhs_args, rhs_args) => {
+            v.visit_arguments(location, lhs_args);
+            v.visit_arguments(location, rhs_args);
+        },
+    }
+}
+
+pub fn walk_follow<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, follow: &'ast Follow) {
+    match follow {
+        Follow::Index(expr) => v.visit_expression(location, expr),
+        Follow::Field(..) => {},
+        Follow::Call(_, _, args) => v.visit_arguments(location, args),
+    }
+}
+
+pub fn walk_index_or_field<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, index_or_field: &'ast IndexOrField) {
+    match index_or_field {
+        IndexOrField::Index(expr) => v.visit_expression(location, expr),
+        IndexOrField::Field(..) => {},
+    }
+}
+
+pub fn walk_new_type<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, new_type: &'ast NewType) {
+    match new_type {
+        NewType::Implicit => {},
+        NewType::Prefab(prefab) => v.visit_prefab(location, prefab),
+        NewType::MiniExpr { fields, .. } => {
+            for field in fields.iter() {
+                v.visit_index_or_field(location, field);
+            }
+        },
+    }
+}
+
+pub fn walk_prefab<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, prefab: &'ast Prefab) {
+    for (_, value) in prefab.vars.iter() {
+        v.visit_expression(location, value);
+    }
+}
+
+pub fn walk_arguments<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, location: Location, arguments: &'ast [Expression]) {
+    for arg in arguments.iter() {
+        v.visit_expression(location, arg);
+    }
+}
+
+/// A visitor which may modify the AST in place.
+///
+/// Like `Visitor`, but each method defaults to calling the matching
+/// `walk_*_mut` function.
+pub trait VisitorMut {
+    fn visit_block(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_statement(&mut self, location: Location, statement: &mut Statement) {
+        walk_statement_mut(self, location, statement)
+    }
+
+    fn visit_var_statement(&mut self, location: Location, var: &mut VarStatement) {
+        walk_var_statement_mut(self, location, var)
+    }
+
+    fn visit_case(&mut self, location: Location, case: &mut Case) {
+        walk_case_mut(self, location, case)
+    }
+
+    fn visit_expression(&mut self, location: Location, expression: &mut Expression) {
+        walk_expression_mut(self, location, expression)
+    }
+
+    fn visit_term(&mut self, location: Location, term: &mut Term) {
+        walk_term_mut(self, location, term)
+    }
+
+    fn visit_follow(&mut self, location: Location, follow: &mut Follow) {
+        walk_follow_mut(self, location, follow)
+    }
+
+    fn visit_index_or_field(&mut self, location: Location, index_or_field: &mut IndexOrField) {
+        walk_index_or_field_mut(self, location, index_or_field)
+    }
+
+    fn visit_new_type(&mut self, location: Location, new_type: &mut NewType) {
+        walk_new_type_mut(self, location, new_type)
+    }
+
+    fn visit_prefab(&mut self, location: Location, prefab: &mut Prefab) {
+        walk_prefab_mut(self, location, prefab)
+    }
+
+    fn visit_arguments(&mut self, location: Location, arguments: &mut [Expression]) {
+        walk_arguments_mut(self, location, arguments)
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(v: &mut V, block: &mut Block) {
+    for statement in block.iter_mut() {
+        v.visit_statement(statement.location, &mut statement.elem);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, statement: &mut Statement) {
+    match statement {
+        Statement::Expr(expr) |
+        Statement::Throw(expr) |
+        Statement::Del(expr) => v.visit_expression(location, expr),
+        Statement::Return(expr) => {
+            if let Some(expr) = expr {
+                v.visit_expression(location, expr);
+            }
+        },
+        Statement::While { condition, block } => {
+            v.visit_expression(location, condition);
+            v.visit_block(block);
+        },
+        Statement::DoWhile { block, condition } => {
+            v.visit_block(block);
+            v.visit_expression(location, condition);
+        },
+        Statement::If { arms, else_arm } => {
+            for (condition, block) in arms.iter_mut() {
+                v.visit_expression(location, condition);
+                v.visit_block(block);
+            }
+            if let Some(else_arm) = else_arm {
+                v.visit_block(else_arm);
+            }
+        },
+        Statement::ForLoop { init, test, inc, block } => {
+            if let Some(init) = init {
+                v.visit_statement(location, init);
+            }
+            if let Some(test) = test {
+                v.visit_expression(location, test);
+            }
+            if let Some(inc) = inc {
+                v.visit_statement(location, inc);
+            }
+            v.visit_block(block);
+        },
+        Statement::ForList { in_list, block, .. } => {
+            if let Some(in_list) = in_list {
+                v.visit_expression(location, in_list);
+            }
+            v.visit_block(block);
+        },
+        Statement::ForRange { start, end, step, block, .. } => {
+            v.visit_expression(location, start);
+            v.visit_expression(location, end);
+            if let Some(step) = step {
+                v.visit_expression(location, step);
+            }
+            v.visit_block(block);
+        },
+        Statement::Var(var) => v.visit_var_statement(location, var),
+        Statement::Vars(vars) => {
+            for var in vars.iter_mut() {
+                v.visit_var_statement(location, var);
+            }
+        },
+        Statement::Setting { value, .. } => v.visit_expression(location, value),
+        Statement::Spawn { delay, block } => {
+            if let Some(delay) = delay {
+                v.visit_expression(location, delay);
+            }
+            v.visit_block(block);
+        },
+        Statement::Switch { input, cases, default } => {
+            v.visit_expression(location, input);
+            for (case, block) in cases.iter_mut() {
+                for each in case.iter_mut() {
+                    v.visit_case(location, each);
+                }
+                v.visit_block(block);
+            }
+            if let Some(default) = default {
+                v.visit_block(default);
+            }
+        },
+        Statement::TryCatch { try_block, catch_block, .. } => {
+            v.visit_block(try_block);
+            v.visit_block(catch_block);
+        },
+        Statement::Label { block, .. } => v.visit_block(block),
+        Statement::Continue(_) |
+        Statement::Break(_) |
+        Statement::Goto(_) |
+        Statement::Invalid => {},
+    }
+}
+
+pub fn walk_var_statement_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, var: &mut VarStatement) {
+    if let Some(value) = &mut var.value {
+        v.visit_expression(location, value);
+    }
+}
+
+pub fn walk_case_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, case: &mut Case) {
+    match case {
+        Case::Exact(expr) => v.visit_expression(location, expr),
+        Case::Range(start, end) => {
+            v.visit_expression(location, start);
+            v.visit_expression(location, end);
+        },
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, expression: &mut Expression) {
+    match expression {
+        Expression::Base { term, follow, .. } => {
+            v.visit_term(term.location, &mut term.elem);
+            for each in follow.iter_mut() {
+                v.visit_follow(each.location, &mut each.elem);
+            }
+        },
+        Expression::BinaryOp { lhs, rhs, .. } |
+        Expression::AssignOp { lhs, rhs, .. } => {
+            v.visit_expression(location, lhs);
+            v.visit_expression(location, rhs);
+        },
+        Expression::TernaryOp { cond, if_, else_ } => {
+            v.visit_expression(location, cond);
+            v.visit_expression(location, if_);
+            v.visit_expression(location, else_);
+        },
+    }
+}
+
+pub fn walk_term_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, term: &mut Term) {
+    match term {
+        Term::Null |
+        Term::Int(_) |
+        Term::Float(_) |
+        Term::Ident(_) |
+        Term::String(_) |
+        Term::Resource(_) |
+        Term::As(_) => {},
+        Term::Expr(expr) => v.visit_expression(location, expr),
+        Term::Prefab(prefab) => v.visit_prefab(location, prefab),
+        Term::InterpString(_, parts) => {
+            for (expr, _) in parts.iter_mut() {
+                if let Some(expr) = expr {
+                    v.visit_expression(location, expr);
+                }
+            }
+        },
+        Term::Call(_, args) |
+        Term::SelfCall(args) |
+        Term::ParentCall(args) |
+        Term::List(args) => v.visit_arguments(location, args),
+        Term::New { type_, args } => {
+            v.visit_new_type(location, type_);
+            if let Some(args) = args {
+                v.visit_arguments(location, args);
+            }
+        },
+        Term::Input { args, in_list, .. } |
+        Term::Locate { args, in_list } => {
+            v.visit_arguments(location, args);
+            if let Some(in_list) = in_list {
+                v.visit_expression(location, in_list);
+            }
+        },
+        Term::Pick(choices) => {
+            for (weight, choice) in choices.iter_mut() {
+                if let Some(weight) = weight {
+                    v.visit_expression(location, weight);
+                }
+                v.visit_expression(location, choice);
+            }
+        },
+        Term::DynamicCall(lhs_args, rhs_args) => {
+            v.visit_arguments(location, lhs_args);
+            v.visit_arguments(location, rhs_args);
+        },
+    }
+}
+
+pub fn walk_follow_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, follow: &mut Follow) {
+    match follow {
+        Follow::Index(expr) => v.visit_expression(location, expr),
+        Follow::Field(..) => {},
+        Follow::Call(_, _, args) => v.visit_arguments(location, args),
+    }
+}
+
+pub fn walk_index_or_field_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, index_or_field: &mut IndexOrField) {
+    match index_or_field {
+        IndexOrField::Index(expr) => v.visit_expression(location, expr),
+        IndexOrField::Field(..) => {},
+    }
+}
+
+pub fn walk_new_type_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, new_type: &mut NewType) {
+    match new_type {
+        NewType::Implicit => {},
+        NewType::Prefab(prefab) => v.visit_prefab(location, prefab),
+        NewType::MiniExpr { fields, .. } => {
+            for field in fields.iter_mut() {
+                v.visit_index_or_field(location, field);
+            }
+        },
+    }
+}
+
+pub fn walk_prefab_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, prefab: &mut Prefab) {
+    for (_, value) in prefab.vars.iter_mut() {
+        v.visit_expression(location, value);
+    }
+}
+
+pub fn walk_arguments_mut<V: VisitorMut + ?Sized>(v: &mut V, location: Location, arguments: &mut [Expression]) {
+    for arg in arguments.iter_mut() {
+        v.visit_expression(location, arg);
+    }
+}
@@ -2,14 +2,20 @@
 
 use super::objtree::*;
 use super::ast::*;
-use super::{Location, DMError};
+use super::{Location, DMError, ByondVersion};
 use super::preprocessor::{DefineMap, Define};
 
-const DM_VERSION: i32 = 513;
-const DM_BUILD: i32 = 1501;
+/// The `rgb()` color spaces, as the `COLORSPACE_*` defines.
+pub const COLORSPACE_RGB: i32 = 0;
+pub const COLORSPACE_HSV: i32 = 1;
+pub const COLORSPACE_HSL: i32 = 2;
+pub const COLORSPACE_HCY: i32 = 3;
+
+pub(crate) const DM_VERSION: i32 = 513;
+pub(crate) const DM_BUILD: i32 = 1501;
 
 /// Register BYOND builtin macros to the given define map.
-pub fn default_defines(defines: &mut DefineMap) {
+pub fn default_defines(defines: &mut DefineMap, version: ByondVersion) {
     use super::lexer::*;
     use super::lexer::Token::*;
     let location = Location::builtins();
@@ -69,8 +75,8 @@ pub fn default_defines(defines: &mut DefineMap) {
         }
     }
     c! {
-        DM_VERSION = Int(DM_VERSION);
-        DM_BUILD = Int(DM_BUILD);
+        DM_VERSION = Int(version.version);
+        DM_BUILD = Int(version.build);
         SPACEMAN_DMM = Int(1);
 
         // eye and sight
@@ -133,7 +139,6 @@ pub fn default_defines(defines: &mut DefineMap) {
         QUAD_EASING = Int(7);
         EASE_IN = Int(64);
         EASE_OUT = Int(128);
-        JUMP_EASING = Int(256); // 513
 
         // animation flags
         ANIMATION_END_NOW = Int(1);
@@ -156,8 +161,15 @@ pub fn default_defines(defines: &mut DefineMap) {
         DATABASE_ROW_COLUMN_NAMES = Int(16);
         DATABASE_ROW_COLUMN_VALUE = Int(17);
         DATABASE_ROW_LIST = Int(18);
+    }
+
+    if !version.at_least(513) {
+        return;
+    }
+
+    c! {
+        JUMP_EASING = Int(256);
 
-        // 513 stuff
         // alpha mask filter
         MASK_INVERSE = Int(1);
         MASK_SWAP = Int(2);
@@ -193,10 +205,22 @@ pub fn default_defines(defines: &mut DefineMap) {
         VIS_UNDERLAY = Int(64);
         VIS_HIDE = Int(128);
     }
+
+    if !version.at_least(514) {
+        return;
+    }
+
+    c! {
+        // rgb() color spaces
+        COLORSPACE_RGB = Int(COLORSPACE_RGB);
+        COLORSPACE_HSV = Int(COLORSPACE_HSV);
+        COLORSPACE_HSL = Int(COLORSPACE_HSL);
+        COLORSPACE_HCY = Int(COLORSPACE_HCY);
+    }
 }
 
 /// Register BYOND builtins into the specified object tree.
-pub fn register_builtins(tree: &mut ObjectTree) -> Result<(), DMError> {
+pub fn register_builtins(tree: &mut ObjectTree, version: ByondVersion) -> Result<(), DMError> {
     macro_rules! entries {
         ($($($elem:ident)/ * $(($($arg:ident $(= $ignored:expr)*),*))* $(= $val:expr)*;)*) => {
             $(loop {
@@ -652,8 +676,8 @@ pub fn register_builtins(tree: &mut ObjectTree) -> Result<(), DMError> {
         var/static/world/world;
         world/var/address;
         world/var/area/area = path!(/area);
-        world/var/byond_build = int!(DM_BUILD);
-        world/var/byond_version = int!(DM_VERSION);
+        world/var/byond_build = int!(version.build);
+        world/var/byond_version = int!(version.version);
         world/var/cache_lifespan = int!(30);
         world/var/list/atom/contents;
         world/var/cpu;
@@ -933,8 +957,13 @@ pub fn register_builtins(tree: &mut ObjectTree) -> Result<(), DMError> {
         savefile/proc/ImportText(/* path=cd, file */);
         savefile/proc/Lock(timeout);
         savefile/proc/Unlock();
+    };
 
-        // 513 stuff
+    if !version.at_least(513) {
+        return Ok(());
+    }
+
+    entries! {
         proc/arctan(A,B);
         proc/clamp(NumberOrList,Low,High);
         proc/islist(List);
@@ -10,6 +10,7 @@ use super::{DMError, Location, HasLocation, Context};
 use super::objtree::*;
 use super::ast::*;
 use super::preprocessor::DefineMap;
+use super::builtins::{COLORSPACE_RGB, COLORSPACE_HSV, COLORSPACE_HSL, COLORSPACE_HCY};
 
 /// An absolute typepath and optional variables.
 ///
@@ -415,11 +416,17 @@ impl Expression {
 
 /// Evaluate an expression in the preprocessor, with `defined()` available.
 pub fn preprocessor_evaluate(location: Location, expr: Expression, defines: &DefineMap) -> Result<Constant, DMError> {
+    preprocessor_evaluate_with(location, expr, &|name: &str| defines.contains_key(name))
+}
+
+/// Evaluate an expression in the preprocessor, with `defined()` answered by
+/// the given callback.
+pub fn preprocessor_evaluate_with(location: Location, expr: Expression, is_defined: &dyn Fn(&str) -> bool) -> Result<Constant, DMError> {
     ConstantFolder {
         tree: None,
         location,
         ty: NodeIndex::new(0),
-        defines: Some(defines),
+        defines: Some(is_defined),
     }.expr(expr, None)
 }
 
@@ -532,9 +539,78 @@ fn constant_ident_lookup(
     Ok(ConstLookup::Found(type_hint, value))
 }
 
+fn clamp_byte(value: f32) -> i32 {
+    (value as i32).clamp(0, 255)
+}
+
+/// Convert a float which is known to be a whole number back to an integer
+/// constant where possible.
+fn float_to_constant(value: f32) -> Constant {
+    if value >= i32::MIN as f32 && value <= i32::MAX as f32 {
+        Constant::Int(value as i32)
+    } else {
+        Constant::Float(value)
+    }
+}
+
+/// Convert hue (0-360), saturation (0-100), and value (0-100) to RGB (0-255).
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let value = value.clamp(0.0, 100.0) * 2.55;
+    let chroma = value * saturation.clamp(0.0, 100.0) / 100.0;
+    hue_to_rgb(hue, chroma, value - chroma)
+}
+
+/// Convert hue (0-360), saturation (0-100), and luminance (0-100) to RGB (0-255).
+fn hsl_to_rgb(hue: f32, saturation: f32, luminance: f32) -> [f32; 3] {
+    let luminance = luminance.clamp(0.0, 100.0) / 100.0;
+    let saturation = saturation.clamp(0.0, 100.0) / 100.0;
+    let chroma = (1.0 - (2.0 * luminance - 1.0).abs()) * saturation * 255.0;
+    hue_to_rgb(hue, chroma, luminance * 255.0 - chroma / 2.0)
+}
+
+fn hue_to_rgb(hue: f32, chroma: f32, min: f32) -> [f32; 3] {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as i32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    [(r + min).round(), (g + min).round(), (b + min).round()]
+}
+
+/// Format a number with the given number of significant digits, as
+/// `num2text` does.
+fn format_significant(n: f32, digits: i32) -> String {
+    if !n.is_finite() || n == 0.0 || digits <= 0 {
+        return crate::lexer::FormatFloat(n).to_string();
+    }
+    let exp = n.abs().log10().floor() as i32;
+    let trim = |mut s: String| {
+        if s.contains('.') {
+            while s.ends_with('0') {
+                s.pop();
+            }
+            if s.ends_with('.') {
+                s.pop();
+            }
+        }
+        s
+    };
+    if exp >= digits || exp <= -5 {
+        let mantissa = n / 10.0f32.powi(exp);
+        format!("{}e{:+04}", trim(format!("{:.*}", (digits - 1) as usize, mantissa)), exp)
+    } else {
+        trim(format!("{:.*}", std::cmp::max(digits - 1 - exp, 0) as usize, n))
+    }
+}
+
 struct ConstantFolder<'a> {
     tree: Option<&'a mut ObjectTree>,
-    defines: Option<&'a DefineMap>,
+    defines: Option<&'a dyn Fn(&str) -> bool>,
     location: Location,
     ty: NodeIndex,
 }
@@ -559,7 +635,7 @@ impl<'a> ConstantFolder<'a> {
                     term = self.follow(term, each.elem)?;
                 }
                 for each in unary.into_iter().rev() {
-                    term = self.unary(term, each)?;
+                    term = self.unary(term, each.elem)?;
                 }
                 term
             },
@@ -579,7 +655,6 @@ impl<'a> ConstantFolder<'a> {
     }
 
     /// list of expressions, keyword arguments disallowed
-    #[allow(dead_code)]
     fn expr_vec(&mut self, v: Vec<Expression>) -> Result<Vec<Constant>, DMError> {
         let mut out = Vec::new();
         for each in v {
@@ -740,25 +815,40 @@ impl<'a> ConstantFolder<'a> {
                 "cos" => self.trig_op(args, f32::cos)?,
                 "arcsin" => self.trig_op(args, f32::asin)?,
                 "arccos" => self.trig_op(args, f32::acos)?,
-                "rgb" => {
-                    use std::fmt::Write;
-                    if args.len() != 3 && args.len() != 4 {
-                        return Err(self.error("malformed rgb() call"));
-                    }
-                    let mut result = String::with_capacity(7);
-                    result.push_str("#");
-                    for each in args {
-                        if let Some(i) = self.expr(each, None)?.to_int() {
-                            let clamped = std::cmp::max(::std::cmp::min(i, 255), 0);
-                            let _ = write!(result, "{:02x}", clamped);
-                        } else {
-                            return Err(self.error("malformed rgb() call"));
-                        }
-                    }
-                    Constant::String(result)
+                "sqrt" => self.sqrt(args)?,
+                "abs" => match self.single_arg(args, "abs")? {
+                    Constant::Int(i) => Constant::Int(i.wrapping_abs()),
+                    Constant::Float(f) => Constant::Float(f.abs()),
+                    _ => return Err(self.error("abs() requires numeric argument")),
+                },
+                "log" => self.log(args)?,
+                "round" => self.round(args)?,
+                "min" => self.min_max(args, "min", std::cmp::Ordering::Less)?,
+                "max" => self.min_max(args, "max", std::cmp::Ordering::Greater)?,
+                "rgb" => self.rgb(args)?,
+                "text" => self.text(args)?,
+                "num2text" => self.num2text(args)?,
+                "length" => match self.single_arg(args, "length")? {
+                    Constant::String(s) => Constant::Int(s.len() as i32),
+                    Constant::List(list) => Constant::Int(list.len() as i32),
+                    Constant::Null(_) | Constant::Int(_) | Constant::Float(_) => Constant::Int(0),
+                    other => return Err(self.error(format!("non-constant length: {}", other))),
+                },
+                "copytext" => self.copytext(args)?,
+                "uppertext" => {
+                    let text = self.single_arg(args, "uppertext")?;
+                    Constant::String(self.stringify(&text)?.to_ascii_uppercase())
+                },
+                "lowertext" => {
+                    let text = self.single_arg(args, "lowertext")?;
+                    Constant::String(self.stringify(&text)?.to_ascii_lowercase())
+                },
+                "ascii2text" => match self.single_arg(args, "ascii2text")?.to_int() {
+                    Some(i) => Constant::String(std::char::from_u32(i as u32).map(String::from).unwrap_or_default()),
+                    None => return Err(self.error("ascii2text() requires numeric argument")),
                 },
                 "defined" if self.defines.is_some() => {
-                    let defines = self.defines.unwrap();  // annoying, but keeps the match clean
+                    let is_defined = self.defines.unwrap();  // annoying, but keeps the match clean
                     if args.len() != 1 {
                         return Err(self.error("malformed defined() call"));
                     }
@@ -768,7 +858,7 @@ impl<'a> ConstantFolder<'a> {
                             term: Spanned { elem: Term::Ident(ref ident), .. },
                             ref follow
                         } if unary.is_empty() && follow.is_empty() => {
-                            Constant::Int(if defines.contains_key(ident) { 1 } else { 0 })
+                            Constant::Int(if is_defined(ident) { 1 } else { 0 })
                         },
                         _ => return Err(self.error("malformed defined() call")),
                     }
@@ -797,6 +887,245 @@ impl<'a> ConstantFolder<'a> {
         }
     }
 
+    fn sqrt(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        match self.single_arg(args, "sqrt")?.to_float() {
+            Some(f) if f < 0. => Err(self.error("sqrt() of negative number")),
+            Some(f) => Ok(Constant::Float(f.sqrt())),
+            None => Err(self.error("sqrt() requires numeric argument")),
+        }
+    }
+
+    fn single_arg(&mut self, mut args: Vec<Expression>, name: &str) -> Result<Constant, DMError> {
+        if args.len() != 1 {
+            return Err(self.error(format!("{}() requires exactly 1 argument", name)));
+        }
+        self.expr(args.remove(0), None)
+    }
+
+    /// Convert a constant to text the way embedding it in a string would.
+    fn stringify(&self, value: &Constant) -> Result<String, DMError> {
+        Ok(match value {
+            Constant::Null(_) => String::new(),
+            Constant::String(s) | Constant::Resource(s) => s.clone(),
+            Constant::Int(i) => crate::lexer::FormatFloat(*i as f32).to_string(),
+            Constant::Float(f) => crate::lexer::FormatFloat(*f).to_string(),
+            Constant::Prefab(pop) if pop.vars.is_empty() => pop.to_string(),
+            other => return Err(self.error(format!("non-constant text conversion: {}", other))),
+        })
+    }
+
+    fn log(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        let args = self.expr_vec(args)?;
+        match (args.first().and_then(Constant::to_float), args.get(1).and_then(Constant::to_float), args.len()) {
+            (Some(x), _, 1) => Ok(Constant::Float(x.ln())),
+            (Some(base), Some(x), 2) => Ok(Constant::Float(x.log(base))),
+            _ => Err(self.error("malformed log() call")),
+        }
+    }
+
+    fn round(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        let args = self.expr_vec(args)?;
+        match (args.first(), args.get(1), args.len()) {
+            // round(A) rounds down
+            (Some(&Constant::Int(a)), _, 1) => Ok(Constant::Int(a)),
+            (Some(&Constant::Float(a)), _, 1) => Ok(float_to_constant(a.floor())),
+            // round(A, B) rounds to the nearest multiple of B
+            (Some(&Constant::Int(a)), Some(&Constant::Int(b)), 2) if b != 0 => {
+                Ok(Constant::Int(((a as f32 / b as f32) + 0.5).floor() as i32 * b))
+            },
+            (Some(a), Some(b), 2) => match (a.to_float(), b.to_float()) {
+                (Some(a), Some(b)) if b != 0.0 => Ok(Constant::Float(((a / b) + 0.5).floor() * b)),
+                _ => Err(self.error("malformed round() call")),
+            },
+            _ => Err(self.error("malformed round() call")),
+        }
+    }
+
+    fn min_max(&mut self, args: Vec<Expression>, name: &str, keep: std::cmp::Ordering) -> Result<Constant, DMError> {
+        let mut values = self.expr_vec(args)?;
+        // min(list) and max(list) consider the list's elements
+        if values.len() == 1 {
+            if let Constant::List(list) = values.remove(0) {
+                values = list.into_iter().map(|(key, _)| key).collect();
+            } else {
+                return Err(self.error(format!("malformed {}() call", name)));
+            }
+        }
+
+        let mut iter = values.into_iter();
+        let mut best = match iter.next() {
+            Some(first) => first,
+            None => return Err(self.error(format!("malformed {}() call", name))),
+        };
+        for each in iter {
+            let ordering = match (&each, &best) {
+                (Constant::String(a), Constant::String(b)) => a.cmp(b),
+                (a, b) => match (a.to_float(), b.to_float()) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+                    _ => return Err(self.error(format!("non-constant {}: {}, {}", name, a, b))),
+                },
+            };
+            if ordering == keep {
+                best = each;
+            }
+        }
+        Ok(best)
+    }
+
+    fn rgb(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        use std::fmt::Write;
+
+        // red, green, blue, alpha, color space
+        let mut components: [Option<Constant>; 5] = Default::default();
+        let mut implied_space = None;
+        let mut position = 0;
+        for (key, value) in self.arguments(args)? {
+            let (index, value) = match value {
+                None => {
+                    position += 1;
+                    (position - 1, key)
+                },
+                Some(value) => {
+                    let index = match key.as_str() {
+                        Some("r") | Some("red") => 0,
+                        Some("g") | Some("green") => 1,
+                        Some("b") | Some("blue") => 2,
+                        Some("h") | Some("hue") => 0,
+                        Some("s") | Some("saturation") => 1,
+                        Some("v") | Some("value") => {
+                            implied_space = Some(COLORSPACE_HSV);
+                            2
+                        },
+                        Some("l") | Some("luminance") => {
+                            implied_space = Some(COLORSPACE_HSL);
+                            2
+                        },
+                        Some("c") | Some("chroma") => {
+                            implied_space = Some(COLORSPACE_HCY);
+                            1
+                        },
+                        Some("y") => {
+                            implied_space = Some(COLORSPACE_HCY);
+                            2
+                        },
+                        Some("a") | Some("alpha") => 3,
+                        Some("space") => 4,
+                        _ => return Err(self.error(format!("bad rgb() argument: {}", key))),
+                    };
+                    (index, value)
+                },
+            };
+            match components.get_mut(index) {
+                Some(slot) => *slot = Some(value),
+                None => return Err(self.error("too many arguments to rgb()")),
+            }
+        }
+
+        let mut numbers = [0.0f32; 5];
+        for (number, component) in numbers.iter_mut().zip(components.iter()) {
+            if let Some(component) = component {
+                *number = match component.to_float() {
+                    Some(f) => f,
+                    None => return Err(self.error("malformed rgb() call")),
+                };
+            }
+        }
+        if components[0].is_none() || components[1].is_none() || components[2].is_none() {
+            return Err(self.error("malformed rgb() call"));
+        }
+
+        let space = match components[4] {
+            Some(_) => numbers[4] as i32,
+            None => implied_space.unwrap_or(COLORSPACE_RGB),
+        };
+        let rgb = match space {
+            COLORSPACE_RGB => [numbers[0], numbers[1], numbers[2]],
+            COLORSPACE_HSV => hsv_to_rgb(numbers[0], numbers[1], numbers[2]),
+            COLORSPACE_HSL => hsl_to_rgb(numbers[0], numbers[1], numbers[2]),
+            COLORSPACE_HCY => return Err(self.error("rgb() with COLORSPACE_HCY cannot be evaluated as a constant")),
+            _ => return Err(self.error(format!("unsupported rgb() color space: {}", space))),
+        };
+
+        let mut result = String::with_capacity(9);
+        result.push('#');
+        for &each in rgb.iter() {
+            let _ = write!(result, "{:02x}", clamp_byte(each));
+        }
+        if components[3].is_some() {
+            let _ = write!(result, "{:02x}", clamp_byte(numbers[3]));
+        }
+        Ok(Constant::String(result))
+    }
+
+    fn text(&mut self, mut args: Vec<Expression>) -> Result<Constant, DMError> {
+        if args.is_empty() {
+            return Err(self.error("malformed text() call"));
+        }
+        let format = args.remove(0);
+        let mut args = self.expr_vec(args)?.into_iter();
+
+        // each empty [] in the format string is replaced by the next argument
+        let (mut result, parts) = match format {
+            Expression::Base { ref unary, ref follow, term: Spanned { elem: Term::InterpString(ref begin, ref parts), .. } }
+                if unary.is_empty() && follow.is_empty() => (begin.clone(), parts.clone()),
+            other => match self.expr(other, None)? {
+                Constant::String(s) => (s, Vec::new()),
+                _ => return Err(self.error("malformed text() call")),
+            },
+        };
+        for (expr, piece) in parts {
+            let value = match expr {
+                Some(expr) => Some(self.expr(expr, None)?),
+                None => args.next(),
+            };
+            if let Some(value) = value {
+                result.push_str(&self.stringify(&value)?);
+            }
+            result.push_str(&piece);
+        }
+        Ok(Constant::String(result))
+    }
+
+    fn num2text(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        let args = self.expr_vec(args)?;
+        match (args.first().and_then(Constant::to_float), args.get(1).and_then(Constant::to_int), args.len()) {
+            (Some(n), _, 1) => Ok(Constant::String(crate::lexer::FormatFloat(n).to_string())),
+            (Some(n), Some(digits), 2) => Ok(Constant::String(format_significant(n, digits))),
+            _ => Err(self.error("malformed num2text() call")),
+        }
+    }
+
+    fn copytext(&mut self, args: Vec<Expression>) -> Result<Constant, DMError> {
+        let args = self.expr_vec(args)?;
+        if args.is_empty() || args.len() > 3 {
+            return Err(self.error("malformed copytext() call"));
+        }
+        let text = match &args[0] {
+            Constant::String(text) => text.as_bytes(),
+            Constant::Null(_) => return Ok(Constant::String(String::new())),
+            _ => return Err(self.error("copytext() requires text argument")),
+        };
+        let mut bounds = [1, 0];
+        for (bound, arg) in bounds.iter_mut().zip(args[1..].iter()) {
+            *bound = match arg.to_int() {
+                Some(i) => i,
+                None => return Err(self.error("copytext() requires numeric bounds")),
+            };
+        }
+
+        // 1-indexed and inclusive of start; 0 or negative ends count from
+        // the end of the text
+        let len = text.len() as i32;
+        let resolve = |i: i32| if i <= 0 { len + 1 + i } else { i };
+        let start = std::cmp::max(resolve(bounds[0]), 1);
+        let end = std::cmp::min(resolve(bounds[1]), len + 1);
+        if start >= end {
+            return Ok(Constant::String(String::new()));
+        }
+        let slice = &text[start as usize - 1..end as usize - 1];
+        Ok(Constant::String(String::from_utf8_lossy(slice).into_owned()))
+    }
+
     fn prefab(&mut self, prefab: Prefab) -> Result<Pop, DMError> {
         let vars = self.vars(prefab.vars)?;
 
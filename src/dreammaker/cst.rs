@@ -0,0 +1,384 @@
+//! A lossless token tree, for tools which rewrite source code.
+//!
+//! Unlike the normal parsing pipeline, the tree keeps every byte of the input:
+//! whitespace and comments are retained as trivia attached to the token which
+//! follows them, and the original file can be reproduced exactly. The tree is
+//! built from the same `Lexer` and `IndentProcessor` used for parsing, and so
+//! operates on the file as written, before preprocessing.
+//!
+//! The only nodes are the groups formed by `{}`, `()`, and `[]`, including
+//! the braces implied by indentation. There are no statement or expression
+//! nodes; to find the trivia belonging to part of the AST, look up the tokens
+//! within its span with `SyntaxTree::tokens_between`.
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+use super::{Context, FileId, Location};
+use super::lexer::{Lexer, LocatedToken, Token, Punctuation, from_utf8_or_latin1_borrowed};
+use super::indents::IndentProcessor;
+
+/// A byte range within the source file.
+pub type Span = Range<usize>;
+
+/// The kind of a piece of trivia.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs, newlines, and line continuations.
+    Whitespace,
+    /// A comment which is not a documentation comment.
+    Comment,
+}
+
+/// Whitespace or a comment between two tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+/// A single token and the trivia which precedes it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub token: Token,
+    pub location: Location,
+    /// The source bytes of the token. Empty for virtual tokens.
+    pub span: Span,
+    pub leading_trivia: Vec<Trivia>,
+    /// Whether this token was inserted by the indentation processor and does
+    /// not appear in the source.
+    pub is_virtual: bool,
+}
+
+/// The kind of a syntax node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The whole file.
+    Root,
+    /// A `{}` block, which may be written out or implied by indentation.
+    Block,
+    /// A `()` group.
+    Paren,
+    /// A `[]` group.
+    Bracket,
+}
+
+impl NodeKind {
+    fn open(token: &Token) -> Option<NodeKind> {
+        match *token {
+            Token::Punct(Punctuation::LBrace) => Some(NodeKind::Block),
+            Token::Punct(Punctuation::LParen) => Some(NodeKind::Paren),
+            Token::Punct(Punctuation::LBracket) => Some(NodeKind::Bracket),
+            _ => None,
+        }
+    }
+
+    fn is_close(self, token: &Token) -> bool {
+        match (self, token) {
+            (NodeKind::Block, &Token::Punct(Punctuation::RBrace)) => true,
+            (NodeKind::Paren, &Token::Punct(Punctuation::RParen)) => true,
+            (NodeKind::Bracket, &Token::Punct(Punctuation::RBracket)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A child of a syntax node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Token(SyntaxToken),
+    Node(SyntaxNode),
+}
+
+/// A delimited group of tokens and nodes. Includes its delimiters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: NodeKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: NodeKind) -> SyntaxNode {
+        SyntaxNode { kind, children: Vec::new() }
+    }
+
+    /// Iterate over all the tokens beneath this node, in source order.
+    pub fn tokens(&self) -> Tokens {
+        Tokens { stack: vec![self.children.iter()] }
+    }
+}
+
+/// An iterator over the tokens beneath a node.
+pub struct Tokens<'a> {
+    stack: Vec<std::slice::Iter<'a, SyntaxElement>>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a SyntaxToken;
+
+    fn next(&mut self) -> Option<&'a SyntaxToken> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(SyntaxElement::Token(token)) => return Some(token),
+                Some(SyntaxElement::Node(node)) => self.stack.push(node.children.iter()),
+                None => { self.stack.pop(); }
+            }
+        }
+    }
+}
+
+/// A lossless syntax tree for a single file.
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    source: Vec<u8>,
+    root: SyntaxNode,
+    trailing_trivia: Vec<Trivia>,
+}
+
+impl SyntaxTree {
+    /// Build the syntax tree for a file on disk.
+    pub fn from_path(context: &Context, path: &Path) -> io::Result<SyntaxTree> {
+        let source = std::fs::read(path)?;
+        let file = context.register_file(path);
+        Ok(SyntaxTree::parse(context, file, source))
+    }
+
+    /// Build the syntax tree for the given source text.
+    pub fn parse(context: &Context, file: FileId, source: Vec<u8>) -> SyntaxTree {
+        let line_starts = line_starts(&source);
+        let raw = Rc::new(RefCell::new(VecDeque::new()));
+
+        let mut builder = Builder {
+            source: &source,
+            pending: Vec::new(),
+            position: 0,
+            stack: vec![SyntaxNode::new(NodeKind::Root)],
+        };
+        {
+            let recorder = Recorder {
+                lexer: Lexer::new(context, file, source.iter().map(|&b| Ok(b))),
+                line_starts: &line_starts,
+                len: source.len(),
+                raw: raw.clone(),
+            };
+            for token in IndentProcessor::new(context, recorder) {
+                builder.token(&mut raw.borrow_mut(), token);
+            }
+        }
+
+        // Anything left over is whitespace trailing the last real token.
+        for (_, span) in raw.borrow_mut().drain(..) {
+            builder.trivia_until(span.end);
+        }
+        builder.trivia_until(source.len());
+        let trailing_trivia = std::mem::replace(&mut builder.pending, Vec::new());
+        let root = builder.finish();
+
+        SyntaxTree {
+            source,
+            root,
+            trailing_trivia,
+        }
+    }
+
+    /// The root node of the tree.
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    /// Iterate over the tokens written between two locations, such as the
+    /// span of an AST node. Virtual tokens are skipped.
+    pub fn tokens_between(&self, start: Location, end: Location) -> impl Iterator<Item=&SyntaxToken> {
+        self.root.tokens().filter(move |token| {
+            !token.is_virtual && token.location >= start && token.location < end
+        })
+    }
+
+    /// Trivia following the last token in the file.
+    pub fn trailing_trivia(&self) -> &[Trivia] {
+        &self.trailing_trivia
+    }
+
+    /// The original source bytes.
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    /// Get the source text for the given span.
+    pub fn text(&self, span: &Span) -> Cow<str> {
+        from_utf8_or_latin1_borrowed(&self.source[span.clone()])
+    }
+
+    /// Write the tree back out. The output is identical to the input.
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        for token in self.root.tokens() {
+            for trivia in token.leading_trivia.iter() {
+                w.write_all(&self.source[trivia.span.clone()])?;
+            }
+            w.write_all(&self.source[token.span.clone()])?;
+        }
+        for trivia in self.trailing_trivia.iter() {
+            w.write_all(&self.source[trivia.span.clone()])?;
+        }
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Construction
+
+/// Passes tokens from the lexer to the indent processor, noting the source
+/// span of each.
+struct Recorder<'ctx, 'a, I> {
+    lexer: Lexer<'ctx, I>,
+    line_starts: &'a [usize],
+    len: usize,
+    raw: Rc<RefCell<VecDeque<(LocatedToken, Span)>>>,
+}
+
+impl<'ctx, 'a, I: Iterator<Item=io::Result<u8>>> Iterator for Recorder<'ctx, 'a, I> {
+    type Item = LocatedToken;
+
+    fn next(&mut self) -> Option<LocatedToken> {
+        let token = self.lexer.next()?;
+        let start = match self.line_starts.get((token.location.line as usize).wrapping_sub(1)) {
+            Some(&line_start) => std::cmp::min(line_start + (token.location.column as usize).saturating_sub(1), self.len),
+            None => self.len,
+        };
+        let end = std::cmp::max(start, self.lexer.byte_offset());
+        self.raw.borrow_mut().push_back((token.clone(), start..end));
+        Some(token)
+    }
+}
+
+fn line_starts(source: &[u8]) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (i, &b) in source.iter().enumerate() {
+        if b == b'\n' {
+            starts.push(i + 1);
+        }
+    }
+    starts
+}
+
+struct Builder<'a> {
+    source: &'a [u8],
+    pending: Vec<Trivia>,
+    position: usize,
+    stack: Vec<SyntaxNode>,
+}
+
+impl<'a> Builder<'a> {
+    fn token(&mut self, raw: &mut VecDeque<(LocatedToken, Span)>, token: LocatedToken) {
+        // Whitespace the indent processor consumed becomes trivia.
+        while let Some((front, _)) = raw.front() {
+            match front.token {
+                Token::Punct(Punctuation::Newline) |
+                Token::Punct(Punctuation::Space) |
+                Token::Punct(Punctuation::Tab) => {
+                    let (_, span) = raw.pop_front().unwrap();
+                    self.trivia_until(span.end);
+                }
+                _ => break,
+            }
+        }
+
+        let is_virtual = match raw.front() {
+            Some((front, _)) => *front != token,
+            None => true,
+        };
+        let span = if is_virtual {
+            self.position..self.position
+        } else {
+            let (_, span) = raw.pop_front().unwrap();
+            self.trivia_until(span.start);
+            self.position = span.end;
+            span
+        };
+        let leading_trivia = if is_virtual {
+            Vec::new()
+        } else {
+            std::mem::replace(&mut self.pending, Vec::new())
+        };
+
+        let open = NodeKind::open(&token.token);
+        let closes = self.stack.len() > 1 && self.stack.last().unwrap().kind.is_close(&token.token);
+        let element = SyntaxElement::Token(SyntaxToken {
+            token: token.token,
+            location: token.location,
+            span,
+            leading_trivia,
+            is_virtual,
+        });
+
+        if let Some(kind) = open {
+            let mut node = SyntaxNode::new(kind);
+            node.children.push(element);
+            self.stack.push(node);
+        } else {
+            self.stack.last_mut().unwrap().children.push(element);
+            if closes {
+                let node = self.stack.pop().unwrap();
+                self.stack.last_mut().unwrap().children.push(SyntaxElement::Node(node));
+            }
+        }
+    }
+
+    /// Split the source between the current position and `end` into trivia.
+    fn trivia_until(&mut self, end: usize) {
+        let mut i = self.position;
+        while i < end {
+            let start = i;
+            let kind = if self.source[i..end].starts_with(b"//") {
+                while i < end && self.source[i] != b'\n' {
+                    i += 1;
+                }
+                TriviaKind::Comment
+            } else if self.source[i..end].starts_with(b"/*") {
+                let mut depth = 0;
+                while i < end {
+                    if self.source[i..end].starts_with(b"/*") {
+                        depth += 1;
+                        i += 2;
+                    } else if self.source[i..end].starts_with(b"*/") {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                TriviaKind::Comment
+            } else {
+                while i < end && !self.source[i..end].starts_with(b"//") && !self.source[i..end].starts_with(b"/*") {
+                    i += 1;
+                }
+                TriviaKind::Whitespace
+            };
+            let i_end = std::cmp::min(i, end);
+            match self.pending.last_mut() {
+                Some(last) if last.kind == kind && kind == TriviaKind::Whitespace && last.span.end == start => {
+                    last.span.end = i_end;
+                }
+                _ => self.pending.push(Trivia { kind, span: start..i_end }),
+            }
+            i = i_end;
+        }
+        self.position = std::cmp::max(self.position, end);
+    }
+
+    fn finish(mut self) -> SyntaxNode {
+        // Fold any unclosed nodes into their parents.
+        while self.stack.len() > 1 {
+            let node = self.stack.pop().unwrap();
+            self.stack.last_mut().unwrap().children.push(SyntaxElement::Node(node));
+        }
+        self.stack.pop().unwrap()
+    }
+}
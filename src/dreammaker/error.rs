@@ -2,8 +2,10 @@
 
 use std::{fmt, error, io};
 use std::path::{PathBuf, Path};
-use std::cell::{RefCell, Ref, RefMut};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use termcolor::{ColorSpec, Color};
 
@@ -22,39 +24,61 @@ impl Default for FileId {
     }
 }
 
+/// The number of buckets new diagnostics are spread across.
+const ERROR_SHARDS: usize = 16;
+
 /// A diagnostics context, tracking loaded files and any observed errors.
+///
+/// The context is internally synchronized, so files and errors may be
+/// registered from several threads at once.
 #[derive(Debug, Default)]
 pub struct Context {
     /// The list of loaded files.
-    files: RefCell<Vec<PathBuf>>,
+    files: RwLock<Vec<PathBuf>>,
     /// Reverse mapping from paths to file numbers.
-    reverse_files: RefCell<HashMap<PathBuf, FileId>>,
-    /// A list of errors, warnings, and other diagnostics generated.
-    errors: RefCell<Vec<DMError>>,
+    reverse_files: RwLock<HashMap<PathBuf, FileId>>,
+    /// A list of errors, warnings, and other diagnostics generated. Views
+    /// returned by `errors` share it, and it is copied on write while any
+    /// are alive.
+    errors: Mutex<Arc<Vec<DMError>>>,
+    /// Diagnostics not yet merged into `errors`, tagged with the order in
+    /// which they were registered. Each thread pushes to its own shard.
+    pending_errors: [Mutex<Vec<(usize, DMError)>>; ERROR_SHARDS],
+    /// The registration order of the next diagnostic.
+    error_sequence: AtomicUsize,
     /// Severity at and above which errors will be printed immediately.
     print_severity: Option<Severity>,
+    /// The BYOND version whose builtins and syntax are accepted.
+    byond_version: ByondVersion,
+    /// Lines whose diagnostics are suppressed by a comment.
+    suppressed_lines: RwLock<HashSet<(FileId, u32)>>,
 }
 
 impl Context {
     /// Add a new file to the context and return its index.
     pub fn register_file(&self, path: &Path) -> FileId {
-        if let Some(id) = self.reverse_files.borrow().get(path).cloned() {
+        if let Some(id) = self.get_file(path) {
+            return id;
+        }
+        let mut reverse_files = write(&self.reverse_files);
+        // Another thread may have registered the file in the meantime.
+        if let Some(id) = reverse_files.get(path).cloned() {
             return id;
         }
-        let mut files = self.files.borrow_mut();
+        let mut files = write(&self.files);
         if files.len() > FILEID_MAX.0 as usize {
             panic!("file limit of {} exceeded", FILEID_MAX.0);
         }
         let len = files.len() as u16;
         files.push(path.to_owned());
         let id = FileId(len + FILEID_MIN.0);
-        self.reverse_files.borrow_mut().insert(path.to_owned(), id);
+        reverse_files.insert(path.to_owned(), id);
         id
     }
 
     /// Look up a file's ID by its path, without inserting it.
     pub fn get_file(&self, path: &Path) -> Option<FileId> {
-        self.reverse_files.borrow().get(path).cloned()
+        read(&self.reverse_files).get(path).cloned()
     }
 
     /// Look up a file path by its index returned from `register_file`.
@@ -63,7 +87,7 @@ impl Context {
             return "(builtins)".into();
         }
         let idx = (file.0 - FILEID_MIN.0) as usize;
-        let files = self.files.borrow();
+        let files = read(&self.files);
         if idx > files.len() {
             "(unknown)".into()
         } else {
@@ -73,6 +97,9 @@ impl Context {
 
     /// Push an error or other diagnostic to the context.
     pub fn register_error(&self, error: DMError) {
+        if self.is_suppressed(error.location) {
+            return;
+        }
         if let Some(severity) = self.print_severity {
             if error.severity <= severity {
                 let stderr = termcolor::StandardStream::stderr(termcolor::ColorChoice::Auto);
@@ -80,17 +107,61 @@ impl Context {
                     .expect("error writing to stderr");
             }
         }
-        self.errors.borrow_mut().push(error);
+        let sequence = self.error_sequence.fetch_add(1, Ordering::Relaxed);
+        lock(&self.pending_errors[shard_index()]).push((sequence, error));
     }
 
     /// Access the list of diagnostics generated so far.
-    pub fn errors(&self) -> Ref<[DMError]> {
-        Ref::map(self.errors.borrow(), |x| &**x)
+    ///
+    /// The returned view is a snapshot which holds no lock: diagnostics
+    /// registered while it is alive appear in the next call.
+    pub fn errors(&self) -> Errors {
+        let mut errors = lock(&self.errors);
+        self.merge_pending_errors(&mut errors);
+        Errors(errors.clone())
     }
 
     /// Mutably access the diagnostics list. Dangerous.
-    pub fn errors_mut(&self) -> RefMut<Vec<DMError>> {
-        self.errors.borrow_mut()
+    ///
+    /// Views returned by `errors` are not affected by changes made here.
+    pub fn errors_mut(&self) -> ErrorsMut {
+        let mut errors = lock(&self.errors);
+        self.merge_pending_errors(&mut errors);
+        ErrorsMut(errors)
+    }
+
+    fn merge_pending_errors(&self, errors: &mut Arc<Vec<DMError>>) {
+        let mut pending = Vec::new();
+        for shard in self.pending_errors.iter() {
+            pending.extend(lock(shard).drain(..));
+        }
+        if pending.is_empty() {
+            return;
+        }
+        pending.sort_by_key(|&(sequence, _)| sequence);
+        let suppressed = read(&self.suppressed_lines);
+        Arc::make_mut(errors).extend(pending.into_iter()
+            .map(|(_, error)| error)
+            .filter(|error| !suppressed.contains(&(error.location.file, error.location.line))));
+    }
+
+    /// Suppress diagnostics on the given line, as by a
+    /// `// SpacemanDMM_ignore` comment.
+    ///
+    /// Diagnostics on the line which were already returned by `errors` are
+    /// not removed.
+    pub fn suppress_line(&self, file: FileId, line: u32) {
+        write(&self.suppressed_lines).insert((file, line));
+    }
+
+    /// Forget the suppressed lines in a file, before it is parsed again.
+    pub fn clear_suppressed_lines(&self, file: FileId) {
+        write(&self.suppressed_lines).retain(|&(each, _)| each != file);
+    }
+
+    /// Check whether diagnostics at the given location are suppressed.
+    pub fn is_suppressed(&self, location: Location) -> bool {
+        read(&self.suppressed_lines).contains(&(location.file, location.line))
     }
 
     /// Set a severity at and above which errors will be printed immediately.
@@ -98,6 +169,17 @@ impl Context {
         self.print_severity = print_severity;
     }
 
+    /// Get the BYOND version being targeted.
+    pub fn byond_version(&self) -> ByondVersion {
+        self.byond_version
+    }
+
+    /// Set the BYOND version to target. Builtins and syntax newer than this
+    /// version will not be recognized.
+    pub fn set_byond_version(&mut self, byond_version: ByondVersion) {
+        self.byond_version = byond_version;
+    }
+
     /// Pretty-print a `DMError` to the given output.
     pub fn pretty_print_error<W: termcolor::WriteColor>(&self, w: &mut W, error: &DMError) -> io::Result<()> {
         writeln!(
@@ -171,19 +253,125 @@ impl Context {
     /// Clone the file list of this Context but not its error list.
     pub fn clone_file_list(&self) -> Context {
         Context {
-            files: self.files.clone(),
-            reverse_files: self.reverse_files.clone(),
+            files: RwLock::new(read(&self.files).clone()),
+            reverse_files: RwLock::new(read(&self.reverse_files).clone()),
             errors: Default::default(),
+            pending_errors: Default::default(),
+            error_sequence: Default::default(),
             print_severity: Default::default(),
+            byond_version: self.byond_version,
+            suppressed_lines: Default::default(),
         }
     }
 }
 
+/// A read-only snapshot of the diagnostics registered with a `Context`.
+pub struct Errors(Arc<Vec<DMError>>);
+
+impl Deref for Errors {
+    type Target = [DMError];
+
+    fn deref(&self) -> &[DMError] {
+        &self.0
+    }
+}
+
+/// Mutable access to the diagnostics registered with a `Context`. New
+/// diagnostics wait to be merged until this is dropped.
+pub struct ErrorsMut<'a>(MutexGuard<'a, Arc<Vec<DMError>>>);
+
+impl<'a> Deref for ErrorsMut<'a> {
+    type Target = Vec<DMError>;
+
+    fn deref(&self) -> &Vec<DMError> {
+        &self.0
+    }
+}
+
+impl<'a> DerefMut for ErrorsMut<'a> {
+    fn deref_mut(&mut self) -> &mut Vec<DMError> {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+/// Pick the pending error shard for the current thread.
+fn shard_index() -> usize {
+    static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % ERROR_SHARDS;
+    }
+    SHARD.with(|&shard| shard)
+}
+
+// A panic while holding one of these locks can't leave the data in an
+// inconsistent state, so poisoning is ignored.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+// ----------------------------------------------------------------------------
+// BYOND version targeting
+
+/// A BYOND major version and build number.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ByondVersion {
+    /// The major version, such as `513`.
+    pub version: i32,
+    /// The build number, such as `1501`.
+    pub build: i32,
+}
+
+impl ByondVersion {
+    pub fn new(version: i32, build: i32) -> ByondVersion {
+        ByondVersion { version, build }
+    }
+
+    /// Check whether this version is at least the given major version.
+    #[inline]
+    pub fn at_least(self, version: i32) -> bool {
+        self.version >= version
+    }
+}
+
+impl Default for ByondVersion {
+    fn default() -> ByondVersion {
+        ByondVersion::new(crate::builtins::DM_VERSION, crate::builtins::DM_BUILD)
+    }
+}
+
+impl fmt::Display for ByondVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.version, self.build)
+    }
+}
+
+impl std::str::FromStr for ByondVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ByondVersion, String> {
+        let mut parts = s.splitn(2, '.');
+        let version = parts.next().unwrap_or("").parse().map_err(|_| format!("bad BYOND version: {:?}", s))?;
+        let build = match parts.next() {
+            Some(build) => build.parse().map_err(|_| format!("bad BYOND build: {:?}", s))?,
+            None => 0,
+        };
+        Ok(ByondVersion { version, build })
+    }
+}
+
 // ----------------------------------------------------------------------------
 // Location handling
 
 /// File, line, and column information for an error.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default, Hash)]
 pub struct Location {
     /// The index into the file table.
     pub file: FileId,
@@ -246,7 +434,7 @@ pub(crate) trait HasLocation {
 // Error handling
 
 /// The possible diagnostic severities available.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum Severity {
     Error = 1,
     Warning = 2,
@@ -285,7 +473,7 @@ impl fmt::Display for Severity {
 }
 
 /// A component which generated a diagnostic, when separation is desired.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Component {
     Unspecified,
     DreamChecker,
@@ -315,16 +503,34 @@ impl fmt::Display for Component {
     }
 }
 
+/// A machine-readable way to fix the problem a diagnostic points out, for
+/// editors to offer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    /// Call the parent proc at the start of the proc the diagnostic is on.
+    CallParent,
+    /// Add the named parameters to the proc declared at the given location.
+    AddParameters(Location, Vec<String>),
+    /// Declare the named var as a local where the diagnostic assigns to it.
+    DeclareVar(String),
+    /// Give the var with the given name, declared at the given location,
+    /// the given type path.
+    SetVarType(Location, String, String),
+}
+
 /// An error produced during DM parsing, with location information.
 #[derive(Debug)]
 #[must_use]
 pub struct DMError {
     location: Location,
+    end: Location,
     severity: Severity,
     component: Component,
     description: String,
     notes: Vec<DiagnosticNote>,
-    cause: Option<Box<dyn error::Error + Send + Sync>>,
+    unnecessary: bool,
+    fixes: Vec<Fix>,
+    cause: Option<Arc<dyn error::Error + Send + Sync>>,
 }
 
 /// An additional note attached to an error, at some other location.
@@ -339,16 +545,19 @@ impl DMError {
     pub fn new<S: Into<String>>(location: Location, desc: S) -> DMError {
         DMError {
             location,
+            end: location,
             severity: Default::default(),
             component: Default::default(),
             description: desc.into(),
             notes: Vec::new(),
+            unnecessary: false,
+            fixes: Vec::new(),
             cause: None,
         }
     }
 
     pub fn set_cause<E: error::Error + Send + Sync + 'static>(mut self, cause: E) -> DMError {
-        self.cause = Some(Box::new(cause));
+        self.cause = Some(Arc::new(cause));
         self
     }
 
@@ -374,6 +583,30 @@ impl DMError {
         self
     }
 
+    /// Mark this diagnostic as pointing out code which has no effect, such as
+    /// an unused macro.
+    pub fn set_unnecessary(mut self) -> DMError {
+        self.unnecessary = true;
+        self
+    }
+
+    pub fn add_fix(&mut self, fix: Fix) {
+        self.fixes.push(fix);
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> DMError {
+        self.add_fix(fix);
+        self
+    }
+
+    /// Extend this error to cover the code up to `end`.
+    pub fn with_end(mut self, end: Location) -> DMError {
+        if end > self.location {
+            self.end = end;
+        }
+        self
+    }
+
     #[inline]
     pub fn register(self, context: &Context) {
         context.register_error(self)
@@ -384,6 +617,12 @@ impl DMError {
         self.location
     }
 
+    /// Get the end of the code this error covers. Equal to `location()` if
+    /// the error has no known extent.
+    pub fn end(&self) -> Location {
+        self.end
+    }
+
     /// Get the severity of this diagnostic.
     pub fn severity(&self) -> Severity {
         self.severity
@@ -394,6 +633,16 @@ impl DMError {
         self.component
     }
 
+    /// Whether this diagnostic points out code which has no effect.
+    pub fn is_unnecessary(&self) -> bool {
+        self.unnecessary
+    }
+
+    /// Get the suggested ways to fix this diagnostic.
+    pub fn fixes(&self) -> &[Fix] {
+        &self.fixes
+    }
+
     /// Get the description associated with this error.
     pub fn description(&self) -> &str {
         &self.description
@@ -430,11 +679,14 @@ impl Clone for DMError {
     fn clone(&self) -> DMError {
         DMError {
             location: self.location,
+            end: self.end,
             severity: self.severity,
             component: self.component,
             description: self.description.clone(),
             notes: self.notes.clone(),
-            cause: None,  // not trivially cloneable
+            unnecessary: self.unnecessary,
+            fixes: self.fixes.clone(),
+            cause: self.cause.clone(),
         }
     }
 }
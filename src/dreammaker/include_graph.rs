@@ -0,0 +1,183 @@
+//! The graph of which files `#include` which, as followed by the preprocessor.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+
+use super::{Context, FileId, Location};
+
+/// The kind of file named by an `#include`, by its extension.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IncludeKind {
+    /// A `.dm` or `.dme` file, which is preprocessed in place.
+    Code,
+    /// A `.dmm` map.
+    Map,
+    /// A `.dmf` interface file.
+    Interface,
+    /// A `.dms` script.
+    Script,
+}
+
+impl IncludeKind {
+    pub fn from_extension(extension: &str) -> Option<IncludeKind> {
+        match extension {
+            "dm" | "dme" => Some(IncludeKind::Code),
+            "dmm" => Some(IncludeKind::Map),
+            "dmf" => Some(IncludeKind::Interface),
+            "dms" => Some(IncludeKind::Script),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IncludeKind::Code => "code",
+            IncludeKind::Map => "map",
+            IncludeKind::Interface => "interface",
+            IncludeKind::Script => "script",
+        }
+    }
+}
+
+/// A single `#include` which the preprocessor followed.
+#[derive(Debug, Clone)]
+pub struct Inclusion {
+    /// The included file, relative to the environment's directory.
+    pub path: PathBuf,
+    pub kind: IncludeKind,
+    /// The location of the `#include` directive.
+    pub location: Location,
+    /// The file ID of the included file, for code files.
+    pub file: Option<FileId>,
+}
+
+/// Every `#include` followed while preprocessing an environment, in order.
+#[derive(Debug, Clone)]
+pub struct IncludeGraph {
+    env_file: PathBuf,
+    root: FileId,
+    inclusions: Vec<Inclusion>,
+}
+
+impl IncludeGraph {
+    pub(crate) fn new(env_file: PathBuf, root: FileId) -> IncludeGraph {
+        IncludeGraph {
+            env_file,
+            root,
+            inclusions: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, inclusion: Inclusion) {
+        self.inclusions.push(inclusion);
+    }
+
+    /// The environment file at the root of the graph.
+    pub fn environment(&self) -> &Path {
+        &self.env_file
+    }
+
+    /// The file ID of the environment file.
+    pub fn root(&self) -> FileId {
+        self.root
+    }
+
+    /// All inclusions, in the order they were followed.
+    pub fn inclusions(&self) -> &[Inclusion] {
+        &self.inclusions
+    }
+
+    /// The inclusions made directly by the given file.
+    pub fn includes_from(&self, file: FileId) -> impl Iterator<Item=&Inclusion> {
+        self.inclusions.iter().filter(move |inclusion| inclusion.location.file == file)
+    }
+
+    /// Find how the given file, relative to the environment's directory, was
+    /// included, if it was.
+    pub fn find(&self, path: &Path) -> Option<&Inclusion> {
+        self.inclusions.iter().find(|inclusion| inclusion.path == path)
+    }
+
+    /// The directory the environment file is in, which include paths are
+    /// relative to.
+    pub fn environment_dir(&self) -> &Path {
+        match self.env_file.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        }
+    }
+
+    /// Filter the given files, relative to the environment's directory, to
+    /// the `.dm` and `.dmm` files which were never included, in DreamMaker's
+    /// order.
+    pub fn unincluded_files<I: IntoIterator<Item=PathBuf>>(&self, files: I) -> Vec<PathBuf> {
+        let included: HashSet<&Path> = self.inclusions.iter().map(|inclusion| inclusion.path.as_path()).collect();
+        let mut result: Vec<PathBuf> = files.into_iter()
+            .filter(|path| match path.extension().and_then(|ext| ext.to_str()) {
+                Some("dm") | Some("dmm") => true,
+                _ => false,
+            })
+            .filter(|path| !included.contains(path.as_path()))
+            .collect();
+        result.sort_by(|a, b| dreammaker_order(a, b));
+        result
+    }
+
+    /// Find the `#include`s in the environment file which are out of the
+    /// order DreamMaker keeps them in. Each pair is an inclusion and the one
+    /// before it, which should have come after it.
+    pub fn misordered_includes(&self) -> Vec<(&Inclusion, &Inclusion)> {
+        let mut result = Vec::new();
+        let mut previous: Option<&Inclusion> = None;
+        for inclusion in self.includes_from(self.root) {
+            if let Some(prev) = previous {
+                if dreammaker_order(&prev.path, &inclusion.path) == Ordering::Greater {
+                    result.push((prev, inclusion));
+                }
+            }
+            previous = Some(inclusion);
+        }
+        result
+    }
+
+    /// Write the graph in Graphviz DOT format. Non-code files are drawn as
+    /// boxes.
+    pub fn write_dot<W: Write>(&self, context: &Context, mut w: W) -> io::Result<()> {
+        writeln!(w, "digraph includes {{")?;
+        for inclusion in self.inclusions.iter() {
+            let from = context.file_path(inclusion.location.file);
+            let to = inclusion.path.display().to_string();
+            if inclusion.kind != IncludeKind::Code {
+                writeln!(w, "    {:?} [shape=box];", to)?;
+            }
+            writeln!(w, "    {:?} -> {:?};", from.display().to_string(), to)?;
+        }
+        writeln!(w, "}}")
+    }
+}
+
+/// Compare two paths in the order DreamMaker sorts `#include`s in the
+/// environment file: case-insensitively, with the files in a directory
+/// before its subdirectories.
+pub fn dreammaker_order(a: &Path, b: &Path) -> Ordering {
+    fn lower(path: &Path) -> Vec<String> {
+        path.components().map(|c| c.as_os_str().to_string_lossy().to_lowercase()).collect()
+    }
+    let (a, b) = (lower(a), lower(b));
+    for (i, (a_part, b_part)) in a.iter().zip(b.iter()).enumerate() {
+        if a_part == b_part {
+            continue;
+        }
+        let a_is_file = i + 1 == a.len();
+        let b_is_file = i + 1 == b.len();
+        return match (a_is_file, b_is_file) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            _ => a_part.cmp(b_part),
+        };
+    }
+    a.len().cmp(&b.len())
+}
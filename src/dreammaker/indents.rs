@@ -12,6 +12,7 @@ pub struct IndentProcessor<'ctx, I> {
     inner: I,
 
     last_input_loc: Location,
+    last_input_end: Location,
     eol_location: Option<Location>,
     output: VecDeque<LocatedToken>,
 
@@ -31,6 +32,7 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
             context,
             inner: inner.into_iter(),
             last_input_loc: Location::default(),
+            last_input_end: Location::default(),
             eol_location: None,
             output: VecDeque::new(),
             current: None,
@@ -181,7 +183,7 @@ impl<'ctx, I> IndentProcessor<'ctx, I> where
         }
 
         self.eol_location = None;
-        self.push(read);
+        self.output.push_back(LocatedToken::with_end(self.last_input_loc, self.last_input_end, read));
     }
 }
 
@@ -198,6 +200,7 @@ impl<'ctx, I> Iterator for IndentProcessor<'ctx, I> where
 
             if let Some(tok) = self.inner_next() {
                 self.last_input_loc = tok.location;
+                self.last_input_end = tok.end;
                 self.real_next(tok.token);
             } else if self.eof_yielded {
                 return None;
@@ -98,6 +98,7 @@ table! {
     "//",  LineComment;
     "/=",  DivAssign;
     ":",   Colon -> CloseColon;
+    "::",  ColonColon;
     ";",   Semicolon;
     "<",   Less;
     "<<",  LShift;
@@ -147,15 +148,15 @@ static SPEEDY_TABLE: [(usize, usize); 127] = [
     (2, 3), (3, 5), (5, 6), (6, 8), (0, 0), (8, 10), (10, 13), (13, 14),
     (14, 15), (15, 16), (16, 19), (19, 22), (22, 23), (23, 26), (26, 29), (29, 33),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (33, 34), (34, 35), (35, 40), (40, 42), (42, 46), (46, 49),
+    (0, 0), (0, 0), (33, 35), (35, 36), (36, 41), (41, 43), (43, 47), (47, 50),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (0, 0), (49, 50), (0, 0), (50, 51), (51, 53), (0, 0),
+    (0, 0), (0, 0), (0, 0), (50, 51), (0, 0), (51, 52), (52, 54), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
     (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0),
-    (0, 0), (0, 0), (0, 0), (53, 55), (55, 58), (58, 59), (59, 62)];
+    (0, 0), (0, 0), (0, 0), (54, 56), (56, 59), (59, 60), (60, 63)];
 
 #[test]
 fn make_speedy_table() {
@@ -410,13 +411,21 @@ impl fmt::Display for FormatFloat {
 #[derive(Clone, Debug, PartialEq)]
 pub struct LocatedToken {
     pub location: Location,
+    /// The location just past the end of the token's text. Equal to
+    /// `location` for tokens which do not appear in the source as written.
+    pub end: Location,
     pub token: Token,
 }
 
 impl LocatedToken {
     #[inline]
     pub fn new(location: Location, token: Token) -> LocatedToken {
-        LocatedToken { location, token }
+        LocatedToken { location, end: location, token }
+    }
+
+    #[inline]
+    pub fn with_end(location: Location, end: Location, token: Token) -> LocatedToken {
+        LocatedToken { location, end, token }
     }
 }
 
@@ -428,6 +437,16 @@ fn is_ident(ch: u8) -> bool {
     (ch >= b'a' && ch <= b'z') || (ch >= b'A' && ch <= b'Z') || ch == b'_'
 }
 
+/// The text of a line comment which suppresses diagnostics on its line.
+pub const SUPPRESS_COMMENT: &str = "SpacemanDMM_ignore";
+
+fn is_suppress_comment(text: &[u8]) -> bool {
+    let start = text.iter().position(|&ch| ch != b' ' && ch != b'\t').unwrap_or(text.len());
+    let text = &text[start..];
+    text.starts_with(SUPPRESS_COMMENT.as_bytes())
+        && text.get(SUPPRESS_COMMENT.len()).map_or(true, |&ch| !is_ident(ch) && !is_digit(ch))
+}
+
 fn from_latin1(bytes: &[u8]) -> String {
     let non_ascii = bytes.iter().filter(|&&i| i > 0x7f).count();
     let mut output = String::with_capacity(bytes.len() + non_ascii);
@@ -479,6 +498,8 @@ pub struct LocationTracker<I> {
     inner: I,
     /// The location of the last character returned by `next()`.
     location: Location,
+    /// The number of bytes returned by `next()`.
+    offset: usize,
     at_line_end: bool,
 }
 
@@ -491,6 +512,7 @@ impl<I> LocationTracker<I> {
                 line: 0,
                 column: 0,
             },
+            offset: 0,
             at_line_end: true,
         }
     }
@@ -526,6 +548,7 @@ impl<I: Iterator<Item=io::Result<u8>>> Iterator for LocationTracker<I> {
         match self.inner.next() {
             None => None,
             Some(Ok(ch)) => {
+                self.offset += 1;
                 if ch == b'\n' {
                     self.at_line_end = true;
                 }
@@ -629,6 +652,13 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
         self.next = val;
     }
 
+    /// The number of input bytes consumed so far, not counting lookahead.
+    ///
+    /// Immediately after a token is produced, this is the end of its text.
+    pub(crate) fn byte_offset(&self) -> usize {
+        self.input.offset - if self.next.is_some() { 1 } else { 0 }
+    }
+
     fn skip_block_comments(&mut self) -> Option<Token> {
         let mut depth = 1;
         let mut buffer = [0, 0];
@@ -681,6 +711,9 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
 
     fn skip_line_comment(&mut self) -> Option<Token> {
         let mut backslash = false;
+        let start = self.input.location;
+        // the start of an ordinary comment, to check for a suppression
+        let mut prefix = Vec::new();
 
         // read the first character and check for being a comment
         let mut comment = None;
@@ -692,13 +725,16 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
                 return None;
             }
             Some(b'\\') => backslash = true,
-            _ => {}
+            Some(ch) => prefix.push(ch),
+            None => {}
         }
 
         while let Some(ch) = self.next() {
             if ch != b'\r' && ch != b'\n' {
                 if let Some(ref mut comment) = comment {
                     comment.text.push(ch as char);
+                } else if prefix.len() < SUPPRESS_COMMENT.len() + 8 {
+                    prefix.push(ch);
                 }
             }
 
@@ -714,6 +750,9 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
             }
         }
 
+        if is_suppress_comment(&prefix) {
+            self.context.suppress_line(start.file, start.line);
+        }
         comment.map(Token::DocComment)
     }
 
@@ -1048,10 +1087,20 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
     }
 }
 
-impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
-    type Item = LocatedToken;
+impl<'ctx, I: Iterator<Item=io::Result<u8>>> Lexer<'ctx, I> {
+    /// The location just past the most recently read character, not counting
+    /// lookahead.
+    ///
+    /// Immediately after a token is produced, this is the end of its text.
+    fn end_location(&self) -> Location {
+        if self.next.is_some() {
+            self.input.location
+        } else {
+            self.input.location.add_columns(1)
+        }
+    }
 
-    fn next(&mut self) -> Option<LocatedToken> {
+    fn next_token(&mut self) -> Option<LocatedToken> {
         use self::Token::*;
         use self::Punctuation::*;
         let mut skip_newlines = false;
@@ -1065,10 +1114,7 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
                         self.final_newline = true;
                         let mut location = self.location();
                         location.column += 1;
-                        return Some(LocatedToken {
-                            location,
-                            token: Token::Punct(Punctuation::Newline),
-                        });
+                        return Some(LocatedToken::new(location, Token::Punct(Punctuation::Newline)));
                     } else {
                         return None;
                     }
@@ -1086,6 +1132,11 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
             }
 
             let mut punct = self.read_punct(first);
+            if punct == Some(ColonColon) && !self.context.byond_version().at_least(515) {
+                // Before 515, `::` is two colons, as in `a ? b::c`.
+                self.put_back(Some(b':'));
+                punct = Some(Colon);
+            }
             if self.close_allowed {
                 punct = punct.map(|p| self.check_close(p));
             }
@@ -1177,3 +1228,13 @@ impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
         }
     }
 }
+
+impl<'ctx, I: Iterator<Item=io::Result<u8>>> Iterator for Lexer<'ctx, I> {
+    type Item = LocatedToken;
+
+    fn next(&mut self) -> Option<LocatedToken> {
+        let mut token = self.next_token()?;
+        token.end = std::cmp::max(token.location, self.end_location());
+        Some(token)
+    }
+}
@@ -30,7 +30,9 @@ pub use error::*;
 pub mod docs;
 pub mod lexer;
 pub mod preprocessor;
+pub mod include_graph;
 pub mod indents;
+pub mod cst;
 pub mod parser;
 pub mod annotation;
 pub mod ast;
@@ -38,6 +40,7 @@ pub mod objtree;
 mod builtins;
 pub mod constants;
 pub mod dmi;
+pub mod multiconfig;
 
 impl Context {
     /// Run the parsing suite on a given `.dme` file, producing an object tree.
@@ -0,0 +1,160 @@
+//! Parsing an environment under several sets of defines, to find diagnostics
+//! which only occur in some of them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use super::{Context, DMError, Fix, Location, Severity, Component};
+use super::preprocessor::DefineOverride;
+
+/// A named set of define overrides to parse under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Configuration {
+    pub name: String,
+    pub defines: Vec<DefineOverride>,
+}
+
+impl Configuration {
+    /// Parse a comma-separated list of `NAME` or `NAME=VALUE` to define, or
+    /// `!NAME` to undefine. The configuration is named after the list.
+    pub fn parse(spec: &str) -> Configuration {
+        let defines = spec.split(',')
+            .map(str::trim)
+            .filter(|each| !each.is_empty())
+            .map(|each| match each.strip_prefix('!') {
+                Some(name) => DefineOverride::undefine(name),
+                None => DefineOverride::define(each),
+            })
+            .collect();
+        Configuration {
+            name: spec.to_owned(),
+            defines,
+        }
+    }
+}
+
+impl fmt::Display for Configuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.name.is_empty() {
+            f.write_str("(default)")
+        } else {
+            f.write_str(&self.name)
+        }
+    }
+}
+
+/// A diagnostic, identified independently of the context's file table.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    path: PathBuf,
+    line: u32,
+    column: u16,
+    severity: Severity,
+    component: Component,
+    description: String,
+}
+
+/// Run `analyze` once for each configuration, and register with `context`
+/// every diagnostic which was found under some configurations but not all of
+/// them. Returns the number of such diagnostics.
+///
+/// Diagnostics of unnecessary code, such as unused macros, are instead
+/// registered only if they were found under every configuration, and are not
+/// counted.
+///
+/// Each run is given a fresh context with the BYOND version of `context`,
+/// which prints nothing, and the overrides to preprocess under: `defines`
+/// followed by those of the configuration. Only the merged results are
+/// printed, as `context` registers them.
+pub fn diagnose_configurations<F>(
+    context: &Context,
+    defines: &[DefineOverride],
+    configurations: &[Configuration],
+    mut analyze: F,
+) -> usize
+where
+    F: FnMut(&Context, &[DefineOverride]),
+{
+    let mut found: Vec<(DMError, Vec<usize>)> = Vec::new();
+    let mut index: HashMap<Key, usize> = HashMap::new();
+
+    for (i, configuration) in configurations.iter().enumerate() {
+        let mut run_context = Context::default();
+        run_context.set_byond_version(context.byond_version());
+        let mut run_defines = defines.to_vec();
+        run_defines.extend(configuration.defines.iter().cloned());
+
+        analyze(&run_context, &run_defines);
+
+        for error in run_context.errors().iter() {
+            let key = Key {
+                path: run_context.file_path(error.location().file),
+                line: error.location().line,
+                column: error.location().column,
+                severity: error.severity(),
+                component: error.component(),
+                description: error.description().to_owned(),
+            };
+            if let Some(&idx) = index.get(&key) {
+                let seen_in = &mut found[idx].1;
+                if seen_in.last() != Some(&i) {
+                    seen_in.push(i);
+                }
+            } else {
+                let translated = translate(&run_context, context, error);
+                index.insert(key, found.len());
+                found.push((translated, vec![i]));
+            }
+        }
+    }
+
+    let mut count = 0;
+    for (mut error, seen_in) in found {
+        if error.is_unnecessary() {
+            // Code is only unnecessary if it is so in every configuration,
+            // such as a condition which has the same value in all of them.
+            if seen_in.len() == configurations.len() {
+                context.register_error(error);
+            }
+            continue;
+        }
+        if seen_in.len() == configurations.len() {
+            continue;
+        }
+        let names: Vec<String> = seen_in.iter().map(|&i| configurations[i].to_string()).collect();
+        let location = error.location();
+        error.add_note(location, format!("only in configurations: {}", names.join("; ")));
+        context.register_error(error);
+        count += 1;
+    }
+    count
+}
+
+/// Copy a diagnostic from one context to another, re-registering the files it
+/// refers to.
+fn translate(from: &Context, to: &Context, error: &DMError) -> DMError {
+    let location = |loc: Location| if loc.is_builtins() {
+        loc
+    } else {
+        Location { file: to.register_file(&from.file_path(loc.file)), ..loc }
+    };
+    let mut result = DMError::new(location(error.location()), error.description())
+        .set_severity(error.severity())
+        .with_component(error.component())
+        .with_end(location(error.end()));
+    if error.is_unnecessary() {
+        result = result.set_unnecessary();
+    }
+    for note in error.notes() {
+        result.add_note(location(note.location()), note.description());
+    }
+    for fix in error.fixes() {
+        result.add_fix(match *fix {
+            Fix::AddParameters(loc, ref names) => Fix::AddParameters(location(loc), names.clone()),
+            Fix::SetVarType(loc, ref name, ref path) => Fix::SetVarType(location(loc), name.clone(), path.clone()),
+            ref other => other.clone(),
+        });
+    }
+    result
+}
@@ -12,7 +12,7 @@ use linked_hash_map::LinkedHashMap;
 use super::ast::{Expression, VarType, VarSuffix, PathOp, Parameter, Block, ProcDeclKind};
 use super::constants::{Constant, Pop};
 use super::docs::DocCollection;
-use super::{DMError, Location, Context};
+use super::{DMError, Location, Context, ByondVersion};
 
 // ----------------------------------------------------------------------------
 // Symbol IDs
@@ -614,6 +614,254 @@ impl<'a> std::hash::Hash for ProcRef<'a> {
     }
 }
 
+// ----------------------------------------------------------------------------
+// Queries
+
+/// A comparison operator used in a var filter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// Compare two constants. Numbers are compared numerically and strings
+    /// lexicographically; other values only support `=` and `!=`.
+    pub fn test(self, lhs: &Constant, rhs: &Constant) -> bool {
+        use std::cmp::Ordering;
+
+        let ordering = match (lhs, rhs) {
+            (Constant::String(a), Constant::String(b)) => Some(a.cmp(b)),
+            _ if lhs.is_null() && rhs.is_null() => Some(Ordering::Equal),
+            _ => match (lhs.to_float(), rhs.to_float()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => None,
+            },
+        };
+        match (self, ordering) {
+            (Comparison::Eq, None) => lhs == rhs,
+            (Comparison::Ne, None) => lhs != rhs,
+            (_, None) => false,
+            (Comparison::Eq, Some(o)) => o == Ordering::Equal,
+            (Comparison::Ne, Some(o)) => o != Ordering::Equal,
+            (Comparison::Lt, Some(o)) => o == Ordering::Less,
+            (Comparison::Le, Some(o)) => o != Ordering::Greater,
+            (Comparison::Gt, Some(o)) => o == Ordering::Greater,
+            (Comparison::Ge, Some(o)) => o != Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        })
+    }
+}
+
+/// A single condition in a `TypeQuery`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeFilter {
+    /// The type is the given type or one of its subtypes.
+    SubtypeOf(String),
+    /// The type's path contains the given text.
+    PathContains(String),
+    /// The type has the given var, declared on it or a parent.
+    HasVar(String),
+    /// The type's value for the given var compares true against a constant.
+    Var(String, Comparison, Constant),
+    /// The type has the given proc, declared on it or a parent.
+    HasProc(String),
+    /// The type itself defines or overrides the given proc.
+    DefinesProc(String),
+    /// The inner filter does not match.
+    Not(Box<TypeFilter>),
+}
+
+impl TypeFilter {
+    pub fn matches(&self, ty: TypeRef) -> bool {
+        match *self {
+            TypeFilter::SubtypeOf(ref path) => match ty.tree().find(path) {
+                Some(parent) => ty.is_subtype_of(&parent),
+                None => false,
+            },
+            TypeFilter::PathContains(ref text) => ty.path.contains(text.as_str()),
+            TypeFilter::HasVar(ref name) => ty.get_value(name).is_some(),
+            TypeFilter::Var(ref name, op, ref value) => match ty.get_value(name) {
+                Some(&VarValue { constant: Some(ref constant), .. }) => op.test(constant, value),
+                _ => false,
+            },
+            TypeFilter::HasProc(ref name) => ty.get_proc(name).is_some(),
+            TypeFilter::DefinesProc(ref name) => match ty.procs.get(name) {
+                Some(type_proc) => !type_proc.value.is_empty(),
+                None => false,
+            },
+            TypeFilter::Not(ref inner) => !inner.matches(ty),
+        }
+    }
+
+    fn var_name(&self) -> Option<&str> {
+        match *self {
+            TypeFilter::HasVar(ref name) | TypeFilter::Var(ref name, _, _) => Some(name),
+            TypeFilter::Not(ref inner) => inner.var_name(),
+            _ => None,
+        }
+    }
+
+    fn parse(clause: &str) -> Result<TypeFilter, String> {
+        if let Some(rest) = clause.strip_prefix('!') {
+            return Ok(TypeFilter::Not(Box::new(TypeFilter::parse(rest)?)));
+        } else if clause.starts_with('/') {
+            return Ok(TypeFilter::SubtypeOf(clause.trim_end_matches('/').to_owned()));
+        }
+
+        if let Some(colon) = clause.find(':') {
+            let name = clause[colon + 1..].to_owned();
+            match &clause[..colon] {
+                _ if name.is_empty() => {},
+                "path" => return Ok(TypeFilter::PathContains(name)),
+                "var" => return Ok(TypeFilter::HasVar(name)),
+                "proc" => return Ok(TypeFilter::HasProc(name)),
+                "defines" => return Ok(TypeFilter::DefinesProc(name)),
+                _ => {},
+            }
+        }
+
+        let split = match clause.find(|c| "=!<>".contains(c)) {
+            Some(split) if split > 0 => split,
+            _ => return Err(format!("bad query clause: {:?}", clause)),
+        };
+        let (name, rest) = clause.split_at(split);
+        let operators = [
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("=", Comparison::Eq),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ];
+        let &(op_text, op) = operators.iter()
+            .find(|&&(text, _)| rest.starts_with(text))
+            .ok_or_else(|| format!("bad operator in query clause: {:?}", clause))?;
+        let value = &rest[op_text.len()..];
+        // Values are DM expressions, but bare words are taken as text so
+        // they need not be quoted on the command line.
+        let value = match super::constants::evaluate_str(Location::builtins(), value.as_bytes()) {
+            Ok(constant) => constant,
+            Err(_) => Constant::String(value.to_owned()),
+        };
+        Ok(TypeFilter::Var(name.to_owned(), op, value))
+    }
+}
+
+/// A set of conditions which types in the object tree must all satisfy.
+///
+/// Queries may be built up with the builder methods or parsed from a string
+/// of whitespace-separated clauses:
+///
+/// * `/obj/item` - the type or one of its subtypes.
+/// * `path:text` - the type's path contains `text`.
+/// * `var:name` - the type has a var `name`.
+/// * `name=value` - the var `name` compares against `value` using one of
+///   `=`, `!=`, `<`, `<=`, `>`, or `>=`.
+/// * `proc:name` - the type has a proc `name`.
+/// * `defines:name` - the type itself defines or overrides proc `name`.
+///
+/// Any clause may be prefixed with `!` to negate it. Values containing spaces
+/// may be written in double quotes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeQuery {
+    pub filters: Vec<TypeFilter>,
+}
+
+impl TypeQuery {
+    pub fn new() -> TypeQuery {
+        TypeQuery::default()
+    }
+
+    pub fn filter(mut self, filter: TypeFilter) -> TypeQuery {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn subtype_of(self, path: &str) -> TypeQuery {
+        self.filter(TypeFilter::SubtypeOf(path.to_owned()))
+    }
+
+    pub fn path_contains(self, text: &str) -> TypeQuery {
+        self.filter(TypeFilter::PathContains(text.to_owned()))
+    }
+
+    pub fn has_var(self, name: &str) -> TypeQuery {
+        self.filter(TypeFilter::HasVar(name.to_owned()))
+    }
+
+    pub fn var<C: Into<Constant>>(self, name: &str, op: Comparison, value: C) -> TypeQuery {
+        self.filter(TypeFilter::Var(name.to_owned(), op, value.into()))
+    }
+
+    pub fn has_proc(self, name: &str) -> TypeQuery {
+        self.filter(TypeFilter::HasProc(name.to_owned()))
+    }
+
+    pub fn defines_proc(self, name: &str) -> TypeQuery {
+        self.filter(TypeFilter::DefinesProc(name.to_owned()))
+    }
+
+    /// Check whether the given type satisfies every filter.
+    pub fn matches(&self, ty: TypeRef) -> bool {
+        self.filters.iter().all(|filter| filter.matches(ty))
+    }
+
+    /// The names of all vars mentioned by this query, in order.
+    pub fn var_names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for name in self.filters.iter().filter_map(TypeFilter::var_name) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+impl std::str::FromStr for TypeQuery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TypeQuery, String> {
+        let mut query = TypeQuery::new();
+        let mut clause = String::new();
+        let mut quoted = false;
+        for ch in s.chars().chain(std::iter::once(' ')) {
+            if ch == '"' {
+                quoted = !quoted;
+                clause.push(ch);
+            } else if ch.is_whitespace() && !quoted {
+                if !clause.is_empty() {
+                    query.filters.push(TypeFilter::parse(&clause)?);
+                    clause.clear();
+                }
+            } else {
+                clause.push(ch);
+            }
+        }
+        if quoted {
+            return Err("unterminated quote in query".to_owned());
+        }
+        Ok(query)
+    }
+}
+
 // ----------------------------------------------------------------------------
 // The object tree itself
 
@@ -648,13 +896,17 @@ impl Default for ObjectTree {
 
 impl ObjectTree {
     pub fn with_builtins() -> ObjectTree {
+        ObjectTree::with_builtins_for(Default::default())
+    }
+
+    pub fn with_builtins_for(version: ByondVersion) -> ObjectTree {
         let mut objtree = ObjectTree::default();
-        objtree.register_builtins();
+        objtree.register_builtins(version);
         objtree
     }
 
-    pub(crate) fn register_builtins(&mut self) {
-        super::builtins::register_builtins(self).expect("register_builtins failed");
+    pub(crate) fn register_builtins(&mut self, version: ByondVersion) {
+        super::builtins::register_builtins(self, version).expect("register_builtins failed");
     }
 
     // ------------------------------------------------------------------------
@@ -722,6 +974,13 @@ impl ObjectTree {
         (true, TypeRef::new(self, current))
     }
 
+    /// Find all types matching the given query, in path order.
+    pub fn query<'a>(&'a self, query: &'a TypeQuery) -> impl Iterator<Item=TypeRef<'a>> + 'a {
+        self.types.values()
+            .map(move |&ix| TypeRef::new(self, ix))
+            .filter(move |&ty| query.matches(ty))
+    }
+
     pub fn type_by_constant(&self, constant: &Constant) -> Option<TypeRef> {
         match *constant {
             Constant::String(ref string_path) => self.find(string_path),
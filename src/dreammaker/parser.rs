@@ -359,6 +359,12 @@ pub struct Parser<'ctx, 'an, 'inp> {
     eof: bool,
     next: Option<Token>,
     location: Location,
+    /// The end of the token at `location`.
+    location_end: Location,
+    /// The end of the last token consumed and not put back.
+    consumed_end: Location,
+    /// The value of `consumed_end` before the most recent token was read.
+    previous_end: Location,
     expected: Vec<Cow<'static, str>>,
 
     docs_following: DocCollection,
@@ -390,6 +396,9 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             eof: false,
             next: None,
             location: Default::default(),
+            location_end: Default::default(),
+            consumed_end: Default::default(),
+            previous_end: Default::default(),
             expected: Vec::new(),
 
             docs_following: Default::default(),
@@ -413,13 +422,13 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
     }
 
     pub fn parse_object_tree(mut self) -> ObjectTree {
-        self.tree.register_builtins();
+        self.tree.register_builtins(self.context.byond_version());
         self.run();
         self.finalize_object_tree()
     }
 
     pub fn parse_with_module_docs(mut self) -> (ObjectTree, BTreeMap<FileId, Vec<(u32, DocComment)>>) {
-        self.tree.register_builtins();
+        self.tree.register_builtins(self.context.byond_version());
         self.run();
         let docs = std::mem::replace(&mut self.module_docs, Default::default());
         (self.finalize_object_tree(), docs)
@@ -493,12 +502,14 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
     fn next<S: Into<Cow<'static, str>>>(&mut self, expected: S) -> Result<Token, DMError> {
         let tok = loop {
             if let Some(next) = self.next.take() {
+                self.consumed_end = self.location_end;
                 break Ok(next);
             }
             match self.input.next() {
                 Some(LocatedToken {
                     location,
                     token: Token::DocComment(dc),
+                    ..
                 }) => match dc.target {
                     DocTarget::EnclosingItem if self.in_docs == 0 => {
                         self.module_docs
@@ -512,11 +523,15 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 Some(token) => {
                     self.expected.clear();
                     self.location = token.location;
+                    self.location_end = token.end;
+                    self.previous_end = self.consumed_end;
+                    self.consumed_end = token.end;
                     break Ok(token.token);
                 }
                 None => {
                     if !self.eof {
                         self.eof = true;
+                        self.previous_end = self.consumed_end;
                         break Ok(Token::Eof);
                     } else {
                         break self.parse_error();
@@ -535,6 +550,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         if self.next.is_some() {
             panic!("cannot put_back twice")
         }
+        self.consumed_end = self.previous_end;
         self.next = Some(tok);
     }
 
@@ -1065,9 +1081,22 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                     break;
                 } else if let Some(()) = self.exact(Token::Punct(Punctuation::Semicolon))? {
                     continue;
-                } else {
-                    statements.push(require!(self.statement(loop_ctx, &mut vars)));
                 }
+
+                // on a syntax error, skip to the next statement rather than
+                // discarding the whole proc
+                let start = self.updated_location();
+                let error = match self.statement(loop_ctx, &mut vars) {
+                    Ok(Some(statement)) => {
+                        statements.push(statement);
+                        continue;
+                    }
+                    Ok(None) => self.describe_parse_error(),
+                    Err(e) => e,
+                };
+                self.context.register_error(error);
+                require!(self.skip_statement());
+                statements.push(Spanned::with_end(start, self.consumed_end, Statement::Invalid));
             }
             statements
         } else if let Some(()) = self.statement_terminator()? {
@@ -1085,6 +1114,12 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
     }
 
     fn statement(&mut self, loop_ctx: &LoopContext, vars: &mut Vec<(Location, VarType, String)>) -> Status<Spanned<Statement>> {
+        let mut statement = leading!(self.statement_inner(loop_ctx, vars));
+        statement.end = self.consumed_end;
+        success(statement)
+    }
+
+    fn statement_inner(&mut self, loop_ctx: &LoopContext, vars: &mut Vec<(Location, VarType, String)>) -> Status<Spanned<Statement>> {
         let start = self.location();
         let spanned = |v| success(Spanned::new(start, v));
 
@@ -1706,17 +1741,18 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         // read unary ops
         let mut unary_ops = Vec::new();
         loop {
-            match self.next("operator")? {
-                Token::Punct(Punctuation::Sub) => unary_ops.push(UnaryOp::Neg),
-                Token::Punct(Punctuation::Not) => unary_ops.push(UnaryOp::Not),
-                Token::Punct(Punctuation::BitNot) => unary_ops.push(UnaryOp::BitNot),
-                Token::Punct(Punctuation::PlusPlus) => unary_ops.push(UnaryOp::PreIncr),
-                Token::Punct(Punctuation::MinusMinus) => unary_ops.push(UnaryOp::PreDecr),
+            let op = match self.next("operator")? {
+                Token::Punct(Punctuation::Sub) => UnaryOp::Neg,
+                Token::Punct(Punctuation::Not) => UnaryOp::Not,
+                Token::Punct(Punctuation::BitNot) => UnaryOp::BitNot,
+                Token::Punct(Punctuation::PlusPlus) => UnaryOp::PreIncr,
+                Token::Punct(Punctuation::MinusMinus) => UnaryOp::PreDecr,
                 other => {
                     self.put_back(other);
                     break;
                 }
-            }
+            };
+            unary_ops.push(Spanned::with_end(self.location, self.location_end, op));
         }
 
         let mut belongs_to = Vec::new();
@@ -1730,8 +1766,12 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         let mut follow = Vec::new();
         loop {
             match self.next("operator")? {
-                Token::Punct(Punctuation::PlusPlus) => unary_ops.push(UnaryOp::PostIncr),
-                Token::Punct(Punctuation::MinusMinus) => unary_ops.push(UnaryOp::PostDecr),
+                Token::Punct(Punctuation::PlusPlus) => {
+                    unary_ops.push(Spanned::with_end(self.location, self.location_end, UnaryOp::PostIncr));
+                }
+                Token::Punct(Punctuation::MinusMinus) => {
+                    unary_ops.push(Spanned::with_end(self.location, self.location_end, UnaryOp::PostDecr));
+                }
                 other => {
                     self.put_back(other);
                     match self.follow(&mut belongs_to, in_ternary)? {
@@ -1961,7 +2001,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
 
             other => return self.try_another(other),
         };
-        success(Spanned::new(start, term))
+        success(Spanned::with_end(start, self.consumed_end, term))
     }
 
     fn follow(&mut self, belongs_to: &mut Vec<String>, in_ternary: bool) -> Status<Spanned<Follow>> {
@@ -1972,7 +2012,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 belongs_to.clear();
                 let expr = require!(self.expression());
                 require!(self.exact(Token::Punct(Punctuation::RBracket)));
-                return success(Spanned::new(first_location, Follow::Index(Box::new(expr))))
+                return success(Spanned::with_end(first_location, self.consumed_end, Follow::Index(Box::new(expr))))
             }
 
             // follow :: '.' ident arglist?
@@ -1981,9 +2021,11 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             Token::Punct(Punctuation::CloseColon) if !belongs_to.is_empty() || !in_ternary => IndexKind::Colon,
             Token::Punct(Punctuation::SafeDot) => IndexKind::SafeDot,
             Token::Punct(Punctuation::SafeColon) => IndexKind::SafeColon,
+            Token::Punct(Punctuation::ColonColon) => IndexKind::Scope,
 
             other => return self.try_another(other),
         };
+        self.check_index_kind(kind);
 
         let mut index_op_loc = self.location;
         let start = self.updated_location();
@@ -2017,7 +2059,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
                 Follow::Field(kind, ident)
             },
         };
-        success(Spanned::new(first_location, follow))
+        success(Spanned::with_end(first_location, self.consumed_end, follow))
     }
 
     // TODO: somehow fix the fact that this is basically copy-pasted from
@@ -2038,9 +2080,11 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
             Token::Punct(Punctuation::CloseColon) if !belongs_to.is_empty() || !in_ternary => IndexKind::Colon,
             Token::Punct(Punctuation::SafeDot) => IndexKind::SafeDot,
             Token::Punct(Punctuation::SafeColon) => IndexKind::SafeColon,
+            Token::Punct(Punctuation::ColonColon) => IndexKind::Scope,
 
             other => return self.try_another(other),
         };
+        self.check_index_kind(kind);
 
         let mut index_op_loc = self.location;
         let start = self.updated_location();
@@ -2065,6 +2109,20 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         success(IndexOrField::Field(kind, ident))
     }
 
+    /// Reject field access operators newer than the targeted BYOND version.
+    fn check_index_kind(&self, kind: IndexKind) {
+        let required = match kind {
+            IndexKind::SafeDot | IndexKind::SafeColon => 513,
+            IndexKind::Scope => 515,
+            IndexKind::Dot | IndexKind::Colon => return,
+        };
+        let version = self.context.byond_version();
+        if !version.at_least(required) {
+            self.context.register_error(DMError::new(self.location, format!(
+                "'{}' requires BYOND {} or later, but targeting {}", kind, required, version.version)));
+        }
+    }
+
     /// a parenthesized, comma-separated list of expressions
     fn arguments(&mut self, parents: &[String], proc: &str) -> Status<Vec<Expression>> {
         leading!(self.exact(Token::Punct(Punctuation::LParen)));
@@ -2146,11 +2204,34 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
     // ------------------------------------------------------------------------
     // Procs
 
+    /// Skip the remainder of a malformed statement, stopping after the next
+    /// terminator or before the `}` which closes the current block.
+    fn skip_statement(&mut self) -> Status<()> {
+        let mut depth = 0usize;
+        loop {
+            match self.next("")? {
+                Token::Eof => return self.try_another(Token::Eof),
+                Token::Punct(Punctuation::Semicolon) if depth == 0 => return SUCCESS,
+                Token::Punct(Punctuation::RBrace) if depth == 0 => {
+                    self.put_back(Token::Punct(Punctuation::RBrace));
+                    return SUCCESS;
+                }
+                Token::Punct(Punctuation::LBrace) |
+                Token::Punct(Punctuation::LParen) |
+                Token::Punct(Punctuation::LBracket) => depth += 1,
+                Token::Punct(Punctuation::RBrace) |
+                Token::Punct(Punctuation::RParen) |
+                Token::Punct(Punctuation::RBracket) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+
     fn read_any_tt(&mut self, target: &mut Vec<LocatedToken>) -> Status<()> {
         // read a single arbitrary "token tree", either a group or a single token
         let start = self.next("anything")?;
         let kind = TTKind::from_token(&start);
-        target.push(LocatedToken::new(self.location(), start));
+        target.push(LocatedToken::with_end(self.location, self.location_end, start));
         let kind = match kind {
             Some(k) => k,
             None => return SUCCESS,
@@ -2158,7 +2239,7 @@ impl<'ctx, 'an, 'inp> Parser<'ctx, 'an, 'inp> {
         loop {
             let token = self.next("anything")?;
             if kind.is_end(&token) {
-                target.push(LocatedToken::new(self.location(), token));
+                target.push(LocatedToken::with_end(self.location, self.location_end, token));
                 return SUCCESS;
             } else {
                 self.put_back(token);
@@ -1,5 +1,5 @@
 //! The preprocessor.
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::{io, fmt};
 use std::fs::File;
 use std::path::{Path, PathBuf};
@@ -7,14 +7,40 @@ use std::borrow::Cow;
 
 use interval_tree::{IntervalTree, range};
 
-use super::{DMError, Location, HasLocation, FileId, Context, Severity};
+use super::{DMError, Location, HasLocation, FileId, Context, Severity, ByondVersion};
 use super::lexer::*;
 use super::docs::{DocComment, DocTarget, DocCollection};
 use super::annotation::*;
+use super::include_graph::{IncludeGraph, IncludeKind, Inclusion};
 
 /// The maximum recursion depth of macro expansion.
 const MAX_RECURSION_DEPTH: usize = 32;
 
+/// The names accepted by `#pragma NAME warning|error|disabled`.
+///
+/// Only some of these affect diagnostics we actually produce; the rest are
+/// accepted so that code targeting newer BYOND versions is not flagged.
+pub const KNOWN_PRAGMAS: &[&str] = &[
+    "FileAlreadyIncluded",
+    "MissingIncludedFile",
+    "MisplacedDirective",
+    "UndefineMissingDirective",
+    "DefinedMacro",
+    "EmptyBlock",
+    "EmptyProc",
+    "SoftReservedKeyword",
+    "AmbiguousResourcePath",
+    "SuspiciousMatrixCall",
+    "FallbackBuiltinArgument",
+    "PointlessParentCall",
+    "PointlessBuiltinCall",
+    "SuspiciousSemicolon",
+    "AssignmentInConditional",
+    "UnusedMacro",
+    "UndefineUnusedMacro",
+    "ConstantCondition",
+];
+
 // ----------------------------------------------------------------------------
 // Macro representation and predefined macros
 
@@ -90,9 +116,11 @@ impl DefineHistory {
             maps: Default::default(),
             skins: Default::default(),
             scripts: Default::default(),
+            include_graph: IncludeGraph::new(self.env_file.clone(), context.register_file(&self.env_file)),
             ifdef_stack: Default::default(),  // should be fine
             ifdef_history: Default::default(),
             last_input_loc: location,
+            last_input_end: location,
             last_printable_input_loc: location,
             output: Default::default(),
             danger_idents: Default::default(),
@@ -100,6 +128,13 @@ impl DefineHistory {
             docs_out: Default::default(),
             in_interp_string: 0,
             annotations: None,
+            expansions: None,
+            pragmas: Default::default(),
+            defined_macros: None,
+            used_macros: Default::default(),
+            macro_uses: 0,
+            condition_values: Default::default(),
+            lint_macro_conditions: false,
         }
     }
 
@@ -115,9 +150,11 @@ impl DefineHistory {
             maps: Default::default(),
             skins: Default::default(),
             scripts: Default::default(),
+            include_graph: IncludeGraph::new(self.env_file.clone(), context.register_file(&self.env_file)),
             ifdef_stack: Default::default(),  // should be fine
             ifdef_history: Default::default(),
             last_input_loc: self.last_input_loc,
+            last_input_end: self.last_input_loc,
             last_printable_input_loc: self.last_input_loc,
             output: Default::default(),
             danger_idents: Default::default(),
@@ -125,6 +162,13 @@ impl DefineHistory {
             docs_out: Default::default(),
             in_interp_string: 0,
             annotations: None,
+            expansions: None,
+            pragmas: Default::default(),
+            defined_macros: None,
+            used_macros: Default::default(),
+            macro_uses: 0,
+            condition_values: Default::default(),
+            lint_macro_conditions: false,
         }
     }
 }
@@ -143,6 +187,76 @@ impl std::ops::DerefMut for DefineHistory {
     }
 }
 
+/// A define set or removed before preprocessing begins, as with `-D` or `-U`
+/// on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DefineOverride {
+    Define {
+        name: String,
+        value: String,
+    },
+    Undefine(String),
+}
+
+impl DefineOverride {
+    /// Parse the argument to `-D`, either `NAME` or `NAME=VALUE`.
+    pub fn define(arg: &str) -> DefineOverride {
+        match arg.find('=') {
+            Some(idx) => DefineOverride::Define {
+                name: arg[..idx].to_owned(),
+                value: arg[idx + 1..].to_owned(),
+            },
+            None => DefineOverride::Define {
+                name: arg.to_owned(),
+                value: String::new(),
+            },
+        }
+    }
+
+    /// Parse the argument to `-U`.
+    pub fn undefine(arg: &str) -> DefineOverride {
+        DefineOverride::Undefine(arg.to_owned())
+    }
+
+    /// Parse a `-D` or `-U` command-line argument, taking its value from
+    /// `rest` when it is not attached, as in `-D NAME`. Returns `None` if
+    /// `arg` is neither.
+    pub fn parse_arg<I: Iterator<Item=String>>(arg: &str, rest: &mut I) -> Option<Result<DefineOverride, String>> {
+        let parse: fn(&str) -> DefineOverride = if arg.starts_with("-D") {
+            DefineOverride::define
+        } else if arg.starts_with("-U") {
+            DefineOverride::undefine
+        } else {
+            return None;
+        };
+        Some(match &arg[2..] {
+            "" => match rest.next() {
+                Some(value) => Ok(parse(&value)),
+                None => Err(format!("must specify a value for {}", arg)),
+            },
+            value => Ok(parse(value)),
+        })
+    }
+
+    /// The name of the define being set or removed.
+    pub fn name(&self) -> &str {
+        match self {
+            DefineOverride::Define { name, .. } => name,
+            DefineOverride::Undefine(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for DefineOverride {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DefineOverride::Define { name, value } if value.is_empty() => write!(f, "-D{}", name),
+            DefineOverride::Define { name, value } => write!(f, "-D{}={}", name, value),
+            DefineOverride::Undefine(name) => write!(f, "-U{}", name),
+        }
+    }
+}
+
 /// A map from macro names to their locations and definitions.
 ///
 /// Redefinitions of macros push to a stack, and undefining the macro returns
@@ -155,11 +269,40 @@ pub struct DefineMap {
 
 impl DefineMap {
     pub fn with_builtins() -> DefineMap {
+        DefineMap::with_builtins_for(ByondVersion::default())
+    }
+
+    /// Create a DefineMap holding the builtins of the given BYOND version.
+    pub fn with_builtins_for(version: ByondVersion) -> DefineMap {
         let mut this = Default::default();
-        super::builtins::default_defines(&mut this);
+        super::builtins::default_defines(&mut this, version);
         this
     }
 
+    /// Apply defines set or removed before preprocessing, in order.
+    pub fn apply_overrides(&mut self, context: &Context, overrides: &[DefineOverride]) {
+        for each in overrides {
+            match each {
+                DefineOverride::Define { name, value } => {
+                    let location = Location::builtins();
+                    let subst = Lexer::new(context, location.file, value.as_bytes().iter().map(|&b| Ok(b)))
+                        .map(|token| token.token)
+                        .filter(|token| match token {
+                            Token::Punct(Punctuation::Newline) |
+                            Token::Punct(Punctuation::Space) |
+                            Token::Punct(Punctuation::Tab) => false,
+                            _ => true,
+                        })
+                        .collect();
+                    self.insert(name.clone(), (location, Define::Constant { subst, docs: Default::default() }));
+                }
+                DefineOverride::Undefine(name) => {
+                    self.remove(name);
+                }
+            }
+        }
+    }
+
     /// Returns the number of elements in the map.
     #[inline]
     pub fn len(&self) -> usize {
@@ -238,6 +381,71 @@ impl DefineMap {
     */
 }
 
+// ----------------------------------------------------------------------------
+// Macro expansion tracing
+
+/// A record of a single macro expansion, collected when tracing is enabled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroExpansion {
+    /// The name of the expanded macro.
+    pub name: String,
+    /// The location of the macro's definition.
+    pub definition: Location,
+    /// The location of the macro use which triggered this expansion.
+    pub location: Location,
+    /// The arguments, if the macro is function-like.
+    pub args: Option<Vec<Vec<Token>>>,
+    /// The tokens the macro expanded to.
+    pub tokens: Vec<Token>,
+    /// The index of the expansion this one occurred inside, if any.
+    pub parent: Option<usize>,
+}
+
+impl MacroExpansion {
+    /// Count how many expansions this one is nested inside.
+    pub fn depth(&self, trace: &[MacroExpansion]) -> usize {
+        let mut depth = 0;
+        let mut current = self.parent;
+        while let Some(idx) = current {
+            depth += 1;
+            current = trace[idx].parent;
+        }
+        depth
+    }
+}
+
+impl fmt::Display for MacroExpansion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if let Some(args) = self.args.as_ref() {
+            f.write_str("(")?;
+            for (i, arg) in args.iter().enumerate() {
+                if i != 0 {
+                    f.write_str(", ")?;
+                }
+                write_tokens(f, arg)?;
+            }
+            f.write_str(")")?;
+        }
+        f.write_str(" => ")?;
+        write_tokens(f, &self.tokens)
+    }
+}
+
+fn write_tokens(f: &mut fmt::Formatter, tokens: &[Token]) -> fmt::Result {
+    let mut prev: Option<&Token> = None;
+    for token in tokens {
+        if let Some(prev) = prev {
+            if token.separate_from(prev) {
+                f.write_str(" ")?;
+            }
+        }
+        write!(f, "{}", token)?;
+        prev = Some(token);
+    }
+    Ok(())
+}
+
 // ----------------------------------------------------------------------------
 // The stack of currently #included files
 
@@ -251,7 +459,9 @@ enum Include<'ctx> {
     Expansion {
         name: String,
         location: Location,
+        end: Location,
         tokens: VecDeque<Token>,
+        trace: Option<usize>,
     },
 }
 
@@ -303,9 +513,10 @@ impl<'ctx> Iterator for IncludeStack<'ctx> {
                 Some(&mut Include::Expansion {
                     ref mut tokens,
                     location,
+                    end,
                     ..
                 }) => match tokens.pop_front() {
-                    Some(token) => return Some(LocatedToken { location, token }),
+                    Some(token) => return Some(LocatedToken::with_end(location, end, token)),
                     None => {} // fall through
                 },
                 None => return None,
@@ -358,16 +569,34 @@ pub struct Preprocessor<'ctx> {
     include_stack: IncludeStack<'ctx>,
     include_locations: HashMap<FileId, Location>,
     last_input_loc: Location,
+    last_input_end: Location,
     output: VecDeque<Token>,
     ifdef_stack: Vec<Ifdef>,
     ifdef_history: IntervalTree<Location, bool>,
     annotations: Option<AnnotationTree>,
+    expansions: Option<Vec<MacroExpansion>>,
+    pragmas: HashMap<String, Option<Severity>>,
+
+    /// Every `#define` so far with the span of its name and body, if unnecessary
+    /// code is being linted for.
+    defined_macros: Option<Vec<(String, Location, Location)>>,
+    /// The definition locations of macros which have been expanded or
+    /// tested with `#ifdef` or `defined()`.
+    used_macros: HashSet<Location>,
+    macro_uses: usize,
+    /// The end of each `#if` condition evaluated so far and whether it has
+    /// been false and true, if unnecessary code is being linted for. Only
+    /// conditions which use no macros are recorded, unless
+    /// `lint_macro_conditions` is set.
+    condition_values: BTreeMap<Location, (Location, [bool; 2])>,
+    lint_macro_conditions: bool,
 
     history: InnerDefineHistory,
     defines: DefineMap,
     maps: Vec<PathBuf>,
     skins: Vec<PathBuf>,
     scripts: Vec<PathBuf>,
+    include_graph: IncludeGraph,
 
     last_printable_input_loc: Location,
     danger_idents: HashMap<String, Location>,
@@ -399,6 +628,7 @@ impl<'ctx> Preprocessor<'ctx> {
             file.read_to_end(&mut buffer)?;
         }
         let include = Include::from_read(context, env_file.clone(), Box::new(io::Cursor::new(buffer)));
+        let include_graph = IncludeGraph::new(env_file.clone(), context.register_file(&env_file));
 
         Ok(Preprocessor {
             context,
@@ -406,13 +636,15 @@ impl<'ctx> Preprocessor<'ctx> {
             include_stack: IncludeStack { stack: vec![include] },
             include_locations: Default::default(),
             history: Default::default(),
-            defines: DefineMap::with_builtins(),
+            defines: DefineMap::with_builtins_for(context.byond_version()),
             maps: Default::default(),
             skins: Default::default(),
             scripts: Default::default(),
+            include_graph,
             ifdef_stack: Default::default(),
             ifdef_history: Default::default(),
             last_input_loc: Default::default(),
+            last_input_end: Default::default(),
             last_printable_input_loc: Default::default(),
             output: Default::default(),
             danger_idents: Default::default(),
@@ -420,6 +652,13 @@ impl<'ctx> Preprocessor<'ctx> {
             docs_out: Default::default(),
             in_interp_string: 0,
             annotations: None,
+            expansions: None,
+            pragmas: Default::default(),
+            defined_macros: None,
+            used_macros: Default::default(),
+            macro_uses: 0,
+            condition_values: Default::default(),
+            lint_macro_conditions: false,
         })
     }
 
@@ -429,19 +668,22 @@ impl<'ctx> Preprocessor<'ctx> {
             Cow::Owned(s) => Cow::Owned(s.into_bytes()),
         };
         let include = Include::from_read(context, env_file.clone(), Box::new(io::Cursor::new(cow_u8)));
+        let include_graph = IncludeGraph::new(env_file.clone(), context.register_file(&env_file));
         Preprocessor {
             context,
             env_file,
             include_stack: IncludeStack { stack: vec![include] },
             include_locations: Default::default(),
             history: Default::default(),
-            defines: DefineMap::with_builtins(),
+            defines: DefineMap::with_builtins_for(context.byond_version()),
             maps: Default::default(),
             skins: Default::default(),
             scripts: Default::default(),
+            include_graph,
             ifdef_stack: Default::default(),
             ifdef_history: Default::default(),
             last_input_loc: Default::default(),
+            last_input_end: Default::default(),
             last_printable_input_loc: Default::default(),
             output: Default::default(),
             danger_idents: Default::default(),
@@ -449,6 +691,13 @@ impl<'ctx> Preprocessor<'ctx> {
             docs_out: Default::default(),
             in_interp_string: 0,
             annotations: None,
+            expansions: None,
+            pragmas: Default::default(),
+            defined_macros: None,
+            used_macros: Default::default(),
+            macro_uses: 0,
+            condition_values: Default::default(),
+            lint_macro_conditions: false,
         }
     }
 
@@ -501,6 +750,17 @@ impl<'ctx> Preprocessor<'ctx> {
         idx
     }
 
+    /// Access the graph of `#include`s followed so far.
+    pub fn include_graph(&self) -> &IncludeGraph {
+        &self.include_graph
+    }
+
+    /// Set or remove defines on top of the builtins, as with `-D` or `-U`.
+    /// Call before reading any tokens.
+    pub fn set_define_overrides(&mut self, overrides: &[DefineOverride]) {
+        self.defines.apply_overrides(self.context, overrides);
+    }
+
     /// Enable source file annotations.
     pub fn enable_annotations(&mut self) {
         self.annotations = Some(AnnotationTree::default());
@@ -511,9 +771,101 @@ impl<'ctx> Preprocessor<'ctx> {
         self.annotations.take()
     }
 
+    /// Enable diagnostics for unused macros and for `#if` conditions which
+    /// use no macros and so are the same in every build. Only meaningful when
+    /// reading the whole environment.
+    pub fn enable_unnecessary_lints(&mut self) {
+        self.defined_macros = Some(Vec::new());
+    }
+
+    /// Also report `#if` conditions which use macros but never changed value.
+    /// Only meaningful when comparing several configurations, where the
+    /// reports which agree in all of them are kept, as `multiconfig` does.
+    pub fn enable_macro_condition_lints(&mut self) {
+        self.defined_macros.get_or_insert_with(Vec::new);
+        self.lint_macro_conditions = true;
+    }
+
+    /// Enable recording of macro expansions.
+    pub fn enable_expansion_trace(&mut self) {
+        self.expansions = Some(Vec::new());
+    }
+
+    /// Access the macro expansions recorded so far, if tracing is enabled.
+    pub fn expansion_trace(&self) -> Option<&[MacroExpansion]> {
+        self.expansions.as_ref().map(|v| &v[..])
+    }
+
+    /// Retrieve the recorded macro expansions.
+    pub fn take_expansion_trace(&mut self) -> Option<Vec<MacroExpansion>> {
+        self.expansions.take()
+    }
+
+    /// Override the severity of a diagnostic as if by `#pragma`.
+    ///
+    /// A severity of `None` disables the diagnostic. Returns `false` if the
+    /// pragma name is not known.
+    pub fn set_pragma(&mut self, name: &str, severity: Option<Severity>) -> bool {
+        if !KNOWN_PRAGMAS.contains(&name) {
+            return false;
+        }
+        self.pragmas.insert(name.to_owned(), severity);
+        true
+    }
+
+    /// Register a diagnostic, applying any `#pragma` which controls it.
+    fn register_pragma_error(&self, pragma: &str, error: DMError) {
+        match self.pragmas.get(pragma) {
+            Some(&Some(severity)) => error.set_severity(severity).register(self.context),
+            Some(&None) => {}
+            None => error.register(self.context),
+        }
+    }
+
     // ------------------------------------------------------------------------
     // Macro definition handling
 
+    /// Record an expansion of the macro defined at `def_loc`.
+    fn use_macro(&mut self, ident: &str, def_loc: Location) {
+        self.used_macros.insert(def_loc);
+        self.macro_uses += 1;
+        self.annotate_macro(ident, def_loc);
+    }
+
+    /// Record that a macro was tested by `#ifdef` or `defined()`, which
+    /// counts as using it even though it is not expanded.
+    fn test_macro(&mut self, name: &str) {
+        if let Some(&(location, _)) = self.defines.get(name) {
+            self.used_macros.insert(location);
+        }
+    }
+
+    /// Report the macros which were defined but never used, and the `#if`
+    /// conditions which never changed value. Only possible once the whole
+    /// environment has been read.
+    fn check_unnecessary_directives(&mut self) {
+        let defined_macros = match self.defined_macros.take() {
+            Some(defined_macros) => defined_macros,
+            None => return,
+        };
+        for (name, start, end) in defined_macros {
+            if !self.used_macros.contains(&start) {
+                self.register_pragma_error("UnusedMacro", DMError::new(start, format!("macro defined but never used: {}", name))
+                    .set_severity(Severity::Hint)
+                    .set_unnecessary()
+                    .with_end(end));
+            }
+        }
+        for (start, (end, seen)) in std::mem::take(&mut self.condition_values) {
+            if seen[0] != seen[1] {
+                self.register_pragma_error("ConstantCondition", DMError::new(start, format!("condition is always {}", seen[1]))
+                    .set_severity(Severity::Hint)
+                    .set_unnecessary()
+                    .with_end(end));
+            }
+        }
+    }
+
     fn annotate_macro(&mut self, ident: &str, def_loc: Location) {
         if self.include_stack.in_expansion() {
             return;
@@ -526,6 +878,34 @@ impl<'ctx> Preprocessor<'ctx> {
         }
     }
 
+    fn push_expansion(&mut self, name: &str, definition: Location, args: Option<Vec<Vec<Token>>>, tokens: VecDeque<Token>) {
+        let trace = match self.expansions.as_mut() {
+            Some(expansions) => {
+                let parent = match self.include_stack.stack.last() {
+                    Some(&Include::Expansion { trace, .. }) => trace,
+                    _ => None,
+                };
+                expansions.push(MacroExpansion {
+                    name: name.to_owned(),
+                    definition,
+                    location: self.last_input_loc,
+                    args,
+                    tokens: tokens.iter().cloned().collect(),
+                    parent,
+                });
+                Some(expansions.len() - 1)
+            }
+            None => None,
+        };
+        self.include_stack.stack.push(Include::Expansion {
+            name: name.to_owned(),
+            tokens,
+            location: self.last_input_loc,
+            end: self.last_input_end,
+            trace,
+        });
+    }
+
     fn in_environment(&self) -> bool {
         for include in self.include_stack.stack.iter().rev() {
             if let Include::File { ref path, .. } = *include {
@@ -538,6 +918,7 @@ impl<'ctx> Preprocessor<'ctx> {
     fn is_defined(&self, name: &str) -> bool {
         match name {
             "__MAIN__" => self.in_environment(),
+            "__FILE__" | "__LINE__" => true,
             _ => self.defines.contains_key(name),
         }
     }
@@ -563,13 +944,29 @@ impl<'ctx> Preprocessor<'ctx> {
     fn evaluate_inner(&mut self) -> Result<bool, DMError> {
         // pump real_next to fill output until we get a real newline on input
         let start = self.last_input_loc;
+        let uses_before = self.macro_uses;
         while let Some(tok) = self.inner_next() {
             self.last_input_loc = tok.location;
+            self.last_input_end = tok.end;
 
             if let Token::Punct(Punctuation::Newline) = tok.token {
                 break;
             }
 
+            // the argument to `defined()` is a name, not something to expand
+            if let Token::Ident(..) = tok.token {
+                let mut last_two = self.output.iter().rev();
+                if let (Some(Token::Punct(Punctuation::LParen)), Some(Token::Ident(defined, _))) = (last_two.next(), last_two.next()) {
+                    if defined == "defined" {
+                        if let Token::Ident(ref name, _) = tok.token {
+                            self.test_macro(name);
+                        }
+                        self.output.push_back(tok.token);
+                        continue;
+                    }
+                }
+            }
+
             if let Err(e) = self.real_next(tok.token, true) {
                 self.context.register_error(e);
             }
@@ -582,12 +979,25 @@ impl<'ctx> Preprocessor<'ctx> {
             return Ok(false);
         }
 
+        // A condition which expands no macros and names nothing is the same
+        // in every build.
+        let constant = self.macro_uses == uses_before && !self.output.iter().any(|token| match token {
+            Token::Ident(..) => true,
+            _ => false,
+        });
+
         let expr = crate::parser::parse_expression(
             self.context,
             start,
             self.output.drain(..).map(|token| LocatedToken::new(start, token))
         )?;
-        Ok(crate::constants::preprocessor_evaluate(start, expr, &self.defines)?.to_bool())
+        let is_defined = |name: &str| self.is_defined(name);
+        let value = crate::constants::preprocessor_evaluate_with(start, expr, &is_defined)?.to_bool();
+        if self.defined_macros.is_some() && (constant || self.lint_macro_conditions) {
+            let end = self.last_input_loc;
+            self.condition_values.entry(start).or_insert((end, [false; 2])).1[value as usize] = true;
+        }
+        Ok(value)
     }
 
     fn evaluate(&mut self) -> bool {
@@ -612,7 +1022,7 @@ impl<'ctx> Preprocessor<'ctx> {
     // ------------------------------------------------------------------------
     // Internal utilities
 
-    fn prepare_include_file(&mut self, path: PathBuf) -> Result<Include<'ctx>, DMError> {
+    fn prepare_include_file(&mut self, path: PathBuf) -> Result<Option<Include<'ctx>>, DMError> {
         // Attempt to open the file.
         let read = io::BufReader::new(File::open(&path).map_err(|e|
             DMError::new(self.last_input_loc, format!("failed to open file: #include {:?}", path))
@@ -625,16 +1035,17 @@ impl<'ctx> Preprocessor<'ctx> {
         // All DM source is effectively `#pragma once`.
         let file_id = self.context.register_file(&register);
         if let Some(&loc) = self.include_locations.get(&file_id) {
-            Err(DMError::new(self.last_input_loc, format!("duplicate #include {:?}", path))
+            self.register_pragma_error("FileAlreadyIncluded", DMError::new(self.last_input_loc, format!("duplicate #include {:?}", path))
                 .set_severity(Severity::Warning)
-                .with_note(loc, "previously included here"))
+                .with_note(loc, "previously included here"));
+            Ok(None)
         } else {
             self.include_locations.insert(file_id, self.last_input_loc);
-            Ok(Include::File {
+            Ok(Some(Include::File {
                 path,
                 file: file_id,
                 lexer: Lexer::from_read(&self.context, file_id, Box::new(read)),
-            })
+            }))
         }
     }
 
@@ -676,7 +1087,7 @@ impl<'ctx> Preprocessor<'ctx> {
 
         const ALL_DIRECTIVES: &[&str] = &[
             "if", "ifdef", "ifndef", "elif", "else", "endif",
-            "include", "define", "undef", "warn", "error",
+            "include", "define", "undef", "warn", "error", "pragma",
         ];
         let disabled = !inside_condition && self.is_disabled();
         match read {
@@ -697,12 +1108,14 @@ impl<'ctx> Preprocessor<'ctx> {
                     "ifdef" => {
                         expect_token!((define_name) = Token::Ident(define_name, _));
                         expect_token!(() = Token::Punct(Punctuation::Newline));
+                        self.test_macro(&define_name);
                         let enabled = self.is_defined(&define_name);
                         self.ifdef_stack.push(Ifdef::new(self.last_input_loc, enabled));
                     }
                     "ifndef" => {
                         expect_token!((define_name) = Token::Ident(define_name, _));
                         expect_token!(() = Token::Punct(Punctuation::Newline));
+                        self.test_macro(&define_name);
                         let enabled = !self.is_defined(&define_name);
                         self.ifdef_stack.push(Ifdef::new(self.last_input_loc, enabled));
                     }
@@ -735,49 +1148,54 @@ impl<'ctx> Preprocessor<'ctx> {
                             if !candidate.exists() {
                                 continue;
                             }
-                            // Double-match is used to let go of the borrow of
-                            // `candidate` so it can be used in the second half.
-                            enum FileType {
-                                DMM,
-                                DMF,
-                                DMS,
-                                DM,
-                            }
-                            match match candidate.extension().and_then(|s| s.to_str()) {
-                                Some("dmm") => FileType::DMM,
-                                Some("dmf") => FileType::DMF,
-                                Some("dms") => FileType::DMS,
-                                Some("dm") => FileType::DM,
-                                Some("dme") => FileType::DM,
-                                Some(ext) => {
-                                    self.context.register_error(DMError::new(
-                                        self.last_input_loc,
-                                        format!("unknown extension {:?}", ext),
-                                    ));
-                                    return Ok(());
-                                }
+                            let kind = match candidate.extension().and_then(|s| s.to_str()) {
+                                Some(ext) => match IncludeKind::from_extension(ext) {
+                                    Some(kind) => kind,
+                                    None => {
+                                        self.context.register_error(DMError::new(
+                                            self.last_input_loc,
+                                            format!("unknown extension {:?}", ext),
+                                        ));
+                                        return Ok(());
+                                    }
+                                },
                                 None => {
                                     self.context.register_error(DMError::new(self.last_input_loc, "filename has no extension"));
                                     return Ok(());
                                 }
-                            } {
-                                FileType::DMM => self.maps.push(candidate),
-                                FileType::DMF => self.skins.push(candidate),
-                                FileType::DMS => self.scripts.push(candidate),
-                                FileType::DM => match self.prepare_include_file(candidate) {
-                                    Ok(include) => {
+                            };
+                            let mut inclusion = Inclusion {
+                                path: candidate.strip_prefix(self.env_file.parent().unwrap()).unwrap_or(&candidate).to_owned(),
+                                kind,
+                                location: self.last_input_loc,
+                                file: None,
+                            };
+                            match kind {
+                                IncludeKind::Map => self.maps.push(candidate),
+                                IncludeKind::Interface => self.skins.push(candidate),
+                                IncludeKind::Script => self.scripts.push(candidate),
+                                IncludeKind::Code => match self.prepare_include_file(candidate) {
+                                    Ok(None) => return Ok(()),
+                                    Ok(Some(include)) => {
+                                        if let Include::File { file, .. } = include {
+                                            inclusion.file = Some(file);
+                                        }
                                         // A phantom newline keeps the include
                                         // directive being indented from making
                                         // the first line of the file indented.
                                         self.output.push_back(Token::Punct(Punctuation::Newline));
                                         self.include_stack.stack.push(include);
                                     },
-                                    Err(e) => self.context.register_error(e),
+                                    Err(e) => {
+                                        self.context.register_error(e);
+                                        return Ok(());
+                                    }
                                 },
                             }
+                            self.include_graph.push(inclusion);
                             return Ok(());
                         }
-                        self.context.register_error(DMError::new(self.last_input_loc, format!("failed to find #include {:?}", path)));
+                        self.register_pragma_error("MissingIncludedFile", DMError::new(self.last_input_loc, format!("failed to find #include {:?}", path)));
                         return Ok(());
                     }
                     // both constant and function defines
@@ -870,14 +1288,19 @@ impl<'ctx> Preprocessor<'ctx> {
                         };
                         // DEBUG can only be defined in the root .dme file
                         if define_name != "DEBUG" || self.in_environment() {
+                            // DEBUG and FILE_DIR are read by the compiler itself.
+                            if define_name != "DEBUG" && define_name != "FILE_DIR" {
+                                if let Some(defined_macros) = self.defined_macros.as_mut() {
+                                    defined_macros.push((define_name.clone(), define_name_loc, _last_expected_loc));
+                                }
+                            }
                             if let Some(previous_loc) = self.defines.insert(define_name.clone(), (define_name_loc, define)) {
                                 // DM doesn't issue a warning for this, but it's usually a mistake, so let's.
                                 // FILE_DIR is handled specially and sometimes makes sense to define multiple times.
                                 if define_name != "FILE_DIR" {
-                                    DMError::new(define_name_loc, format!("macro redefined: {}", define_name))
+                                    self.register_pragma_error("DefinedMacro", DMError::new(define_name_loc, format!("macro redefined: {}", define_name))
                                         .set_severity(Severity::Warning)
-                                        .with_note(previous_loc, format!("previous definition of {}", define_name))
-                                        .register(self.context);
+                                        .with_note(previous_loc, format!("previous definition of {}", define_name)));
                                 }
                             }
                         }
@@ -889,11 +1312,19 @@ impl<'ctx> Preprocessor<'ctx> {
                         self.check_danger_ident(&define_name, "undefined");
                         expect_token!(() = Token::Punct(Punctuation::Newline));
                         if let Some(previous) = self.defines.remove(&define_name) {
+                            if self.defined_macros.is_some() && !previous.0.is_builtins() && self.used_macros.insert(previous.0) {
+                                // Inserting into `used_macros` above keeps
+                                // it from being reported a second time.
+                                self.register_pragma_error("UndefineUnusedMacro", DMError::new(self.last_input_loc, format!("macro undefined without being used: {}", define_name))
+                                    .set_severity(Severity::Hint)
+                                    .set_unnecessary()
+                                    .with_end(_last_expected_loc)
+                                    .with_note(previous.0, format!("{} defined here", define_name)));
+                            }
                             self.move_to_history(define_name, previous);
                         } else {
-                            DMError::new(define_name_loc, format!("macro undefined while not defined: {}", define_name))
-                                .set_severity(Severity::Warning)
-                                .register(self.context);
+                            self.register_pragma_error("UndefineMissingDirective", DMError::new(define_name_loc, format!("macro undefined while not defined: {}", define_name))
+                                .set_severity(Severity::Warning));
                         }
                     }
                     "warn" if disabled => {}
@@ -908,6 +1339,25 @@ impl<'ctx> Preprocessor<'ctx> {
                         expect_token!((text) = Token::String(text));
                         self.context.register_error(DMError::new(self.last_input_loc, format!("#{} {}", ident, text)));
                     }
+                    // pragmas adjust the severity of particular diagnostics
+                    "pragma" if disabled => {}
+                    "pragma" => {
+                        expect_token!((name) = Token::Ident(name, _));
+                        let name_loc = _last_expected_loc;
+                        let severity = match next!() {
+                            Token::Ident(ref setting, _) if setting == "warning" => Some(Severity::Warning),
+                            Token::Ident(ref setting, _) if setting == "error" => Some(Severity::Error),
+                            Token::Ident(ref setting, _) if setting == "disabled" => None,
+                            other => return Err(self.error(format!(
+                                "unexpected token {:?}, expecting \"warning\", \"error\", or \"disabled\"", other))),
+                        };
+                        expect_token!(() = Token::Punct(Punctuation::Newline));
+                        if !self.set_pragma(&name, severity) {
+                            DMError::new(name_loc, format!("unknown #pragma: {}", name))
+                                .set_severity(Severity::Warning)
+                                .register(self.context);
+                        }
+                    }
                     // none of this other stuff should even exist
                     other => {
                         let mut meant = "";
@@ -937,7 +1387,7 @@ impl<'ctx> Preprocessor<'ctx> {
 
                 // substitute special macros
                 if ident == "__FILE__" {
-                    self.annotate_macro(ident, Location::builtins());
+                    self.use_macro(ident, Location::builtins());
                     for include in self.include_stack.stack.iter().rev() {
                         if let Include::File { ref path, .. } = *include {
                             self.output.push_back(Token::String(path.display().to_string()));
@@ -947,7 +1397,7 @@ impl<'ctx> Preprocessor<'ctx> {
                     self.output.push_back(Token::String(String::new()));
                     return Ok(());
                 } else if ident == "__LINE__" {
-                    self.annotate_macro(ident, Location::builtins());
+                    self.use_macro(ident, Location::builtins());
                     self.output.push_back(Token::Int(self.last_input_loc.line as i32));
                     return Ok(());
                 }
@@ -962,12 +1412,8 @@ impl<'ctx> Preprocessor<'ctx> {
 
                 match expansion {
                     Some((location, Define::Constant { subst, docs: _ })) => {
-                        self.annotate_macro(ident, location);
-                        self.include_stack.stack.push(Include::Expansion {
-                            name: ident.to_owned(),
-                            tokens: subst.into_iter().collect(),
-                            location: self.last_input_loc,
-                        });
+                        self.use_macro(ident, location);
+                        self.push_expansion(ident, location, None, subst.into_iter().collect());
                         return Ok(());
                     }
                     Some((location, Define::Function { ref params, ref subst, variadic, docs: _ })) => {
@@ -986,7 +1432,7 @@ impl<'ctx> Preprocessor<'ctx> {
                             }
                         }
 
-                        self.annotate_macro(ident, location);
+                        self.use_macro(ident, location);
 
                         // read arguments
                         let mut args = Vec::new();
@@ -1110,11 +1556,7 @@ impl<'ctx> Preprocessor<'ctx> {
                                 _ => expansion.push_back(token),
                             }
                         }
-                        self.include_stack.stack.push(Include::Expansion {
-                            name: ident.to_owned(),
-                            tokens: expansion,
-                            location: self.last_input_loc,
-                        });
+                        self.push_expansion(ident, location, Some(args), expansion);
                         return Ok(());
                     }
                     None => {}
@@ -1144,17 +1586,11 @@ impl<'ctx> Iterator for Preprocessor<'ctx> {
     fn next(&mut self) -> Option<LocatedToken> {
         loop {
             if let Some((location, doc)) = self.docs_out.pop_front() {
-                return Some(LocatedToken {
-                    location,
-                    token: Token::DocComment(doc),
-                });
+                return Some(LocatedToken::new(location, Token::DocComment(doc)));
             }
 
             if let Some(token) = self.output.pop_front() {
-                return Some(LocatedToken {
-                    location: self.last_input_loc,
-                    token,
-                });
+                return Some(LocatedToken::with_end(self.last_input_loc, self.last_input_end, token));
             }
 
             if let Some(tok) = self.inner_next() {
@@ -1170,6 +1606,7 @@ impl<'ctx> Iterator for Preprocessor<'ctx> {
 
                 // update last_input_loc and attempt to process the input token
                 self.last_input_loc = tok.location;
+                self.last_input_end = tok.end;
                 if let Err(e) = self.real_next(tok.token, false) {
                     self.context.register_error(e);
                 }
@@ -1177,6 +1614,7 @@ impl<'ctx> Iterator for Preprocessor<'ctx> {
                 while let Some(ifdef) = self.pop_ifdef() {
                     self.context.register_error(DMError::new(ifdef.location, "unterminated #if/#ifdef"));
                 }
+                self.check_unnecessary_directives();
                 return None;
             }
         }
@@ -6,3 +6,26 @@ use dm::objtree::ObjectTree;
 fn check_builtins() {
     println!("{:?}", ObjectTree::with_builtins());
 }
+
+#[test]
+fn builtins_by_version() {
+    let old = ObjectTree::with_builtins_for(dm::ByondVersion::new(512, 1488));
+    assert!(old.root().get_proc("arctan").is_none());
+    assert!(old.root().get_proc("abs").is_some());
+
+    let new = ObjectTree::with_builtins_for(dm::ByondVersion::new(513, 1501));
+    assert!(new.root().get_proc("arctan").is_some());
+}
+
+#[test]
+fn dm_version_define() {
+    let mut context = dm::Context::default();
+    context.set_byond_version(dm::ByondVersion::new(512, 1488));
+    let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "builtins_tests.rs".into(), "DM_VERSION JUMP_EASING");
+    let tokens: Vec<_> = pp.map(|t| t.token).collect();
+    assert_eq!(tokens, vec![
+        dm::lexer::Token::Int(512),
+        dm::lexer::Token::Ident("JUMP_EASING".to_owned(), false),
+        dm::lexer::Token::Punct(dm::lexer::Punctuation::Newline),
+    ]);
+}
@@ -13,3 +13,70 @@ fn floating_point_rgb() {
         Constant::String("#7f7f7f".to_owned()),
     );
 }
+
+fn eval(code: &str) -> Constant {
+    dm::constants::evaluate_str(Default::default(), code.as_bytes())
+        .unwrap_or_else(|e| panic!("evaluation of {:?} failed: {}", code, e))
+}
+
+fn string(s: &str) -> Constant {
+    Constant::String(s.to_owned())
+}
+
+#[test]
+fn rgb_alpha_and_spaces() {
+    assert_eq!(eval("rgb(255, 0, 128, 64)"), string("#ff008040"));
+    assert_eq!(eval("rgb(r = 1, g = 2, b = 3, a = 4)"), string("#01020304"));
+    assert_eq!(eval("rgb(0, 100, 100, space = 1)"), string("#ff0000"));
+    assert_eq!(eval("rgb(h = 120, s = 100, v = 100)"), string("#00ff00"));
+    assert_eq!(eval("rgb(h = 240, s = 100, l = 50)"), string("#0000ff"));
+    let err = dm::constants::evaluate_str(Default::default(), b"rgb(1, 2, 3, space = 3)").unwrap_err();
+    assert!(err.description().contains("COLORSPACE_HCY"), "{}", err);
+    let err = dm::constants::evaluate_str(Default::default(), b"rgb(h = 1, c = 2, y = 3)").unwrap_err();
+    assert!(err.description().contains("COLORSPACE_HCY"), "{}", err);
+    assert!(dm::constants::evaluate_str(Default::default(), b"rgb(1, 2, 3, space = 4)").is_err());
+}
+
+#[test]
+fn text_procs() {
+    assert_eq!(eval("text(\"[] and []\", 1, \"two\")"), string("1 and two"));
+    assert_eq!(eval("num2text(1.5)"), string("1.5"));
+    assert_eq!(eval("num2text(3.14159, 3)"), string("3.14"));
+    assert_eq!(eval("length(\"hello\")"), Constant::Int(5));
+    assert_eq!(eval("length(list(1, 2, 3))"), Constant::Int(3));
+    assert_eq!(eval("copytext(\"abcdef\", 2, 4)"), string("bc"));
+    assert_eq!(eval("copytext(\"abcdef\", 3)"), string("cdef"));
+    assert_eq!(eval("copytext(\"abcdef\", -2)"), string("ef"));
+    assert_eq!(eval("uppertext(\"MiXeD\")"), string("MIXED"));
+    assert_eq!(eval("lowertext(\"MiXeD\")"), string("mixed"));
+    assert_eq!(eval("ascii2text(65)"), string("A"));
+}
+
+#[test]
+fn numeric_procs() {
+    assert_eq!(eval("round(2.7)"), Constant::Int(2));
+    assert_eq!(eval("round(17, 5)"), Constant::Int(15));
+    assert_eq!(eval("round(2.25, 0.5)"), Constant::Float(2.5));
+    assert_eq!(eval("min(3, 1, 2)"), Constant::Int(1));
+    assert_eq!(eval("max(3, 1.5, 2)"), Constant::Int(3));
+    assert_eq!(eval("max(list(4, 9, 2))"), Constant::Int(9));
+    assert_eq!(eval("min(\"b\", \"a\")"), string("a"));
+    assert_eq!(eval("abs(-4)"), Constant::Int(4));
+    assert_eq!(eval("sqrt(16)"), Constant::Float(4.0));
+    let err = dm::constants::evaluate_str(Default::default(), b"sqrt(-4)").unwrap_err();
+    assert_eq!(err.description(), "sqrt() of negative number");
+    let err = dm::constants::evaluate_str(Default::default(), b"sqrt(1, 2)").unwrap_err();
+    assert_eq!(err.description(), "sqrt() requires exactly 1 argument");
+    assert_eq!(eval("log(10, 100)"), Constant::Float(2.0));
+}
+
+#[test]
+fn keyed_list() {
+    match eval("list(\"a\" = 1, \"b\" = 2)") {
+        Constant::List(list) => {
+            assert_eq!(list.len(), 2);
+            assert_eq!(list[0], (string("a"), Some(Constant::Int(1))));
+        }
+        other => panic!("not a list: {:?}", other),
+    }
+}
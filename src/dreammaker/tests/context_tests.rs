@@ -0,0 +1,83 @@
+extern crate dreammaker as dm;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use dm::{Context, DMError, Location};
+
+#[test]
+fn context_is_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Context>();
+}
+
+#[test]
+fn concurrent_registration() {
+    let context = Arc::new(Context::default());
+    let threads: Vec<_> = (0..8).map(|i| {
+        let context = context.clone();
+        thread::spawn(move || {
+            let file = context.register_file(Path::new("shared.dm"));
+            let own = context.register_file(Path::new(&format!("thread{}.dm", i)));
+            for line in 0..100 {
+                let location = Location { file: own, line, column: 1 };
+                context.register_error(DMError::new(location, format!("error {}", line)));
+            }
+            file
+        })
+    }).collect();
+    let files: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+    // Every thread sees the same ID for the same path.
+    assert!(files.iter().all(|&file| file == files[0]));
+
+    let errors = context.errors();
+    assert_eq!(errors.len(), 800);
+    for i in 0..8 {
+        let own = context.get_file(Path::new(&format!("thread{}.dm", i))).unwrap();
+        // Errors from a single thread keep their registration order.
+        let lines: Vec<_> = errors.iter()
+            .filter(|e| e.location().file == own)
+            .map(|e| e.location().line)
+            .collect();
+        assert_eq!(lines, (0..100).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn concurrent_registration_and_reading() {
+    let context = Arc::new(Context::default());
+    let file = context.register_file(Path::new("reader.dm"));
+    let threads: Vec<_> = (0..4).map(|_| {
+        let context = context.clone();
+        thread::spawn(move || {
+            for line in 0..200 {
+                let location = Location { file, line, column: 1 };
+                context.register_error(DMError::new(location, "error"));
+            }
+        })
+    }).collect();
+
+    // Views taken while other threads register never go backwards, even
+    // while an earlier view is still held.
+    let mut last = context.errors();
+    while last.len() < 800 {
+        let next = context.errors();
+        assert!(next.len() >= last.len());
+        last = next;
+        thread::yield_now();
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(context.errors().len(), 800);
+
+    // A held view sees neither later registrations nor later changes, and
+    // does not block them.
+    let held = context.errors();
+    context.register_error(DMError::new(Location { file, line: 1000, column: 1 }, "late"));
+    context.errors_mut().retain(|error| error.location().line != 0);
+    assert_eq!(held.len(), 800);
+    assert_eq!(context.errors().len(), 801 - 4);
+}
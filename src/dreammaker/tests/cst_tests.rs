@@ -0,0 +1,61 @@
+extern crate dreammaker as dm;
+
+use dm::cst::*;
+use dm::lexer::{Token, Punctuation};
+
+const SOURCE: &str = "\
+// A comment at the top.
+#define FOO(x) (x + 1) /* trailing */
+
+/obj/thing
+\tname = \"thing\"  // a name
+\tvar/list/things = list(1,
+\t\t2, 3)
+
+/obj/thing/proc/act()
+\tif (FOO(2) > 1)   \r
+\t\tworld << \"[src] acts [ name ]\"
+\treturn
+";
+
+#[test]
+fn round_trip() {
+    let context = dm::Context::default();
+    let tree = SyntaxTree::parse(&context, Default::default(), SOURCE.as_bytes().to_owned());
+    let mut output = Vec::new();
+    tree.write_to(&mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), SOURCE);
+}
+
+#[test]
+fn trivia_and_structure() {
+    let context = dm::Context::default();
+    let tree = SyntaxTree::parse(&context, Default::default(), SOURCE.as_bytes().to_owned());
+
+    let hash = tree.root().tokens().find(|t| !t.is_virtual).unwrap();
+    assert_eq!(hash.token, Token::Punct(Punctuation::Hash));
+    assert_eq!(hash.leading_trivia[0].kind, TriviaKind::Comment);
+    assert_eq!(tree.text(&hash.leading_trivia[0].span), "// A comment at the top.");
+
+    // indentation produces virtual braces, which contain the var definitions
+    let block = tree.root().children.iter().filter_map(|e| match e {
+        SyntaxElement::Node(node) if node.kind == NodeKind::Block => Some(node),
+        _ => None,
+    }).next().unwrap();
+    let first = block.tokens().next().unwrap();
+    assert!(first.is_virtual);
+    assert_eq!(first.token, Token::Punct(Punctuation::LBrace));
+    assert!(block.tokens().any(|t| !t.is_virtual && tree.text(&t.span) == "name"));
+}
+
+#[test]
+fn tokens_between_locations() {
+    let context = dm::Context::default();
+    let tree = SyntaxTree::parse(&context, Default::default(), SOURCE.as_bytes().to_owned());
+
+    let line = |line| dm::Location { file: Default::default(), line, column: 1 };
+    let texts: Vec<_> = tree.tokens_between(line(5), line(6))
+        .map(|t| tree.text(&t.span).into_owned())
+        .collect();
+    assert_eq!(texts, ["name", "=", "\"thing\""]);
+}
@@ -76,3 +76,45 @@ fn ternary_without_spaces() {
     parse_expr(r#"listkey = set_keyword ? "[set_keyword] [locname]":"[locname]""#);
     parse_expr(r#"pump_direction?("release"):("siphon")"#);
 }
+
+#[test]
+fn syntax_by_version() {
+    let mut context = dm::Context::default();
+    context.set_byond_version(dm::ByondVersion::new(512, 1488));
+    let lexer = Lexer::new(&context, Default::default(), "a?.b".bytes().map(Ok));
+    parse_expression(&context, Default::default(), lexer).expect("failed to parse expression");
+    assert_eq!(context.errors().len(), 1);
+
+    let mut context = dm::Context::default();
+    context.set_byond_version(dm::ByondVersion::new(515, 1600));
+    let lexer = Lexer::new(&context, Default::default(), "a::b".bytes().map(Ok));
+    let expr = parse_expression(&context, Default::default(), lexer).expect("failed to parse expression");
+    context.assert_success();
+    assert_eq!(expr, Expression::Base {
+        unary: vec![],
+        term: Spanned::new(Default::default(), Term::Ident("a".to_owned())),
+        follow: vec![Spanned::new(Default::default(), Follow::Field(IndexKind::Scope, "b".to_owned()))],
+    });
+}
+
+#[test]
+fn ternary_with_scope_operator() {
+    let mut context = dm::Context::default();
+    context.set_byond_version(dm::ByondVersion::new(515, 1600));
+    let lexer = Lexer::new(&context, Default::default(), "a ? b::c : d".bytes().map(Ok));
+    let expr = parse_expression(&context, Default::default(), lexer).expect("failed to parse expression");
+    context.assert_success();
+    assert_eq!(expr, Expression::TernaryOp {
+        cond: Box::new(Expression::from(Term::Ident("a".to_owned()))),
+        if_: Box::new(Expression::Base {
+            unary: vec![],
+            term: Spanned::new(Default::default(), Term::Ident("b".to_owned())),
+            follow: vec![Spanned::new(Default::default(), Follow::Field(IndexKind::Scope, "c".to_owned()))],
+        }),
+        else_: Box::new(Expression::from(Term::Ident("d".to_owned()))),
+    });
+
+    // Targeting older versions, the same ternary is still written with a
+    // space between the colons.
+    parse_expr("a ? b : c:d");
+}
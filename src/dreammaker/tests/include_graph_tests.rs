@@ -0,0 +1,82 @@
+extern crate dreammaker as dm;
+extern crate walkdir;
+
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dm::include_graph::{dreammaker_order, IncludeKind};
+use dm::preprocessor::Preprocessor;
+
+#[test]
+fn include_order() {
+    let order = |a: &str, b: &str| dreammaker_order(Path::new(a), Path::new(b));
+    assert_eq!(order("code/a.dm", "code/B.dm"), Ordering::Less);
+    assert_eq!(order("code/zzz.dm", "code/aaa/file.dm"), Ordering::Less);
+    assert_eq!(order("code/aaa/file.dm", "code/zzz.dm"), Ordering::Greater);
+    assert_eq!(order("code/A/x.dm", "code/a/x.dm"), Ordering::Equal);
+}
+
+/// A directory which is removed when dropped, even if the test fails.
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn write(root: &Path, path: &str, contents: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn include_graph() {
+    let temp = TempDir(std::env::temp_dir().join(format!("include_graph_tests_{}", std::process::id())));
+    let root = &temp.0;
+    write(root, "test.dme", "#include \"code/b.dm\"\n#include \"code/a.dm\"\n#include \"maps/station.dmm\"\n");
+    write(root, "code/a.dm", "/obj/a\n");
+    write(root, "code/b.dm", "#include \"nested.dm\"\n/obj/b\n");
+    write(root, "code/nested.dm", "/obj/nested\n");
+    write(root, "code/unused.dm", "/obj/unused\n");
+    write(root, "maps/station.dmm", "");
+    write(root, "maps/unused.dmm", "");
+
+    let context = dm::Context::default();
+    let mut pp = Preprocessor::new(&context, root.join("test.dme")).unwrap();
+    pp.by_ref().for_each(drop);
+    let graph = pp.include_graph();
+
+    let paths: Vec<_> = graph.inclusions().iter().map(|inclusion| inclusion.path.clone()).collect();
+    assert_eq!(paths, vec![
+        PathBuf::from("code/b.dm"),
+        PathBuf::from("code/nested.dm"),
+        PathBuf::from("code/a.dm"),
+        PathBuf::from("maps/station.dmm"),
+    ]);
+    assert_eq!(graph.find(Path::new("maps/station.dmm")).unwrap().kind, IncludeKind::Map);
+    let nested = graph.find(Path::new("code/nested.dm")).unwrap();
+    assert_eq!(Some(nested.location.file), graph.find(Path::new("code/b.dm")).unwrap().file);
+
+    let misordered: Vec<_> = graph.misordered_includes().iter()
+        .map(|(previous, inclusion)| (previous.path.clone(), inclusion.path.clone()))
+        .collect();
+    assert_eq!(misordered, vec![(PathBuf::from("code/b.dm"), PathBuf::from("code/a.dm"))]);
+
+    let files = walkdir::WalkDir::new(root).into_iter()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.path().strip_prefix(root).unwrap().to_owned());
+    assert_eq!(graph.unincluded_files(files), vec![
+        PathBuf::from("code/unused.dm"),
+        PathBuf::from("maps/unused.dmm"),
+    ]);
+
+    let mut dot = Vec::new();
+    graph.write_dot(&context, &mut dot).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert!(dot.contains("\"code/b.dm\" -> \"code/nested.dm\";"));
+    assert!(dot.contains("\"maps/station.dmm\" [shape=box];"));
+}
@@ -1,5 +1,6 @@
 extern crate dreammaker as dm;
 
+use dm::Context;
 use dm::lexer::*;
 use dm::lexer::Token::*;
 use dm::lexer::Punctuation::*;
@@ -83,3 +84,28 @@ fn raw_strings() {
         assert_eq!(each, &desired);
     }
 }
+
+#[test]
+fn suppress_comments() {
+    let context = Context::default();
+    let source = "a ` // SpacemanDMM_ignore\nb `\nc ` //SpacemanDMM_ignored\n";
+    for _ in Lexer::new(&context, Default::default(), source.bytes().map(Ok)) {}
+    let lines: Vec<u32> = context.errors().iter().map(|e| e.location().line).collect();
+    assert_eq!(lines, vec![2, 3]);
+}
+
+#[test]
+fn scope_operator_by_version() {
+    // Before 515, `::` is two colons, as it has always been lexed.
+    let tokens = lex("a ? b::c");
+    assert!(!tokens.contains(&Punct(ColonColon)), "{:?}", tokens);
+    assert_eq!(tokens.iter().filter(|t| **t == Punct(CloseColon)).count(), 2, "{:?}", tokens);
+
+    let mut context = Context::default();
+    context.set_byond_version(dm::ByondVersion::new(515, 1600));
+    let tokens: Vec<Token> = Lexer::new(&context, Default::default(), "a ? b::c".bytes().map(Ok))
+        .map(|t| t.token)
+        .collect();
+    context.assert_success();
+    assert!(tokens.contains(&Punct(ColonColon)), "{:?}", tokens);
+}
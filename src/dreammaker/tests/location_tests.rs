@@ -80,3 +80,45 @@ fn reconstruct(tokens: &[LocatedToken], iffy: bool) -> String {
     }
     reconstructed.join("")
 }
+
+#[test]
+fn token_end_test() {
+    let code = "var/x = \"abc\" + 1.5\n";
+    let context = Default::default();
+    let tokens: Vec<_> = Lexer::new(&context, Default::default(), code.bytes().map(Ok))
+        .filter(|t| !t.token.is_whitespace())
+        .collect();
+    context.assert_success();
+
+    let columns: Vec<_> = tokens.iter().map(|t| (t.location.column, t.end.column)).collect();
+    assert_eq!(columns, [(1, 4), (4, 5), (5, 6), (7, 8), (9, 14), (15, 16), (17, 20)]);
+}
+
+#[test]
+fn expression_span_test() {
+    use dm::ast::*;
+
+    let code = "foo(-bar.baz[1], 2)";
+    let context = Default::default();
+    let lexer = Lexer::new(&context, Default::default(), code.bytes().map(Ok));
+    let expr = dm::parser::parse_expression(&context, Default::default(), lexer).unwrap();
+    context.assert_success();
+
+    let columns = |span: std::ops::Range<dm::Location>| (span.start.column, span.end.column);
+    assert_eq!(columns(expr.span()), (1, 20));
+    let args = match expr {
+        Expression::Base { term: Spanned { elem: Term::Call(_, args), .. }, .. } => args,
+        other => panic!("expected call, got {:?}", other),
+    };
+    assert_eq!(columns(args[0].span()), (5, 16));
+    assert_eq!(columns(args[1].span()), (18, 19));
+    match &args[0] {
+        Expression::Base { unary, term, follow } => {
+            assert_eq!(columns(unary[0].span()), (5, 6));
+            assert_eq!(columns(term.span()), (6, 9));
+            assert_eq!(columns(follow[0].span()), (9, 13));
+            assert_eq!(columns(follow[1].span()), (13, 16));
+        }
+        other => panic!("expected base expression, got {:?}", other),
+    }
+}
@@ -46,3 +46,114 @@ CLAMP(alpha - CLAMP(beta - 2, 0, beta), 3, alpha)
         Punct(RParen),
     ]);
 }
+
+#[test]
+fn expansion_trace() {
+    let ctx = dm::Context::default();
+    let mut pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), r#"
+#define ZERO 0
+#define CLAMP(VAL, MIN, MAX) clamp(VAL, MIN, MAX)
+
+CLAMP(alpha, ZERO, 1)
+"#);
+    pp.enable_expansion_trace();
+    while pp.next().is_some() {}
+    let trace = pp.take_expansion_trace().unwrap();
+
+    assert_eq!(trace.len(), 2);
+    assert_eq!(trace[0].name, "CLAMP");
+    assert_eq!(trace[0].parent, None);
+    assert_eq!(trace[0].args, Some(vec![
+        vec![Ident("alpha".into(), false)],
+        vec![Ident("ZERO".into(), false)],
+        vec![Int(1)],
+    ]));
+    assert_eq!(trace[1].name, "ZERO");
+    assert_eq!(trace[1].parent, Some(0));
+    assert_eq!(trace[1].args, None);
+    assert_eq!(trace[1].tokens, vec![Int(0)]);
+    assert_eq!(trace[1].depth(&trace), 1);
+}
+
+#[test]
+fn pragma_severity() {
+    let ctx = dm::Context::default();
+    let pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), r#"
+#pragma DefinedMacro disabled
+#define FOO 1
+#define FOO 2
+#pragma UndefineMissingDirective error
+#undef BAR
+#pragma NotARealPragma warning
+"#);
+    pp.for_each(drop);
+
+    let errors = ctx.errors();
+    let descriptions: Vec<_> = errors.iter().map(|e| (e.severity(), e.description())).collect();
+    assert_eq!(descriptions, vec![
+        (dm::Severity::Error, "macro undefined while not defined: BAR"),
+        (dm::Severity::Warning, "unknown #pragma: NotARealPragma"),
+    ]);
+}
+
+#[test]
+fn builtin_macros_defined() {
+    let ctx = dm::Context::default();
+    let pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), r#"
+#if defined(__MAIN__) && defined(__FILE__) && defined(__LINE__) && DM_VERSION >= 513
+yes
+#else
+no
+#endif
+"#);
+    let tokens: Vec<_> = pp.map(|loctok| loctok.token).filter(|t| *t != Punct(Newline)).collect();
+    assert_eq!(tokens, vec![Ident("yes".into(), false)]);
+}
+
+#[test]
+fn unnecessary_directives() {
+    let ctx = dm::Context::default();
+    let mut pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), r#"
+#define USED 1
+#define TESTED
+#define UNUSED 2
+#define TEMPORARY 3
+#undef TEMPORARY
+#define FILE_DIR .
+#ifdef TESTED
+#if 1 + 1 == 2
+#endif
+#if USED
+#endif
+#endif
+"#);
+    pp.enable_unnecessary_lints();
+    pp.for_each(drop);
+
+    let errors = ctx.errors();
+    let descriptions: Vec<_> = errors.iter().map(|e| (e.location().line, e.description())).collect();
+    assert_eq!(descriptions, vec![
+        (6, "macro undefined without being used: TEMPORARY"),
+        (4, "macro defined but never used: UNUSED"),
+        (9, "condition is always true"),
+    ]);
+    assert!(errors.iter().all(|e| e.severity() == dm::Severity::Hint && e.is_unnecessary()));
+}
+
+#[test]
+fn condition_changing_value() {
+    const INCLUDED: &str = "#if defined(SEEN)\n#endif\n#if defined(NEVER)\n#endif\n#define SEEN\n";
+    let ctx = dm::Context::default();
+    let mut pp = Preprocessor::from_buffer(&ctx, "macro_tests.rs".into(), "");
+    pp.push_file("included.dm".into(), INCLUDED.as_bytes());
+    pp.push_file("included.dm".into(), INCLUDED.as_bytes());
+    pp.enable_macro_condition_lints();
+    pp.for_each(drop);
+
+    let errors = ctx.errors();
+    let descriptions: Vec<_> = errors.iter()
+        .filter(|e| e.is_unnecessary())
+        .map(|e| (e.location().line, e.description()))
+        .collect();
+    assert_eq!(descriptions, vec![(3, "condition is always false")]);
+}
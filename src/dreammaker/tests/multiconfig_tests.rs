@@ -0,0 +1,100 @@
+extern crate dreammaker as dm;
+
+use dm::preprocessor::*;
+use dm::multiconfig::{Configuration, diagnose_configurations};
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+#[test]
+fn command_line_defines() {
+    let context = dm::Context::default();
+    let mut defines = DefineMap::with_builtins();
+    defines.apply_overrides(&context, &[
+        DefineOverride::define("TESTING"),
+        DefineOverride::define("LEVEL=2"),
+        DefineOverride::undefine("DM_VERSION"),
+    ]);
+    assert!(defines.contains_key("TESTING"));
+    assert!(defines.contains_key("LEVEL"));
+    assert!(!defines.contains_key("DM_VERSION"));
+    assert!(DefineMap::with_builtins().contains_key("DM_VERSION"));
+}
+
+#[test]
+fn parse_command_line_args() {
+    let mut rest = vec!["LEVEL=2".to_owned()].into_iter();
+    assert_eq!(DefineOverride::parse_arg("-DTESTING", &mut rest), Some(Ok(DefineOverride::define("TESTING"))));
+    assert_eq!(DefineOverride::parse_arg("-UDEBUG", &mut rest), Some(Ok(DefineOverride::undefine("DEBUG"))));
+    assert_eq!(DefineOverride::parse_arg("-e", &mut rest), None);
+    assert_eq!(DefineOverride::parse_arg("-D", &mut rest), Some(Ok(DefineOverride::define("LEVEL=2"))));
+    assert!(DefineOverride::parse_arg("-U", &mut rest).unwrap().is_err());
+}
+
+#[test]
+fn parse_configuration() {
+    let config = Configuration::parse("TESTING, LEVEL=2,!DEBUG");
+    assert_eq!(config.defines, vec![
+        DefineOverride::define("TESTING"),
+        DefineOverride::define("LEVEL=2"),
+        DefineOverride::undefine("DEBUG"),
+    ]);
+    assert_eq!(config.to_string(), "TESTING, LEVEL=2,!DEBUG");
+    assert_eq!(Configuration::parse("").to_string(), "(default)");
+}
+
+#[test]
+fn configuration_only_diagnostics() {
+    const CODE: &str = r#"
+#ifdef TESTING
+#error "only when testing"
+#endif
+#if LEVEL > 1
+#warn "only at high levels"
+#endif
+#warn "always"
+"#;
+    let context = dm::Context::default();
+    let configurations = vec![
+        Configuration::parse(""),
+        Configuration::parse("TESTING"),
+        Configuration::parse("LEVEL=2"),
+    ];
+    let count = diagnose_configurations(&context, &[DefineOverride::define("LEVEL=0")], &configurations, |run_context, defines| {
+        let mut pp = Preprocessor::from_buffer(run_context, "multiconfig_tests.rs".into(), CODE);
+        pp.set_define_overrides(defines);
+        let indents = IndentProcessor::new(run_context, pp);
+        Parser::new(run_context, indents).parse_object_tree();
+    });
+    assert_eq!(count, 2);
+
+    let errors = context.errors();
+    let mut descriptions: Vec<_> = errors.iter().map(|error| error.description().to_owned()).collect();
+    descriptions.sort();
+    assert_eq!(descriptions, vec![r#"#error "only when testing""#, r#"#warn "only at high levels""#]);
+}
+
+#[test]
+fn constant_conditions_across_configurations() {
+    const CODE: &str = r#"
+#if defined(TESTING)
+#endif
+#if LEVEL > 1
+#endif
+"#;
+    let context = dm::Context::default();
+    let configurations = vec![
+        Configuration::parse(""),
+        Configuration::parse("TESTING"),
+    ];
+    let count = diagnose_configurations(&context, &[DefineOverride::define("LEVEL=0")], &configurations, |run_context, defines| {
+        let mut pp = Preprocessor::from_buffer(run_context, "multiconfig_tests.rs".into(), CODE);
+        pp.set_define_overrides(defines);
+        pp.enable_macro_condition_lints();
+        pp.for_each(drop);
+    });
+    assert_eq!(count, 0);
+
+    let errors = context.errors();
+    let descriptions: Vec<_> = errors.iter().map(|error| (error.location().line, error.description())).collect();
+    assert_eq!(descriptions, vec![(4, "condition is always false")]);
+}
@@ -0,0 +1,70 @@
+extern crate dreammaker as dm;
+
+use dm::constants::Constant;
+use dm::objtree::{Comparison, ObjectTree, TypeQuery};
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+const CODE: &str = r#"
+/obj/item
+    var/w_class = 2
+    proc/attackby()
+/obj/item/small
+/obj/item/large
+    w_class = 4
+    name = "large item"
+/obj/item/large/huge
+    w_class = 5
+    attackby()
+/obj/machinery
+    proc/process()
+"#;
+
+fn parse(context: &dm::Context) -> ObjectTree {
+    let pp = Preprocessor::from_buffer(context, "query_tests.rs".into(), CODE);
+    let indents = IndentProcessor::new(context, pp);
+    Parser::new(context, indents).parse_object_tree()
+}
+
+fn paths(tree: &ObjectTree, query: &TypeQuery) -> Vec<String> {
+    tree.query(query).map(|ty| ty.path.clone()).collect()
+}
+
+#[test]
+fn builder_queries() {
+    let context = dm::Context::default();
+    let tree = parse(&context);
+
+    let query = TypeQuery::new().subtype_of("/obj/item").var("w_class", Comparison::Gt, 3);
+    assert_eq!(paths(&tree, &query), ["/obj/item/large", "/obj/item/large/huge"]);
+
+    let query = TypeQuery::new().defines_proc("attackby");
+    assert_eq!(paths(&tree, &query), ["/obj/item", "/obj/item/large/huge"]);
+
+    let query = TypeQuery::new().subtype_of("/obj").has_proc("process");
+    assert_eq!(paths(&tree, &query), ["/obj/machinery"]);
+}
+
+#[test]
+fn parsed_queries() {
+    let context = dm::Context::default();
+    let tree = parse(&context);
+
+    let query: TypeQuery = "/obj/item w_class<=2".parse().unwrap();
+    assert_eq!(paths(&tree, &query), ["/obj/item", "/obj/item/small"]);
+
+    let query: TypeQuery = "/obj !var:w_class".parse().unwrap();
+    assert_eq!(paths(&tree, &query), ["/obj", "/obj/machinery"]);
+
+    let query: TypeQuery = "name=\"large item\" path:huge".parse().unwrap();
+    assert_eq!(paths(&tree, &query), ["/obj/item/large/huge"]);
+    assert_eq!(query.var_names(), ["name"]);
+
+    let query: TypeQuery = "name=large".parse().unwrap();
+    assert_eq!(query.filters[0], dm::objtree::TypeFilter::Var(
+        "name".to_owned(), Comparison::Eq, Constant::String("large".to_owned())));
+
+    assert!("w_class~3".parse::<TypeQuery>().is_err());
+    assert!("name=\"unterminated".parse::<TypeQuery>().is_err());
+}
@@ -0,0 +1,69 @@
+extern crate dreammaker as dm;
+
+use dm::ast::*;
+use dm::objtree::Code;
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+
+fn parse_proc_body(context: &dm::Context, code: &'static str) -> Block {
+    let pp = Preprocessor::from_buffer(context, "statement_tests.rs".into(), code);
+    let indents = IndentProcessor::new(context, pp);
+    let mut parser = Parser::new(context, indents);
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    let proc = tree.root().get_proc("test").expect("missing proc");
+    match proc.get().code {
+        Code::Present(ref block) => block.clone(),
+        ref other => panic!("proc body did not parse: {:?}", other),
+    }
+}
+
+#[test]
+fn statement_error_recovery() {
+    let context = Default::default();
+    let block = parse_proc_body(&context, r#"
+/proc/test()
+    var/a = 1
+    a = 1 2
+    if (a)
+        a = * 3
+        a = 4
+    return a
+"#);
+    assert_eq!(context.errors().len(), 2);
+
+    let statements: Vec<_> = block.iter().map(|s| &s.elem).collect();
+    assert_eq!(statements.len(), 4);
+    assert!(match statements[0] { Statement::Var(_) => true, _ => false });
+    assert_eq!(*statements[1], Statement::Invalid);
+    match statements[2] {
+        Statement::If { arms, .. } => {
+            assert_eq!(arms[0].1.len(), 2);
+            assert_eq!(arms[0].1[0].elem, Statement::Invalid);
+            assert!(match arms[0].1[1].elem { Statement::Expr(_) => true, _ => false });
+        }
+        other => panic!("expected if statement, got {:?}", other),
+    }
+    assert!(match statements[3] { Statement::Return(Some(_)) => true, _ => false });
+}
+
+#[test]
+fn statement_spans() {
+    let context = Default::default();
+    let block = parse_proc_body(&context, r#"
+/proc/test()
+    return src.name + 1
+"#);
+    assert!(context.errors().is_empty());
+
+    assert_eq!(block[0].location.line, 3);
+    match &block[0].elem {
+        Statement::Return(Some(expr)) => {
+            let span = expr.span();
+            assert_eq!((span.start.line, span.start.column), (3, 12));
+            assert_eq!((span.end.line, span.end.column), (3, 24));
+        }
+        other => panic!("expected return statement, got {:?}", other),
+    }
+}
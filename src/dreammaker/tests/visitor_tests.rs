@@ -0,0 +1,83 @@
+extern crate dreammaker as dm;
+
+use dm::ast::*;
+use dm::objtree::Code;
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dm::Location;
+
+fn parse_proc_body(context: &dm::Context, code: &'static str) -> Block {
+    let pp = Preprocessor::from_buffer(context, "visitor_tests.rs".into(), code);
+    let indents = IndentProcessor::new(context, pp);
+    let mut parser = Parser::new(context, indents);
+    parser.enable_procs();
+    let tree = parser.parse_object_tree();
+    let proc = tree.root().get_proc("test").expect("missing proc");
+    match proc.get().code {
+        Code::Present(ref block) => block.clone(),
+        ref other => panic!("proc body did not parse: {:?}", other),
+    }
+}
+
+const CODE: &str = r#"
+/proc/test(a)
+    var/b = a + 1
+    for (var/i in list(a, b))
+        b += i
+    switch (a)
+        if (1 to b)
+            return new /obj { name = a }
+    return pick(a, b)
+"#;
+
+#[derive(Default)]
+struct Idents<'ast> {
+    names: Vec<&'ast str>,
+}
+
+impl<'ast> Visitor<'ast> for Idents<'ast> {
+    fn visit_term(&mut self, location: Location, term: &'ast Term) {
+        if let Term::Ident(name) = term {
+            self.names.push(name);
+        }
+        walk_term(self, location, term);
+    }
+}
+
+struct Rename;
+
+impl VisitorMut for Rename {
+    fn visit_term(&mut self, location: Location, term: &mut Term) {
+        if let Term::Ident(name) = term {
+            if name == "a" {
+                *name = "renamed".to_owned();
+            }
+        }
+        walk_term_mut(self, location, term);
+    }
+}
+
+#[test]
+fn visit_identifiers() {
+    let context = Default::default();
+    let block = parse_proc_body(&context, CODE);
+    assert!(context.errors().is_empty());
+
+    let mut idents = Idents::default();
+    idents.visit_block(&block);
+    assert_eq!(idents.names, ["a", "a", "b", "b", "i", "a", "b", "a", "a", "b"]);
+}
+
+#[test]
+fn rename_identifiers() {
+    let context = Default::default();
+    let mut block = parse_proc_body(&context, CODE);
+    assert!(context.errors().is_empty());
+
+    Rename.visit_block(&mut block);
+    let mut idents = Idents::default();
+    idents.visit_block(&block);
+    assert!(!idents.names.contains(&"a"));
+    assert_eq!(idents.names.iter().filter(|&&n| n == "renamed").count(), 5);
+}
@@ -0,0 +1,201 @@
+//! Supporting functions for the call hierarchy.
+
+use std::collections::BTreeMap;
+
+use lsp_types::{SymbolKind, TextDocumentPositionParams};
+
+use dm::annotation::Annotation;
+use dm::objtree::ProcRef;
+
+use crate::{Engine, invalid_request};
+use crate::extras::{CallHierarchyItem, CallHierarchyIncomingCall, CallHierarchyOutgoingCall};
+use crate::find_references::ProcKey;
+
+impl<'a> Engine<'a> {
+    /// Create the call hierarchy item for the proc which is defined or called
+    /// at the given position.
+    pub fn prepare_call_hierarchy(&mut self, params: &TextDocumentPositionParams) -> Result<Option<CallHierarchyItem>, jsonrpc::Error> {
+        match self.call_hierarchy_target(params)?.and_then(|key| key.resolve(&self.objtree)) {
+            Some(proc) => Ok(Some(self.call_hierarchy_item(proc)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn call_hierarchy_target(&mut self, params: &TextDocumentPositionParams) -> Result<Option<ProcKey>, jsonrpc::Error> {
+        let (_, file_id, annotations) = self.get_annotations(&params.text_document.uri)?;
+        let location = dm::Location {
+            file: file_id,
+            line: params.position.line as u32 + 1,
+            column: params.position.character as u16 + 1,
+        };
+        let iter = annotations.get_location(location);
+
+        // Calls are more specific than the proc definitions they're in, so
+        // look for those first.
+        for (_, annotation) in iter.clone() {
+            match annotation {
+                Annotation::UnscopedCall(proc_name) => {
+                    let (ty, _) = self.find_type_context(&iter);
+                    return Ok(ty.unwrap_or(self.objtree.root()).get_proc(proc_name).map(ProcKey::new));
+                },
+                Annotation::ScopedCall(priors, proc_name) => {
+                    return Ok(self.find_scoped_type(&iter, priors).and_then(|ty| ty.get_proc(proc_name)).map(ProcKey::new));
+                },
+                Annotation::ParentCall => {
+                    let (ty, proc_name) = self.find_type_context(&iter);
+                    return Ok(match (ty, proc_name) {
+                        (Some(ty), Some((proc_name, idx))) => self.proc_definition(&ty.path, proc_name, idx)
+                            .and_then(ProcRef::parent_proc)
+                            .map(ProcKey::new),
+                        _ => None,
+                    });
+                },
+                _ => {}
+            }
+        }
+
+        for (_, annotation) in iter.clone() {
+            match annotation {
+                Annotation::ProcHeader(path, idx) => {
+                    // chop off proc name and 'proc/' or 'verb/' if it's there
+                    let (proc_name, mut rest) = match path.split_last() {
+                        Some(split) => split,
+                        None => return Ok(None),
+                    };
+                    if let Some((kwd, most)) = rest.split_last() {
+                        if kwd == "proc" || kwd == "verb" {
+                            rest = most;
+                        }
+                    }
+                    return Ok(self.objtree.type_by_path(rest)
+                        .and_then(|ty| self.proc_definition(&ty.path, proc_name, *idx))
+                        .map(ProcKey::new));
+                },
+                Annotation::TypePath(parts) => {
+                    // '/datum/proc/foo'
+                    if let Some(result) = self.follow_type_path(&iter, parts) {
+                        if let Some((proc_name, _)) = result.proc {
+                            return Ok(result.ty.get_proc(proc_name).map(ProcKey::new));
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn proc_definition(&self, ty: &str, name: &str, idx: usize) -> Option<ProcRef> {
+        ProcKey {
+            ty: ty.to_owned(),
+            name: name.to_owned(),
+            idx,
+        }.resolve(&self.objtree)
+    }
+
+    pub fn call_hierarchy_item(&self, proc: ProcRef) -> Result<CallHierarchyItem, jsonrpc::Error> {
+        let ty = proc.ty();
+        let location = self.convert_location(proc.location, &[&ty.path, "/proc/", proc.name()])?;
+        Ok(CallHierarchyItem {
+            name: proc.name().to_owned(),
+            kind: if ty.is_root() {
+                SymbolKind::Function
+            } else if crate::is_constructor_name(proc.name()) {
+                SymbolKind::Constructor
+            } else {
+                SymbolKind::Method
+            },
+            detail: Some(proc.to_string()),
+            uri: location.uri,
+            range: location.range,
+            selection_range: location.range,
+            data: serde_json::to_value(ProcKey::new(proc)).ok(),
+        })
+    }
+
+    /// Find the proc a call hierarchy item was created for.
+    fn call_hierarchy_proc(&self, item: &CallHierarchyItem) -> Result<ProcRef, jsonrpc::Error> {
+        let key: ProcKey = match item.data {
+            Some(ref data) => serde_json::from_value(data.clone()).map_err(invalid_request)?,
+            None => return Err(invalid_request("call hierarchy item is missing its data")),
+        };
+        key.resolve(&self.objtree)
+            .ok_or_else(|| invalid_request(format!("{} no longer exists", item.detail.as_ref().unwrap_or(&item.name))))
+    }
+
+    /// Find the procs which call the given item, either directly or through
+    /// a call which may dispatch to it as an override.
+    pub fn incoming_calls(&self, item: &CallHierarchyItem) -> Result<Vec<CallHierarchyIncomingCall>, jsonrpc::Error> {
+        let target = self.call_hierarchy_proc(item)?;
+        let table = match self.references_table {
+            Some(ref table) => table,
+            None => return Ok(Vec::new()),
+        };
+
+        let key = ProcKey::new(target);
+        let mut callers: BTreeMap<&ProcKey, Vec<lsp_types::Range>> = BTreeMap::new();
+        for call in table.calls() {
+            if call.callee == key || (!call.exact && may_dispatch_to(&call.callee, target)) {
+                let range = self.convert_location(call.location, &[])?.range;
+                callers.entry(&call.caller).or_default().push(range);
+            }
+        }
+
+        let mut results = Vec::new();
+        for (caller, from_ranges) in callers {
+            if let Some(proc) = caller.resolve(&self.objtree) {
+                results.push(CallHierarchyIncomingCall {
+                    from: self.call_hierarchy_item(proc)?,
+                    from_ranges,
+                });
+            }
+        }
+        Ok(results)
+    }
+
+    /// Find the procs which the given item calls, including its parent if it
+    /// calls `..()`.
+    pub fn outgoing_calls(&self, item: &CallHierarchyItem) -> Result<Vec<CallHierarchyOutgoingCall>, jsonrpc::Error> {
+        let source = self.call_hierarchy_proc(item)?;
+        let table = match self.references_table {
+            Some(ref table) => table,
+            None => return Ok(Vec::new()),
+        };
+
+        let key = ProcKey::new(source);
+        let mut callees: BTreeMap<&ProcKey, Vec<lsp_types::Range>> = BTreeMap::new();
+        for call in table.calls() {
+            if call.caller == key {
+                let range = self.convert_location(call.location, &[])?.range;
+                callees.entry(&call.callee).or_default().push(range);
+            }
+        }
+
+        let mut results = Vec::new();
+        for (callee, from_ranges) in callees {
+            if let Some(proc) = callee.resolve(&self.objtree) {
+                results.push(CallHierarchyOutgoingCall {
+                    to: self.call_hierarchy_item(proc)?,
+                    from_ranges,
+                });
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// Check whether a call which statically resolves to `callee` may run
+/// `target` instead, because `target` overrides it.
+fn may_dispatch_to(callee: &ProcKey, target: ProcRef) -> bool {
+    if callee.name != target.name() || !target.is_externally_visible() {
+        return false;
+    }
+    let mut next = target.ty().parent_type();
+    while let Some(ty) = next {
+        if ty.path == callee.ty {
+            return true;
+        }
+        next = ty.parent_type();
+    }
+    false
+}
@@ -0,0 +1,369 @@
+//! Quick fixes for diagnostics.
+
+use std::collections::{BTreeSet, HashMap};
+
+use lsp_types::{CodeAction, CodeActionOrCommand, CodeActionParams, Diagnostic, Position, Range, TextEdit, WorkspaceEdit};
+
+use dm::{Component, Context, FileId, Fix, Location};
+use dm::annotation::Annotation;
+use dm::lexer::{Lexer, Punctuation, Token, SUPPRESS_COMMENT};
+
+use crate::{Engine, invalid_request, location_to_position};
+
+/// The parts of a diagnostic which quick fixes are computed from.
+struct Problem {
+    location: Location,
+    description: String,
+    component: Component,
+    notes: Vec<(Location, String)>,
+    fixes: Vec<Fix>,
+}
+
+impl<'a> Engine<'a> {
+    /// Find the quick fixes for the diagnostics in the given range.
+    pub fn code_actions(&mut self, params: &CodeActionParams) -> Result<Vec<CodeActionOrCommand>, jsonrpc::Error> {
+        let url = &params.text_document.uri;
+        let (real_file_id, file_id, annotations) = self.get_annotations(url)?;
+        let text = self.docs.get_contents(url).map_err(invalid_request)?.into_owned();
+        let lines: Vec<&str> = text.lines().collect();
+
+        let first_line = params.range.start.line as u32 + 1;
+        let last_line = params.range.end.line as u32 + 1;
+        let in_range = |location: Location| {
+            (location.file == real_file_id || location.file == file_id)
+                && location.line >= first_line
+                && location.line <= last_line
+        };
+
+        let mut problems = Vec::new();
+        for error in self.context.errors().iter() {
+            let notes: Vec<_> = error.notes().iter()
+                .map(|note| (note.location(), note.description().to_owned()))
+                .collect();
+            if in_range(error.location()) || notes.iter().any(|&(location, _)| in_range(location)) {
+                problems.push(Problem {
+                    location: error.location(),
+                    description: error.description().to_owned(),
+                    component: error.component(),
+                    notes,
+                    fixes: error.fixes().to_vec(),
+                });
+            }
+        }
+
+        let mut actions = Vec::new();
+        let mut suppressed_lines = BTreeSet::new();
+        for problem in problems.iter() {
+            let diagnostics = matching_diagnostics(&params.context.diagnostics, problem);
+
+            for fix in problem.fixes.iter() {
+                match *fix {
+                    Fix::CallParent if in_range(problem.location) => {
+                        if let Some(edit) = call_parent_edit(&lines, problem.location) {
+                            actions.push(quick_fix("Call the parent proc with ..()", url, vec![edit], &diagnostics));
+                        }
+                    }
+                    Fix::AddParameters(location, ref missing) if in_range(location) => {
+                        if let Some(edit) = add_parameters_edit(&lines, location, missing) {
+                            let title = format!("Add \"{}\" to the parameters", missing.join("\", \""));
+                            actions.push(quick_fix(&title, url, vec![edit], &diagnostics));
+                        }
+                    }
+                    Fix::SetVarType(location, ref name, ref path) if in_range(location) => {
+                        if let Some(edit) = set_var_type_edit(&lines, location, name, path) {
+                            let title = format!("Declare \"{}\" as {}", name, path);
+                            actions.push(quick_fix(&title, url, vec![edit], &diagnostics));
+                        }
+                    }
+                    Fix::DeclareVar(ref name) if in_range(problem.location) => {
+                        if let Some(edit) = declare_var_edit(&lines, problem.location, name) {
+                            let title = format!("Declare \"{}\" as a local var", name);
+                            actions.push(quick_fix(&title, url, vec![edit], &diagnostics));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if !in_range(problem.location) {
+                continue;
+            }
+
+            if problem.component == Component::DreamChecker {
+                let body_location = Location { file: file_id, ..problem.location };
+                let in_proc_body = annotations.get_location(body_location).any(|(_, annotation)| match annotation {
+                    Annotation::ProcBody(..) => true,
+                    _ => false,
+                });
+                if in_proc_body && self.unlint_defined(&text, Location { file: real_file_id, ..problem.location }) {
+                    if let Some(edits) = unlint_edits(&lines, problem.location) {
+                        actions.push(quick_fix("Wrap in UNLINT()", url, edits, &diagnostics));
+                    }
+                }
+            }
+
+            if suppressed_lines.insert(problem.location.line) {
+                if let Some(edit) = suppress_edit(&lines, problem.location) {
+                    let title = format!("Suppress diagnostics on this line with // {}", SUPPRESS_COMMENT);
+                    let diagnostics = params.context.diagnostics.iter()
+                        .filter(|diag| diag.range.start.line + 1 == problem.location.line as u64)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    actions.push(quick_fix(&title, url, vec![edit], &diagnostics));
+                }
+            }
+        }
+        Ok(actions)
+    }
+
+    /// Check whether the `UNLINT` macro is defined at the given location.
+    fn unlint_defined(&self, text: &str, location: Location) -> bool {
+        match self.defines {
+            Some(ref defines) => defines
+                .range(interval_tree::range(location, location))
+                .any(|(_, &(ref name, _))| name == "UNLINT"),
+            // without an environment, only this file's defines are known
+            None => text.lines().any(|line| {
+                let line = line.trim_start();
+                line.starts_with('#') && line[1..].trim_start().starts_with("define UNLINT(")
+            }),
+        }
+    }
+}
+
+fn quick_fix(title: &str, url: &url::Url, edits: Vec<TextEdit>, diagnostics: &[Diagnostic]) -> CodeActionOrCommand {
+    let mut changes = HashMap::new();
+    changes.insert(url.to_owned(), edits);
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.to_owned(),
+        kind: Some(lsp_types::code_action_kind::QUICKFIX.to_owned()),
+        diagnostics: if diagnostics.is_empty() { None } else { Some(diagnostics.to_vec()) },
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            .. Default::default()
+        }),
+        command: None,
+        is_preferred: None,
+    })
+}
+
+/// Find the client's diagnostics which correspond to a problem or its notes.
+fn matching_diagnostics(diagnostics: &[Diagnostic], problem: &Problem) -> Vec<Diagnostic> {
+    diagnostics.iter()
+        .filter(|diag| {
+            (diag.range.start == location_to_position(problem.location) && diag.message == problem.description)
+                || problem.notes.iter().any(|&(location, ref note)| {
+                    diag.range.start == location_to_position(location) && diag.message == *note
+                })
+        })
+        .cloned()
+        .collect()
+}
+
+fn insert(location: Location, text: String) -> TextEdit {
+    let position = location_to_position(location);
+    TextEdit {
+        range: Range::new(position, position),
+        new_text: text,
+    }
+}
+
+fn line_at<'t>(lines: &[&'t str], location: Location) -> Option<&'t str> {
+    lines.get(location.line.checked_sub(1)? as usize).cloned()
+}
+
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches(&[' ', '\t'][..]).len()]
+}
+
+/// Find the parentheses of the parameter list on a proc's header line, as
+/// byte offsets.
+fn parameter_list(line: &str, location: Location) -> Option<(usize, usize)> {
+    let start = (location.column as usize).checked_sub(1)?;
+    let open = start + line.get(start..)?.find('(')?;
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, ch) in line[open..].char_indices() {
+        match (quote, ch) {
+            (Some(q), _) if ch == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(ch),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((open, open + i));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Insert a `..()` call at the start of the proc declared at `location`.
+fn call_parent_edit(lines: &[&str], location: Location) -> Option<TextEdit> {
+    let header = line_at(lines, location)?;
+    let (_, close) = parameter_list(header, location)?;
+    let rest = header[close + 1..].trim();
+    if !rest.is_empty() && !rest.starts_with("//") {
+        // the body is on the same line, or in braces
+        return None;
+    }
+
+    let header_indent = indentation(header);
+    let next = lines.iter()
+        .enumerate()
+        .skip(location.line as usize)
+        .find(|&(_, line)| !line.trim().is_empty());
+    let (line, indent) = match next {
+        Some((i, line)) if indentation(line).len() > header_indent.len() => (i, indentation(line).to_owned()),
+        _ => {
+            let unit = if header_indent.starts_with(' ') { " ".repeat(header_indent.len()) } else { "\t".to_owned() };
+            (location.line as usize, format!("{}{}", header_indent, unit))
+        }
+    };
+    if line < lines.len() {
+        Some(insert(Location { line: line as u32 + 1, column: 1, ..location }, format!("{}..()\n", indent)))
+    } else {
+        let end = Location { column: header.len() as u16 + 1, ..location };
+        Some(insert(end, format!("\n{}..()", indent)))
+    }
+}
+
+/// Add parameters to the end of the parameter list of the proc declared at
+/// `location`.
+fn add_parameters_edit(lines: &[&str], location: Location, names: &[String]) -> Option<TextEdit> {
+    let header = line_at(lines, location)?;
+    let (open, close) = parameter_list(header, location)?;
+    let existing = header[open + 1..close].trim_end();
+    let text = if existing.trim().is_empty() {
+        names.join(", ")
+    } else {
+        format!(", {}", names.join(", "))
+    };
+    let column = open + 1 + existing.len();
+    Some(insert(Location { column: column as u16 + 1, ..location }, text))
+}
+
+/// Turn an assignment to an undefined var into a declaration.
+fn declare_var_edit(lines: &[&str], location: Location, name: &str) -> Option<TextEdit> {
+    let line = line_at(lines, location)?;
+    let column = (location.column as usize).checked_sub(1)?;
+    if !line.get(..column)?.trim().is_empty() {
+        return None;
+    }
+    let rest = line[column..].strip_prefix(name)?.trim_start();
+    if !rest.starts_with('=') || rest.starts_with("==") {
+        return None;
+    }
+    Some(insert(location, "var/".to_owned()))
+}
+
+/// Put a type path before the name of the var declared at `location`.
+fn set_var_type_edit(lines: &[&str], location: Location, name: &str, path: &str) -> Option<TextEdit> {
+    let line = line_at(lines, location)?;
+    let mut from = (location.column as usize).checked_sub(1)?;
+    while let Some(found) = line.get(from..)?.find(name) {
+        let begin = from + found;
+        let end = begin + name.len();
+        let after_slash = line[..begin].ends_with('/');
+        let whole_word = !line[end..].starts_with(|ch: char| ch == '_' || ch.is_ascii_alphanumeric());
+        if after_slash && whole_word {
+            let column = Location { column: begin as u16 + 1, ..location };
+            return Some(insert(column, format!("{}/", path.trim_start_matches('/'))));
+        }
+        from = end;
+    }
+    None
+}
+
+/// Wrap the expression at `location` in `UNLINT()`.
+fn unlint_edits(lines: &[&str], location: Location) -> Option<Vec<TextEdit>> {
+    let line = line_at(lines, location)?;
+    let column = (location.column as usize).checked_sub(1)?;
+    if column > line.len() || !line.is_char_boundary(column) {
+        return None;
+    }
+
+    // Back up to the start of the term the location is in.
+    let bytes = line.as_bytes();
+    let mut start = column;
+    while start > 0 {
+        let ch = bytes[start - 1];
+        if ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'.' || ch == b':' || ch == b'?' {
+            start -= 1;
+        } else if ch == b')' || ch == b']' {
+            let (open, close) = if ch == b')' { (b'(', b')') } else { (b'[', b']') };
+            let mut depth = 0;
+            let mut i = start;
+            loop {
+                i = i.checked_sub(1)?;
+                if bytes[i] == close {
+                    depth += 1;
+                } else if bytes[i] == open {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            start = i;
+        } else {
+            break;
+        }
+    }
+
+    // Read forward to the end of the term.
+    let context = Context::default();
+    let mut depth = 0usize;
+    let mut end = None;
+    let mut call = false;
+    for token in Lexer::new(&context, FileId::default(), line[start..].bytes().map(Ok)) {
+        match token.token {
+            Token::Punct(Punctuation::LParen) | Token::Punct(Punctuation::LBracket) => depth += 1,
+            Token::Punct(Punctuation::RParen) | Token::Punct(Punctuation::RBracket) if depth == 0 => break,
+            Token::Punct(Punctuation::RParen) | Token::Punct(Punctuation::RBracket) => depth -= 1,
+            Token::Punct(Punctuation::Newline) => break,
+            Token::Ident(..) |
+            Token::Punct(Punctuation::Dot) |
+            Token::Punct(Punctuation::Colon) |
+            Token::Punct(Punctuation::CloseColon) |
+            Token::Punct(Punctuation::SafeDot) |
+            Token::Punct(Punctuation::SafeColon) |
+            Token::Punct(Punctuation::Super) => {}
+            _ if depth > 0 => {}
+            _ => break,
+        }
+        call = token.token == Token::Punct(Punctuation::RParen);
+        end = Some(start + token.end.column as usize - 1);
+    }
+    let end = end?;
+    // Only calls are wrapped, as an assignment to an `UNLINT()` won't work.
+    if depth > 0 || !call || end <= column {
+        return None;
+    }
+
+    Some(vec![
+        insert(Location { column: start as u16 + 1, ..location }, "UNLINT(".to_owned()),
+        insert(Location { column: end as u16 + 1, ..location }, ")".to_owned()),
+    ])
+}
+
+/// Add a suppression comment to the end of the line at `location`.
+fn suppress_edit(lines: &[&str], location: Location) -> Option<TextEdit> {
+    if location.is_builtins() {
+        return None;
+    }
+    let line = line_at(lines, location)?;
+    // A comment already on the line would swallow the new one.
+    if line.contains("//") || line.contains("/*") || line.trim_end().ends_with('\\') {
+        return None;
+    }
+    let end = line.trim_end().len();
+    let position = Position::new(location.line as u64 - 1, end as u64);
+    Some(TextEdit {
+        range: Range::new(position, Position::new(position.line, line.len() as u64)),
+        new_text: format!("  // {}", SUPPRESS_COMMENT),
+    })
+}
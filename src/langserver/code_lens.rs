@@ -0,0 +1,197 @@
+//! Code lenses showing how procs and vars are overridden and used.
+
+use std::collections::HashSet;
+
+use lsp_types::{CodeLens, Command};
+use url::Url;
+
+use dm::Location;
+use dm::annotation::Annotation;
+use dm::objtree::TypeRef;
+
+use crate::{Engine, location_to_range};
+use crate::find_references::ProcKey;
+
+/// The command which code lenses run, with the document URI, the position
+/// of the lens, and the locations it counts as arguments. This is the
+/// VS Code command which shows a list of locations in a peek view.
+pub const SHOW_REFERENCES: &str = "editor.action.showReferences";
+
+/// The key of a var or proc definition, sent as a lens's `data` so that the
+/// counts are only computed once the client resolves the lens.
+#[derive(Debug, Serialize, Deserialize)]
+struct LensData {
+    uri: Url,
+    /// The path of the type the definition is on.
+    ty: String,
+    name: String,
+    /// The index of the definition on its type, for procs.
+    proc_idx: Option<usize>,
+}
+
+impl<'a> Engine<'a> {
+    /// Place a lens on each var and proc definition in a file, to be filled
+    /// in by `resolve_code_lens`.
+    pub fn code_lenses(&mut self, url: &Url) -> Result<Vec<CodeLens>, jsonrpc::Error> {
+        let (_, _, annotations) = self.get_annotations(url)?;
+
+        let mut definitions = Vec::new();
+        for (span, annotation) in annotations.iter() {
+            let (path, proc_idx, keywords) = match annotation {
+                Annotation::ProcHeader(path, idx) => (path, Some(*idx), &["proc", "verb"][..]),
+                Annotation::Variable(path) => (path, None, &["var"][..]),
+                _ => continue,
+            };
+            let name = match path.last() {
+                Some(name) => name,
+                None => continue,
+            };
+            definitions.push((span.start, LensData {
+                uri: url.clone(),
+                ty: self.declaring_type(path, keywords).path.clone(),
+                name: name.to_owned(),
+                proc_idx,
+            }));
+        }
+        definitions.sort_by_key(|&(start, _)| start);
+        definitions.dedup_by_key(|&mut (start, _)| start);
+
+        Ok(definitions.into_iter().map(|(start, data)| CodeLens {
+            range: location_to_range(start),
+            command: None,
+            data: Some(serde_json::to_value(data).unwrap()),
+        }).collect())
+    }
+
+    /// Fill in a lens with the definition's overrides, its references, and
+    /// what it overrides.
+    pub fn resolve_code_lens(&mut self, mut lens: CodeLens) -> Result<CodeLens, jsonrpc::Error> {
+        let data: LensData = match lens.data.take() {
+            Some(data) => serde_json::from_value(data).map_err(crate::invalid_request)?,
+            None => return Err(crate::invalid_request("code lens has no data")),
+        };
+        let objtree = self.objtree.clone();
+        let ty = match objtree.find(&data.ty) {
+            Some(ty) => ty,
+            None => return Err(crate::invalid_request(format!("no such type {}", data.ty))),
+        };
+        let name = data.name.as_str();
+        let mut parts = Vec::new();
+        let mut locations = Vec::new();
+
+        // (type, location) of the definitions which share this one's
+        // declaration, i.e. it and the ones it overrides or is overridden by
+        let mut defined: Vec<(TypeRef, Location)> = Vec::new();
+        let declaration;
+        let mut overridden = None;
+        if let Some(idx) = data.proc_idx {
+            let key = ProcKey {
+                ty: data.ty.clone(),
+                name: name.to_owned(),
+                idx,
+            };
+            let proc = match key.resolve(&objtree) {
+                Some(proc) => proc,
+                None => return Err(crate::invalid_request(format!("no such proc {}/proc/{}", data.ty, name))),
+            };
+            declaration = ty.get_proc_declaration(name).map(|decl| decl.id);
+            objtree.root().recurse(&mut |each| {
+                if let Some(first) = each.procs.get(name).and_then(|proc| proc.value.first()) {
+                    if each.get_proc_declaration(name).map(|decl| decl.id) == declaration {
+                        defined.push((each, first.location));
+                    }
+                }
+            });
+
+            let mut next = proc.parent_proc();
+            while let Some(parent) = next {
+                if parent.ty() != ty {
+                    let path = format!("{}/proc/{}", parent.ty().path, name);
+                    let location = self.convert_location(parent.location, &[&parent.ty().path, "/proc/", name])?;
+                    overridden = Some((path, location));
+                    break;
+                }
+                next = parent.parent_proc();
+            }
+        } else {
+            declaration = ty.get_var_declaration(name).map(|decl| decl.id);
+            objtree.root().recurse(&mut |each| {
+                if let Some(var) = each.vars.get(name) {
+                    if each.get_var_declaration(name).map(|decl| decl.id) == declaration {
+                        defined.push((each, var.value.location));
+                    }
+                }
+            });
+
+            let mut next = ty.parent_type();
+            while let Some(parent) = next {
+                if let Some(var) = parent.vars.get(name) {
+                    let path = format!("{}/var/{}", parent.path, name);
+                    let location = self.convert_location(var.value.location, &[&parent.path, "/var/", name])?;
+                    overridden = Some((path, location));
+                    break;
+                }
+                next = parent.parent_type();
+            }
+        }
+
+        if let Some((_, ref location)) = overridden {
+            locations.push(location.clone());
+        }
+
+        let mut overrides = 0;
+        for &(each, location) in defined.iter() {
+            if each != ty && each.is_subtype_of(&ty) {
+                overrides += 1;
+                locations.push(self.convert_location(location, &[])?);
+            }
+        }
+        if overrides > 0 {
+            parts.push(plural(overrides, "override"));
+        }
+
+        if let (Some(table), Some(id)) = (self.references_table.as_ref(), declaration) {
+            // References are tracked per declaration, so they include uses
+            // of every override, not just this one.
+            let defined_at: HashSet<Location> = defined.iter().map(|&(_, location)| location).collect();
+            let mut references = 0;
+            for &location in table.find_references(id, false) {
+                if !defined_at.contains(&location) {
+                    references += 1;
+                    locations.push(self.convert_location(location, &[])?);
+                }
+            }
+            if defined.len() > 1 {
+                parts.push(format!("{} including overrides", plural(references, "reference")));
+            } else {
+                parts.push(plural(references, "reference"));
+            }
+        }
+
+        if let Some((path, _)) = overridden {
+            parts.push(format!("overrides {}", path));
+        }
+
+        if parts.is_empty() {
+            parts.push("no overrides".to_owned());
+        }
+        lens.command = Some(Command {
+            title: parts.join(" \u{b7} "),
+            command: SHOW_REFERENCES.to_owned(),
+            arguments: Some(vec![
+                serde_json::to_value(&data.uri).unwrap(),
+                serde_json::to_value(lens.range.start).unwrap(),
+                serde_json::to_value(locations).unwrap(),
+            ]),
+        });
+        Ok(lens)
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
@@ -13,96 +13,35 @@ struct WalkProc<'o> {
     local_vars: &'o mut Vec<String>,
 }
 
-impl<'o> WalkProc<'o> {
-    fn visit_block(&mut self, block: &'o [Spanned<Statement>]) {
-        for stmt in block.iter() {
-            self.visit_statement(stmt.location, &stmt.elem);
-        }
-    }
-
-    fn visit_statement(&mut self, location: Location, statement: &'o Statement) {
+impl<'o, 'ast> Visitor<'ast> for WalkProc<'o> {
+    fn visit_statement(&mut self, location: Location, statement: &'ast Statement) {
         match statement {
-            Statement::Expr(_) => {},
-            Statement::Return(_) => {},
-            Statement::Throw(_) => {},
-            Statement::While { block, .. } => self.visit_block(block),
-            Statement::DoWhile { block, .. } => self.visit_block(block),
-            Statement::If { arms, else_arm } => {
-                for &(_, ref block) in arms.iter() {
-                    self.visit_block(block);
-                }
-                if let Some(else_arm) = else_arm {
-                    self.visit_block(else_arm);
-                }
-            },
-            Statement::ForLoop { init, test: _, inc, block } => {
-                if let Some(init) = init {
-                    self.visit_statement(location, init);
-                }
-                if let Some(inc) = inc {
-                    self.visit_statement(location, inc);
-                }
-                self.visit_block(block);
-            },
-            Statement::ForList { block, var_type, name, .. } => {
-                if let Some(var_type) = var_type {
-                    self.visit_var(location, var_type, name, None);
-                }
-                self.visit_block(block);
-            },
-            Statement::ForRange { var_type, name, start, end: _, step: _, block } => {
-                if let Some(var_type) = var_type {
-                    self.visit_var(location, var_type, name, Some(start));
-                }
-                self.visit_block(block);
-            },
-            Statement::Var(var) => self.visit_var_stmt(location, var),
-            Statement::Vars(vars) => {
-                for each in vars.iter() {
-                    self.visit_var_stmt(location, each);
-                }
-            },
-            Statement::Setting { .. } => {},
-            Statement::Spawn { delay: _, block } => {
-                self.visit_block(block);
-            },
-            Statement::Switch { input: _, cases, default } => {
-                for &(_, ref block) in cases.iter() {
-                    self.visit_block(block);
-                }
-                if let Some(default) = default {
-                    self.visit_block(default);
-                }
+            Statement::ForList { var_type: Some(_), name, .. } |
+            Statement::ForRange { var_type: Some(_), name, .. } => {
+                self.local_vars.push(name.to_owned());
             },
             Statement::TryCatch { try_block, catch_params, catch_block } => {
+                // Locals are numbered in declaration order, so the catch
+                // parameters come between the two blocks.
                 self.visit_block(try_block);
                 for caught in catch_params.iter() {
-                    let (var_name, mut type_path) = match caught.split_last() {
-                        Some(x) => x,
-                        None => continue
-                    };
-                    match type_path.split_first() {
-                        Some((first, rest)) if first == "var" => type_path = rest,
-                        _ => {}
+                    if let Some(var_name) = caught.last() {
+                        self.local_vars.push(var_name.to_owned());
                     }
-                    let var_type: VarType = type_path.iter().map(ToOwned::to_owned).collect();
-                    self.visit_var(location, &var_type, var_name, None);
                 }
                 self.visit_block(catch_block);
+                return;
             },
-            Statement::Continue(_) => {},
-            Statement::Break(_) => {},
-            Statement::Goto(_) => {},
-            Statement::Label { name: _, block } => self.visit_block(block),
-            Statement::Del(_) => {},
+            _ => {},
         }
+        walk_statement(self, location, statement);
     }
 
-    fn visit_var_stmt(&mut self, location: Location, var: &'o VarStatement) {
-        self.visit_var(location, &var.var_type, &var.name, var.value.as_ref())
+    fn visit_var_statement(&mut self, _location: Location, var: &'ast VarStatement) {
+        self.local_vars.push(var.name.to_owned());
     }
 
-    fn visit_var(&mut self, _location: Location, _var_type: &VarType, name: &str, _value: Option<&'o Expression>) {
-        self.local_vars.push(name.to_owned());
+    fn visit_expression(&mut self, _location: Location, _expression: &'ast Expression) {
+        // Expressions cannot declare locals.
     }
 }
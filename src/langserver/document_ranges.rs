@@ -0,0 +1,539 @@
+//! Supporting functions for document highlights, folding ranges, and
+//! selection ranges.
+
+use std::collections::HashSet;
+
+use lsp_types::{
+    DocumentHighlight, DocumentHighlightKind, FoldingRange, FoldingRangeKind,
+    Position, Range, TextDocumentPositionParams,
+};
+use url::Url;
+
+use dm::{Context, FileId, Location};
+use dm::annotation::{Annotation, AnnotationTree};
+use dm::ast::{self, Expression, Spanned, Statement, Visitor};
+use dm::lexer::{LocatedToken, Punctuation, Token};
+use dm::objtree::{Code, ObjectTree};
+
+use crate::{Engine, invalid_request, location_to_position, url_to_path};
+use crate::extras::SelectionRange;
+use crate::rename::{Symbol, word_at};
+
+/// Operators which assign to the expression on their left.
+const ASSIGNMENTS: &[&str] = &["+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<=", ">>=", "++", "--"];
+
+impl<'a> Engine<'a> {
+    /// Find every mention of the symbol at the given position in its file,
+    /// marking whether each reads or writes it.
+    pub fn document_highlights(&mut self, params: &TextDocumentPositionParams) -> Result<Vec<DocumentHighlight>, jsonrpc::Error> {
+        let (_, file_id, annotations) = self.get_annotations(&params.text_document.uri)?;
+        let location = Location {
+            file: file_id,
+            line: params.position.line as u32 + 1,
+            column: params.position.character as u16 + 1,
+        };
+        let text = self.docs.get_contents(&params.text_document.uri).map_err(invalid_request)?;
+        let lines: Vec<&str> = text.lines().collect();
+        let (symbol, name, start) = match self.find_symbol_at(&annotations, &lines, location) {
+            Some(found) => found,
+            None => return Ok(Vec::new()),
+        };
+        // Parameters are only the same if they belong to the same proc.
+        let proc = match symbol {
+            Symbol::Parameter => enclosing_proc(&annotations, start),
+            _ => None,
+        };
+
+        let mut seen = HashSet::new();
+        let mut highlights = Vec::new();
+        for (span, annotation) in annotations.iter() {
+            let declaration = match annotation {
+                Annotation::InSequence(_) |
+                Annotation::MacroDefinition(_) => true,
+                Annotation::UnscopedCall(_) |
+                Annotation::UnscopedVar(_) |
+                Annotation::ScopedCall(..) |
+                Annotation::ScopedVar(..) |
+                Annotation::MacroUse(..) => false,
+                _ => continue,
+            };
+            if word_at(&lines, span.start) != name || !seen.insert(span.start) {
+                continue;
+            }
+            match self.find_rename_symbol(&annotations, span.start) {
+                Some((ref found, _, start)) if *found == symbol && start == span.start => {
+                    if proc.is_some() && enclosing_proc(&annotations, start) != proc {
+                        continue;
+                    }
+                    let kind = match symbol {
+                        Symbol::Type(_) => DocumentHighlightKind::Text,
+                        _ if declaration || is_assigned(&lines, start, &name) => DocumentHighlightKind::Write,
+                        _ => DocumentHighlightKind::Read,
+                    };
+                    let start = location_to_position(start);
+                    let end = Position::new(start.line, start.character + name.len() as u64);
+                    highlights.push(DocumentHighlight {
+                        range: Range::new(start, end),
+                        kind: Some(kind),
+                    });
+                }
+                _ => {}
+            }
+        }
+        highlights.sort_by_key(|highlight| highlight.range.start);
+        Ok(highlights)
+    }
+
+    /// Find the foldable blocks, `#if` branches, and comments of a file.
+    pub fn folding_ranges(&mut self, url: &Url) -> Result<Vec<FoldingRange>, jsonrpc::Error> {
+        let (_, _, annotations) = self.get_annotations(url)?;
+        let text = self.docs.get_contents(url).map_err(invalid_request)?;
+        let mut folds = fold_text(&text);
+
+        for (span, annotation) in annotations.iter() {
+            if let Annotation::Conditional(_) = annotation {
+                // The branch ends at its `#else` or `#endif`, which stays
+                // visible.
+                let start_line = span.start.line.saturating_sub(1) as u64;
+                let end_line = span.end.line.saturating_sub(2) as u64;
+                if end_line > start_line {
+                    folds.push(folding_range(start_line, end_line, Some(FoldingRangeKind::Region)));
+                }
+            }
+        }
+
+        folds.sort_by_key(|fold| (fold.start_line, fold.end_line));
+        folds.dedup_by(|a, b| a.start_line == b.start_line && a.end_line == b.end_line);
+        Ok(folds)
+    }
+
+    /// Find the ranges which selections at the given positions can expand
+    /// to: from expressions, to statements, to blocks, to procs, to types.
+    pub fn selection_ranges(&mut self, url: &Url, positions: &[Position]) -> Result<Vec<SelectionRange>, jsonrpc::Error> {
+        let (_, file_id, annotations) = self.get_annotations(url)?;
+        let text = self.docs.get_contents(url).map_err(invalid_request)?.into_owned();
+        let lines: Vec<&str> = text.split('\n').map(|line| line.trim_end_matches('\r')).collect();
+        let ends = CodeEnds::new(&text);
+
+        // Statements aren't annotated, so parse the file again to find them.
+        let context = Context::default();
+        let (scratch_file_id, objtree) = self.parse_statements(&context, url)?;
+        let mut blocks = Vec::new();
+        objtree.root().recurse(&mut |ty| {
+            for proc in ty.iter_self_procs() {
+                if proc.get().location.file == scratch_file_id {
+                    if let Code::Present(ref code) = proc.get().code {
+                        blocks.push(code);
+                    }
+                }
+            }
+        });
+
+        let mut results = Vec::with_capacity(positions.len());
+        for &position in positions {
+            let mut finder = SelectionFinder {
+                ends: &ends,
+                position,
+                ranges: Vec::new(),
+            };
+            if let Some(word) = word_range(&lines, position) {
+                finder.ranges.push(word);
+            }
+            for block in blocks.iter() {
+                finder.visit_block(block);
+            }
+
+            let location = Location {
+                file: file_id,
+                line: position.line as u32 + 1,
+                column: position.character as u16 + 1,
+            };
+            for (span, annotation) in annotations.get_location(location) {
+                match annotation {
+                    Annotation::ProcHeader(path, idx) => {
+                        let body_start = Location { column: span.end.column + 1, ..span.end };
+                        let end = annotations.get_location(body_start)
+                            .find(|&(_, each)| match each {
+                                Annotation::ProcBody(body_path, body_idx) => body_path == path && body_idx == idx,
+                                _ => false,
+                            })
+                            .map_or(span.end, |(body, _)| block_end(&lines, body.end));
+                        finder.ranges.push(Range::new(location_to_position(span.start), ends.before(past(end))));
+                    },
+                    Annotation::ProcBody(path, idx) => {
+                        let start = annotations.get_location(span.start.pred())
+                            .find(|&(_, each)| match each {
+                                Annotation::ProcHeader(header_path, header_idx) => header_path == path && header_idx == idx,
+                                _ => false,
+                            })
+                            .map_or(span.start, |(header, _)| header.start);
+                        finder.ranges.push(Range::new(location_to_position(start), ends.before(past(block_end(&lines, span.end)))));
+                    },
+                    Annotation::TreeBlock(_) => {
+                        // Blocks start at their brace, which is implied by
+                        // indentation after the type's path.
+                        let start = location_to_position(span.start);
+                        let indent = lines.get(start.line as usize)
+                            .map_or(0, |line| line.len() - line.trim_start().len());
+                        let start = Position::new(start.line, std::cmp::min(start.character, indent as u64));
+                        finder.ranges.push(Range::new(start, ends.before(past(block_end(&lines, span.end)))));
+                    },
+                    _ => {}
+                }
+            }
+
+            results.push(selection_chain(position, finder.ranges));
+        }
+        Ok(results)
+    }
+
+    /// Parse a document's procs into a fresh object tree.
+    fn parse_statements(&self, context: &Context, url: &Url) -> Result<(FileId, ObjectTree), jsonrpc::Error> {
+        let (file_id, mut preprocessor) = match self.root {
+            Some(ref root) => {
+                let path = url_to_path(url)?;
+                let root = url_to_path(root)?;
+                let stripped = match path.strip_prefix(&root) {
+                    Ok(path) => path,
+                    Err(_) => return Err(invalid_request(format!("outside workspace: {}", url))),
+                };
+                let defines = match self.defines {
+                    Some(ref d) => d,
+                    None => return Err(invalid_request("no preprocessor history")),
+                };
+                let mut preprocessor = match self.context.get_file(stripped) {
+                    Some(id) => defines.branch_at_file(id, context),
+                    None => defines.branch_at_end(context),
+                };
+                let contents = self.docs.read(url).map_err(invalid_request)?;
+                (preprocessor.push_file(stripped.to_owned(), contents), preprocessor)
+            },
+            None => {
+                let filename = url.to_string();
+                let contents = self.docs.get_contents(url).map_err(invalid_request)?.into_owned();
+                let mut preprocessor = dm::preprocessor::Preprocessor::from_buffer(context, filename.clone().into(), contents);
+                preprocessor.set_define_overrides(&self.define_overrides);
+                (context.get_file(filename.as_ref()).unwrap_or_default(), preprocessor)
+            },
+        };
+
+        let indent = dm::indents::IndentProcessor::new(context, &mut preprocessor);
+        let mut parser = dm::parser::Parser::new(context, indent);
+        parser.enable_procs();
+        Ok((file_id, parser.parse_object_tree()))
+    }
+}
+
+/// Find the proc whose header or body contains the given location.
+fn enclosing_proc(annotations: &AnnotationTree, location: Location) -> Option<(Vec<String>, usize)> {
+    for (_, annotation) in annotations.get_location(location) {
+        match annotation {
+            Annotation::ProcHeader(path, idx) |
+            Annotation::ProcBody(path, idx) => return Some((path.clone(), *idx)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Check whether the name at the given location is assigned to, by `=`, an
+/// augmented assignment, or `++` or `--` on either side.
+fn is_assigned(lines: &[&str], start: Location, name: &str) -> bool {
+    let line = match lines.get(start.line.saturating_sub(1) as usize) {
+        Some(line) => line,
+        None => return false,
+    };
+    let column = start.column.saturating_sub(1) as usize;
+    let (before, after) = match (line.get(..column), line.get(column + name.len()..)) {
+        (Some(before), Some(after)) => (before.trim_end(), after.trim_start()),
+        _ => return false,
+    };
+    before.ends_with("++") || before.ends_with("--")
+        || (after.starts_with('=') && !after.starts_with("=="))
+        || ASSIGNMENTS.iter().any(|op| after.starts_with(op))
+}
+
+fn folding_range(start_line: u64, end_line: u64, kind: Option<FoldingRangeKind>) -> FoldingRange {
+    FoldingRange {
+        start_line,
+        start_character: None,
+        end_line,
+        end_character: None,
+        kind,
+    }
+}
+
+/// Find the indentation blocks, braced blocks, and comments of a file.
+fn fold_text(text: &str) -> Vec<FoldingRange> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    // Lines which begin inside a multi-line string or comment.
+    let mut inside = vec![false; lines.len()];
+
+    let tokens = crate::lex_scratch(text);
+
+    let mut folds = Vec::new();
+    // Indentation blocks, by their first line and its indentation.
+    let mut blocks: Vec<(u64, usize)> = Vec::new();
+    let mut braces: Vec<u64> = Vec::new();
+    let mut parens = 0usize;
+    let mut directive = false;
+    let mut last_code_line = 0u64;
+    let mut prev: Option<&LocatedToken> = None;
+    for token in tokens.iter() {
+        let line = token.location.line as u64 - 1;
+        if line as usize >= lines.len() {
+            break;
+        }
+        let at_line_head = prev.map_or(true, is_newline);
+
+        // A block comment swallows the newlines it covers.
+        if let Some(prev) = prev {
+            let from = if is_newline(prev) { prev.location.line } else { prev.end.line - 1 } as u64;
+            if line > from && lines[from as usize].contains("/*") {
+                for each in inside[from as usize + 1..=line as usize].iter_mut() {
+                    *each = true;
+                }
+                folds.push(folding_range(from, line, Some(FoldingRangeKind::Comment)));
+            }
+        }
+
+        let end_line = std::cmp::min(token.end.line as u64 - 1, lines.len() as u64 - 1);
+        match token.token {
+            Token::Punct(Punctuation::Newline) => {
+                directive = false;
+                prev = Some(token);
+                continue;
+            },
+            Token::DocComment(_) => {
+                if end_line > line {
+                    for each in inside[line as usize + 1..=end_line as usize].iter_mut() {
+                        *each = true;
+                    }
+                    folds.push(folding_range(line, end_line, Some(FoldingRangeKind::Comment)));
+                }
+                prev = Some(token);
+                continue;
+            },
+            _ => {}
+        }
+
+        if at_line_head && token.token == Token::Punct(Punctuation::Hash) {
+            directive = true;
+        } else if at_line_head && parens == 0 && !directive {
+            let text = lines[line as usize];
+            let width = text.len() - text.trim_start_matches(&[' ', '\t'][..]).len();
+            while let Some(&(start, block_width)) = blocks.last() {
+                if block_width < width {
+                    break;
+                }
+                blocks.pop();
+                if last_code_line > start {
+                    folds.push(folding_range(start, last_code_line, None));
+                }
+            }
+            blocks.push((line, width));
+        }
+
+        if end_line > line {
+            for each in inside[line as usize + 1..=end_line as usize].iter_mut() {
+                *each = true;
+            }
+        }
+        match token.token {
+            Token::Punct(Punctuation::LParen) => parens += 1,
+            Token::Punct(Punctuation::RParen) => parens = parens.saturating_sub(1),
+            Token::Punct(Punctuation::LBrace) => braces.push(line),
+            Token::Punct(Punctuation::RBrace) => if let Some(start) = braces.pop() {
+                // The closing brace stays visible.
+                if line > start + 1 {
+                    folds.push(folding_range(start, line - 1, None));
+                }
+            },
+            _ => {}
+        }
+        if !directive {
+            last_code_line = std::cmp::max(last_code_line, end_line);
+        }
+        prev = Some(token);
+    }
+    for (start, _) in blocks {
+        if last_code_line > start {
+            folds.push(folding_range(start, last_code_line, None));
+        }
+    }
+
+    // Runs of line comments.
+    let mut run_start = None;
+    for (i, line) in lines.iter().enumerate() {
+        let comment = !inside[i] && line.trim_start().starts_with("//");
+        match (comment, run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(start)) => {
+                if i - 1 > start {
+                    folds.push(folding_range(start as u64, i as u64 - 1, Some(FoldingRangeKind::Comment)));
+                }
+                run_start = None;
+            },
+            _ => {}
+        }
+    }
+    if let Some(start) = run_start {
+        if lines.len() - 1 > start {
+            folds.push(folding_range(start as u64, lines.len() as u64 - 1, Some(FoldingRangeKind::Comment)));
+        }
+    }
+    folds
+}
+
+fn is_newline(token: &LocatedToken) -> bool {
+    token.token == Token::Punct(Punctuation::Newline)
+}
+
+/// The ends of the tokens of a file, other than those of comments and
+/// preprocessor directives, in order.
+struct CodeEnds(Vec<Position>);
+
+impl CodeEnds {
+    fn new(text: &str) -> CodeEnds {
+        let mut ends = Vec::new();
+        let mut directive = false;
+        let mut at_line_head = true;
+        for token in crate::lex_scratch(text) {
+            match token.token {
+                Token::Punct(Punctuation::Newline) => {
+                    directive = false;
+                    at_line_head = true;
+                    continue;
+                },
+                Token::DocComment(_) => continue,
+                Token::Punct(Punctuation::Hash) if at_line_head => directive = true,
+                _ => {}
+            }
+            at_line_head = false;
+            if !directive {
+                ends.push(location_to_position(token.end));
+            }
+        }
+        CodeEnds(ends)
+    }
+
+    /// Find the end of the last token which ends by the given position.
+    ///
+    /// Spans often end where the next token starts, which may be lines later
+    /// and past comments or directives.
+    fn before(&self, end: Position) -> Position {
+        match self.0.binary_search(&end) {
+            Ok(_) => end,
+            Err(0) => end,
+            Err(idx) => self.0[idx - 1],
+        }
+    }
+}
+
+/// Extend the inclusive end of a block to cover its closing brace, if it was
+/// written rather than implied by indentation.
+fn block_end(lines: &[&str], end: Location) -> Location {
+    let line = lines.get(end.line.saturating_sub(1) as usize).cloned().unwrap_or("");
+    if line.as_bytes().get(end.column as usize) == Some(&b'}') {
+        Location { column: end.column + 1, ..end }
+    } else {
+        end
+    }
+}
+
+/// Convert the inclusive end of an annotation to the position past it.
+fn past(end: Location) -> Position {
+    Position::new(end.line.saturating_sub(1) as u64, end.column as u64)
+}
+
+/// Find the identifier which the given position is within or just after.
+fn word_range(lines: &[&str], position: Position) -> Option<Range> {
+    let line = lines.get(position.line as usize)?;
+    let column = position.character as usize;
+    if column > line.len() || !line.is_char_boundary(column) {
+        return None;
+    }
+    let is_ident = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let start = line[..column].rfind(|ch: char| !is_ident(ch)).map_or(0, |i| i + 1);
+    let end = line[column..].find(|ch: char| !is_ident(ch)).map_or(line.len(), |i| column + i);
+    if start == end {
+        return None;
+    }
+    Some(Range::new(Position::new(position.line, start as u64), Position::new(position.line, end as u64)))
+}
+
+/// Link the ranges containing a position from the outermost inwards,
+/// skipping any which don't nest.
+fn selection_chain(position: Position, mut ranges: Vec<Range>) -> SelectionRange {
+    ranges.retain(|range| range.start <= position && position <= range.end);
+    // innermost first
+    ranges.sort_by(|a, b| b.start.cmp(&a.start).then(a.end.cmp(&b.end)));
+    ranges.dedup();
+
+    let mut chain: Vec<Range> = Vec::new();
+    for range in ranges {
+        if chain.last().map_or(true, |last| range.start <= last.start && last.end <= range.end) {
+            chain.push(range);
+        }
+    }
+
+    let mut result = None;
+    for range in chain.into_iter().rev() {
+        result = Some(Box::new(SelectionRange {
+            range,
+            parent: result,
+        }));
+    }
+    match result {
+        Some(result) => *result,
+        None => SelectionRange {
+            range: Range::new(position, position),
+            parent: None,
+        },
+    }
+}
+
+/// Collects the ranges of the statements, blocks, and expressions which
+/// contain a position.
+struct SelectionFinder<'l> {
+    ends: &'l CodeEnds,
+    position: Position,
+    ranges: Vec<Range>,
+}
+
+impl<'l> SelectionFinder<'l> {
+    fn push(&mut self, range: Range) -> bool {
+        if range.start < range.end && range.start <= self.position && self.position <= range.end {
+            self.ranges.push(range);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<'ast, 'l> Visitor<'ast> for SelectionFinder<'l> {
+    fn visit_block(&mut self, block: &'ast [Spanned<Statement>]) {
+        let (first, last) = match (block.first(), block.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return,
+        };
+        let range = Range::new(location_to_position(first.location), self.ends.before(location_to_position(last.end)));
+        if !self.push(range) {
+            return;
+        }
+        for statement in block.iter() {
+            let range = Range::new(location_to_position(statement.location), self.ends.before(location_to_position(statement.end)));
+            if self.push(range) {
+                ast::walk_statement(self, statement.location, &statement.elem);
+            }
+        }
+    }
+
+    fn visit_expression(&mut self, location: Location, expression: &'ast Expression) {
+        let span = expression.span();
+        if self.push(Range::new(location_to_position(span.start), location_to_position(span.end))) {
+            ast::walk_expression(self, location, expression);
+        }
+    }
+}
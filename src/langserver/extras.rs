@@ -68,3 +68,245 @@ pub struct StartDebuggerParams {
 pub struct StartDebuggerResult {
     pub port: u16,
 }
+
+pub enum Overrides {}
+impl Request for Overrides {
+    const METHOD: &'static str = "experimental/dreammaker/overrides";
+    type Params = lsp_types::TextDocumentPositionParams;
+    type Result = Option<OverridesResult>;
+}
+#[derive(Debug, Serialize)]
+pub struct OverridesResult {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub overrides: Vec<Override>,
+}
+#[derive(Debug, Serialize)]
+pub struct Override {
+    pub type_path: String,
+    pub location: lsp_types::Location,
+    pub declaration: bool,
+    /// The var's value or the proc's parameters on this type, if known.
+    pub value: Option<String>,
+}
+
+// ----------------------------------------------------------------------------
+// Newer protocol features which lsp_types doesn't know about yet
+
+/// `initialize`, answered with capabilities missing from `lsp_types`.
+pub enum InitializeExtended {}
+impl Request for InitializeExtended {
+    const METHOD: &'static str = Initialize::METHOD;
+    type Params = lsp_types::InitializeParams;
+    type Result = InitializeExtendedResult;
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeExtendedResult {
+    pub capabilities: ExtendedServerCapabilities,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_info: Option<lsp_types::ServerInfo>,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtendedServerCapabilities {
+    #[serde(flatten)]
+    pub base: lsp_types::ServerCapabilities,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub semantic_tokens_provider: Option<SemanticTokensOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub call_hierarchy_provider: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_hierarchy_provider: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inlay_hint_provider: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection_range_provider: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensOptions {
+    pub legend: SemanticTokensLegend,
+    pub range: bool,
+    pub full: bool,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensLegend {
+    pub token_types: Vec<String>,
+    pub token_modifiers: Vec<String>,
+}
+
+pub enum SemanticTokensFull {}
+impl Request for SemanticTokensFull {
+    const METHOD: &'static str = "textDocument/semanticTokens/full";
+    type Params = SemanticTokensParams;
+    type Result = Option<SemanticTokens>;
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+}
+
+pub enum SemanticTokensRange {}
+impl Request for SemanticTokensRange {
+    const METHOD: &'static str = "textDocument/semanticTokens/range";
+    type Params = SemanticTokensRangeParams;
+    type Result = Option<SemanticTokens>;
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensRangeParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    pub range: lsp_types::Range,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokens {
+    /// Five integers per token: line delta, start delta, length, type, and
+    /// modifier bits.
+    pub data: Vec<u32>,
+}
+
+pub enum CallHierarchyPrepare {}
+impl Request for CallHierarchyPrepare {
+    const METHOD: &'static str = "textDocument/prepareCallHierarchy";
+    type Params = lsp_types::TextDocumentPositionParams;
+    type Result = Option<Vec<CallHierarchyItem>>;
+}
+
+pub enum CallHierarchyIncomingCalls {}
+impl Request for CallHierarchyIncomingCalls {
+    const METHOD: &'static str = "callHierarchy/incomingCalls";
+    type Params = CallHierarchyCallsParams;
+    type Result = Option<Vec<CallHierarchyIncomingCall>>;
+}
+
+pub enum CallHierarchyOutgoingCalls {}
+impl Request for CallHierarchyOutgoingCalls {
+    const METHOD: &'static str = "callHierarchy/outgoingCalls";
+    type Params = CallHierarchyCallsParams;
+    type Result = Option<Vec<CallHierarchyOutgoingCall>>;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: lsp_types::Url,
+    pub range: lsp_types::Range,
+    pub selection_range: lsp_types::Range,
+    /// Identifies the proc when the item is sent back to us.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+#[derive(Debug, Deserialize)]
+pub struct CallHierarchyCallsParams {
+    pub item: CallHierarchyItem,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyIncomingCall {
+    pub from: CallHierarchyItem,
+    pub from_ranges: Vec<lsp_types::Range>,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyOutgoingCall {
+    pub to: CallHierarchyItem,
+    pub from_ranges: Vec<lsp_types::Range>,
+}
+
+pub enum TypeHierarchyPrepare {}
+impl Request for TypeHierarchyPrepare {
+    const METHOD: &'static str = "textDocument/prepareTypeHierarchy";
+    type Params = lsp_types::TextDocumentPositionParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+}
+
+pub enum TypeHierarchySupertypes {}
+impl Request for TypeHierarchySupertypes {
+    const METHOD: &'static str = "typeHierarchy/supertypes";
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+}
+
+pub enum TypeHierarchySubtypes {}
+impl Request for TypeHierarchySubtypes {
+    const METHOD: &'static str = "typeHierarchy/subtypes";
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: lsp_types::Url,
+    pub range: lsp_types::Range,
+    pub selection_range: lsp_types::Range,
+    /// Identifies the type when the item is sent back to us.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+#[derive(Debug, Deserialize)]
+pub struct TypeHierarchyParams {
+    pub item: TypeHierarchyItem,
+}
+
+pub enum InlayHintRequest {}
+impl Request for InlayHintRequest {
+    const METHOD: &'static str = "textDocument/inlayHint";
+    type Params = InlayHintParams;
+    type Result = Option<Vec<InlayHint>>;
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    pub range: lsp_types::Range,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: lsp_types::Position,
+    pub label: String,
+    /// One of the `inlay_hint_kind` constants.
+    pub kind: u8,
+    pub padding_left: bool,
+    pub padding_right: bool,
+}
+
+pub mod inlay_hint_kind {
+    pub const TYPE: u8 = 1;
+    pub const PARAMETER: u8 = 2;
+}
+
+pub enum SelectionRangeRequest {}
+impl Request for SelectionRangeRequest {
+    const METHOD: &'static str = "textDocument/selectionRange";
+    type Params = SelectionRangeParams;
+    type Result = Option<Vec<SelectionRange>>;
+}
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionRangeParams {
+    pub text_document: lsp_types::TextDocumentIdentifier,
+    pub positions: Vec<lsp_types::Position>,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionRange {
+    pub range: lsp_types::Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Box<SelectionRange>>,
+}
@@ -1,6 +1,6 @@
 //! The symbol table used for "Find References" support.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use dm::Location;
 use dm::objtree::*;
@@ -8,14 +8,20 @@ use dm::ast::*;
 
 pub struct ReferencesTable {
     uses: HashMap<SymbolId, Vec<Location>>,
+    calls: Vec<Call>,
     symbols: SymbolIdSource,
+    /// Names of vars and procs accessed with `:` on a value whose static
+    /// type does not have them, whose uses therefore can't be resolved.
+    approximate: HashSet<String>,
 }
 
 impl ReferencesTable {
     pub fn new(objtree: &ObjectTree) -> Self {
         let mut tab = ReferencesTable {
             uses: Default::default(),
+            calls: Default::default(),
             symbols: SymbolIdSource::new(SymbolIdCategory::LocalVars),
+            approximate: Default::default(),
         };
 
         // Insert the "definition" locations for the types and such
@@ -67,6 +73,17 @@ impl ReferencesTable {
         }
     }
 
+    /// Get every call made from a proc body to a known proc.
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    /// Check whether some uses of vars or procs with the given name were only
+    /// made through `:` and may be missing from the table.
+    pub fn is_approximate(&self, name: &str) -> bool {
+        self.approximate.contains(name)
+    }
+
     fn new_symbol(&mut self, location: Location) -> SymbolId {
         let id = self.symbols.allocate();
         self.uses.insert(id, vec![location]);
@@ -78,6 +95,45 @@ impl ReferencesTable {
     }
 }
 
+/// A single definition of a proc, independent of the object tree's lifetime.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ProcKey {
+    /// The path of the type the proc is defined on.
+    pub ty: String,
+    pub name: String,
+    /// Which of the type's definitions of the proc this is.
+    pub idx: usize,
+}
+
+impl ProcKey {
+    pub fn new(proc: ProcRef) -> ProcKey {
+        ProcKey {
+            ty: proc.ty().path.clone(),
+            name: proc.name().to_owned(),
+            idx: proc.index(),
+        }
+    }
+
+    pub fn resolve<'o>(&self, objtree: &'o ObjectTree) -> Option<ProcRef<'o>> {
+        objtree.find(&self.ty)?
+            .iter_self_procs()
+            .find(|proc| proc.name() == self.name && proc.index() == self.idx)
+    }
+}
+
+/// A call from one proc to another, or a reference to a proc by its path
+/// from another proc's body.
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub caller: ProcKey,
+    /// The proc the call resolves to statically.
+    pub callee: ProcKey,
+    pub location: Location,
+    /// Whether the call can't dispatch to overrides of the callee, as with
+    /// `..()` and `new`.
+    pub exact: bool,
+}
+
 #[derive(Debug, Clone)]
 enum StaticType<'o> {
     None,
@@ -111,6 +167,64 @@ struct WalkProc<'o> {
     local_vars: HashMap<String, Local<'o>>,
 }
 
+impl<'o> Visitor<'o> for WalkProc<'o> {
+    fn visit_statement(&mut self, location: Location, statement: &'o Statement) {
+        match statement {
+            Statement::Return(_) => {
+                let dot = self.local_vars.get(".").unwrap().symbol;
+                self.tab.use_symbol(dot, location);
+                walk_statement(self, location, statement);
+            },
+            Statement::ForList { in_list, block, var_type, name, .. } => {
+                if let Some(in_list) = in_list {
+                    self.visit_expression(location, in_list, None);
+                }
+                if let Some(var_type) = var_type {
+                    self.visit_var(location, var_type, name, None);
+                }
+                self.visit_block(block);
+            },
+            Statement::ForRange { var_type, name, start, end, step, block } => {
+                self.visit_expression(location, end, None);
+                if let Some(step) = step {
+                    self.visit_expression(location, step, None);
+                }
+                if let Some(var_type) = var_type {
+                    self.visit_var(location, var_type, name, Some(start));
+                }
+                self.visit_block(block);
+            },
+            Statement::Setting { .. } => {},
+            Statement::TryCatch { try_block, catch_params, catch_block } => {
+                self.visit_block(try_block);
+                for caught in catch_params.iter() {
+                    let (var_name, mut type_path) = match caught.split_last() {
+                        Some(x) => x,
+                        None => continue
+                    };
+                    match type_path.split_first() {
+                        Some((first, rest)) if first == "var" => type_path = rest,
+                        _ => {}
+                    }
+                    let var_type: VarType = type_path.iter().map(ToOwned::to_owned).collect();
+                    self.visit_var(location, &var_type, var_name, None);
+                }
+                self.visit_block(catch_block);
+            },
+            _ => walk_statement(self, location, statement),
+        }
+    }
+
+    fn visit_var_statement(&mut self, location: Location, var: &'o VarStatement) {
+        self.visit_var(location, &var.var_type, &var.name, var.value.as_ref())
+    }
+
+    fn visit_expression(&mut self, location: Location, expression: &'o Expression) {
+        // Expressions are walked by hand, as their static types are needed.
+        WalkProc::visit_expression(self, location, expression, None);
+    }
+}
+
 impl<'o> WalkProc<'o> {
     fn from_proc(tab: &'o mut ReferencesTable, objtree: &'o ObjectTree, proc: ProcRef<'o>) -> Self {
         let mut local_vars = HashMap::new();
@@ -179,130 +293,6 @@ impl<'o> WalkProc<'o> {
         self.visit_block(block);
     }
 
-    fn visit_block(&mut self, block: &'o [Spanned<Statement>]) {
-        for stmt in block.iter() {
-            self.visit_statement(stmt.location, &stmt.elem);
-        }
-    }
-
-    fn visit_statement(&mut self, location: Location, statement: &'o Statement) {
-        match statement {
-            Statement::Expr(expr) => { self.visit_expression(location, expr, None); },
-            Statement::Return(expr) => {
-                let dot = self.local_vars.get(".").unwrap().symbol;
-                self.tab.use_symbol(dot, location);
-                if let Some(expr) = expr {
-                    self.visit_expression(location, expr, None);
-                }
-            },
-            Statement::Throw(expr) => { self.visit_expression(location, expr, None); },
-            Statement::While { condition, block } => {
-                self.visit_expression(location, condition, None);
-                self.visit_block(block);
-            },
-            Statement::DoWhile { block, condition } => {
-                self.visit_block(block);
-                self.visit_expression(location, condition, None);
-            },
-            Statement::If { arms, else_arm } => {
-                for &(ref condition, ref block) in arms.iter() {
-                    self.visit_expression(location, condition, None);
-                    self.visit_block(block);
-                }
-                if let Some(else_arm) = else_arm {
-                    self.visit_block(else_arm);
-                }
-            },
-            Statement::ForLoop { init, test, inc, block } => {
-                if let Some(init) = init {
-                    self.visit_statement(location, init);
-                }
-                if let Some(test) = test {
-                    self.visit_expression(location, test, None);
-                }
-                if let Some(inc) = inc {
-                    self.visit_statement(location, inc);
-                }
-                self.visit_block(block);
-            },
-            Statement::ForList { in_list, block, var_type, name, .. } => {
-                if let Some(in_list) = in_list {
-                    self.visit_expression(location, in_list, None);
-                }
-                if let Some(var_type) = var_type {
-                    self.visit_var(location, var_type, name, None);
-                }
-                self.visit_block(block);
-            },
-            Statement::ForRange { var_type, name, start, end, step, block } => {
-                self.visit_expression(location, end, None);
-                if let Some(step) = step {
-                    self.visit_expression(location, step, None);
-                }
-                if let Some(var_type) = var_type {
-                    self.visit_var(location, var_type, name, Some(start));
-                }
-                self.visit_block(block);
-            },
-            Statement::Var(var) => self.visit_var_stmt(location, var),
-            Statement::Vars(vars) => {
-                for each in vars.iter() {
-                    self.visit_var_stmt(location, each);
-                }
-            },
-            Statement::Setting { .. } => {},
-            Statement::Spawn { delay, block } => {
-                if let Some(delay) = delay {
-                    self.visit_expression(location, delay, None);
-                }
-                self.visit_block(block);
-            },
-            Statement::Switch { input, cases, default } => {
-                self.visit_expression(location, input, None);
-                for &(ref case, ref block) in cases.iter() {
-                    for case_part in case.iter() {
-                        match case_part {
-                            dm::ast::Case::Exact(expr) => { self.visit_expression(location, expr, None); },
-                            dm::ast::Case::Range(start, end) => {
-                                self.visit_expression(location, start, None);
-                                self.visit_expression(location, end, None);
-                            }
-                        }
-                    }
-                    self.visit_block(block);
-                }
-                if let Some(default) = default {
-                    self.visit_block(default);
-                }
-            },
-            Statement::TryCatch { try_block, catch_params, catch_block } => {
-                self.visit_block(try_block);
-                for caught in catch_params.iter() {
-                    let (var_name, mut type_path) = match caught.split_last() {
-                        Some(x) => x,
-                        None => continue
-                    };
-                    match type_path.split_first() {
-                        Some((first, rest)) if first == "var" => type_path = rest,
-                        _ => {}
-                    }
-                    let var_type: VarType = type_path.iter().map(ToOwned::to_owned).collect();
-                    self.visit_var(location, &var_type, var_name, None);
-                }
-                self.visit_block(catch_block);
-            },
-            Statement::Continue(_) => {},
-            Statement::Break(_) => {},
-            Statement::Goto(_) => {},
-            Statement::Label { name: _, block } => self.visit_block(block),
-            Statement::Del(expr) => { self.visit_expression(location, expr, None); },
-        }
-    }
-
-    fn visit_var_stmt(&mut self, location: Location, var: &'o VarStatement) {
-        self.visit_var(location, &var.var_type, &var.name, var.value.as_ref())
-    }
-
     fn visit_var(&mut self, location: Location, var_type: &VarType, name: &str, value: Option<&'o Expression>) {
         let ty = self.static_type(location, &var_type.type_path);
         self.use_type(location, &ty);
@@ -339,7 +329,7 @@ impl<'o> WalkProc<'o> {
                     ty = self.visit_follow(each.location, ty, &each.elem);
                 }
                 for each in unary.iter().rev() {
-                    ty = self.visit_unary(ty, *each);
+                    ty = self.visit_unary(ty, each.elem);
                 }
                 ty
             },
@@ -515,6 +505,17 @@ impl<'o> WalkProc<'o> {
                 if let Some(decl) = nav.ty().get_proc_declaration(proc.name()) {
                     self.tab.use_symbol(decl.id, location);
                 }
+                // Proc references in a proc body are nearly always passed to
+                // `call()` or a callback, so count them as calls. Calling one
+                // on an object runs that object's override.
+                if let Some(caller) = self.proc {
+                    self.tab.calls.push(Call {
+                        caller: ProcKey::new(caller),
+                        callee: ProcKey::new(proc),
+                        location,
+                        exact: false,
+                    });
+                }
             } else {
                 // Use the type
                 self.tab.use_symbol(nav.ty().id, location);
@@ -545,34 +546,40 @@ impl<'o> WalkProc<'o> {
                     _ => StaticType::None,
                 }
             },
-            Follow::Field(_, name) => {
+            Follow::Field(kind, name) => {
                 if let Some(ty) = lhs.basic_type() {
                     if let Some(decl) = ty.get_var_declaration(name) {
                         self.tab.use_symbol(decl.id, location);
-                        self.static_type(location, &decl.var_type.type_path)
-                    } else {
-                        StaticType::None
+                        return self.static_type(location, &decl.var_type.type_path);
                     }
-                } else {
-                    StaticType::None
                 }
+                self.unresolved_field(*kind, name);
+                StaticType::None
             },
-            Follow::Call(_, name, arguments) => {
+            Follow::Call(kind, name, arguments) => {
                 if let Some(ty) = lhs.basic_type() {
                     if let Some(proc) = ty.get_proc(name) {
-                        self.visit_call(location, ty, proc, arguments, false)
-                    } else {
-                        self.visit_arguments(location, arguments);
-                        StaticType::None
+                        return self.visit_call(location, ty, proc, arguments, false);
                     }
-                } else {
-                    self.visit_arguments(location, arguments);
-                    StaticType::None
                 }
+                self.unresolved_field(*kind, name);
+                self.visit_arguments(location, arguments);
+                StaticType::None
             },
         }
     }
 
+    fn unresolved_field(&mut self, kind: IndexKind, name: &str) {
+        // `a.b` on an unknown type is already an error, but `a:b` is allowed
+        // to refer to anything.
+        match kind {
+            IndexKind::Colon | IndexKind::SafeColon => {
+                self.tab.approximate.insert(name.to_owned());
+            }
+            _ => {}
+        }
+    }
+
     fn visit_unary(&mut self, _rhs: StaticType<'o>, _op: UnaryOp) -> StaticType<'o> {
         // TODO: mark usage of operatorX procs
         StaticType::None
@@ -583,11 +590,19 @@ impl<'o> WalkProc<'o> {
         StaticType::None
     }
 
-    fn visit_call(&mut self, location: Location, src: TypeRef<'o>, proc: ProcRef, args: &'o [Expression], _is_exact: bool) -> StaticType<'o> {
+    fn visit_call(&mut self, location: Location, src: TypeRef<'o>, proc: ProcRef, args: &'o [Expression], is_exact: bool) -> StaticType<'o> {
         // register use of symbol
         if let Some(decl) = src.get_proc_declaration(proc.name()) {
             self.tab.use_symbol(decl.id, location);
         }
+        if let Some(caller) = self.proc {
+            self.tab.calls.push(Call {
+                caller: ProcKey::new(caller),
+                callee: ProcKey::new(proc),
+                location,
+                exact: is_exact,
+            });
+        }
 
         // identify and register kwargs used
         for arg in args {
@@ -650,3 +665,62 @@ impl<'o> WalkProc<'o> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use dm::Context;
+
+    use super::*;
+
+    const CODE: &str = r#"
+/obj/item
+    proc/zap()
+    proc/arm()
+        call(src, .proc/zap)()
+        var/P = /obj/item/proc/zap
+        call(src, P)()
+    proc/fire()
+        zap()
+        ..()
+
+/obj/item/widget
+    zap()
+        ..()
+
+/obj/item/gadget
+    var/callback = /obj/item/proc/zap
+"#;
+
+    fn calls_from(table: &ReferencesTable, caller: &str) -> Vec<(String, u32, bool)> {
+        let mut calls: Vec<_> = table.calls().iter()
+            .filter(|call| format!("{}/proc/{}", call.caller.ty, call.caller.name) == caller)
+            .map(|call| (format!("{}/proc/{}", call.callee.ty, call.callee.name), call.location.line, call.exact))
+            .collect();
+        calls.sort();
+        calls
+    }
+
+    #[test]
+    fn proc_paths_are_calls() {
+        let context = Context::default();
+        let pp = dm::preprocessor::Preprocessor::from_buffer(&context, "calls.dm".into(), CODE);
+        let indent = dm::indents::IndentProcessor::new(&context, pp);
+        let mut parser = dm::parser::Parser::new(&context, indent);
+        parser.enable_procs();
+        let objtree = parser.parse_object_tree();
+        let table = ReferencesTable::new(&objtree);
+
+        assert_eq!(calls_from(&table, "/obj/item/proc/arm"), vec![
+            ("/obj/item/proc/zap".to_owned(), 5, false),
+            ("/obj/item/proc/zap".to_owned(), 6, false),
+        ]);
+        assert_eq!(calls_from(&table, "/obj/item/proc/fire"), vec![
+            ("/obj/item/proc/zap".to_owned(), 9, false),
+        ]);
+        assert_eq!(calls_from(&table, "/obj/item/widget/proc/zap"), vec![
+            ("/obj/item/proc/zap".to_owned(), 14, true),
+        ]);
+        // a var's initial value is not in any proc, so it calls nothing
+        assert!(table.calls().iter().all(|call| call.caller.ty != "/obj/item/gadget"));
+    }
+}
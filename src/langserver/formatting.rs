@@ -0,0 +1,372 @@
+//! Whitespace formatting for DM source files.
+//!
+//! Only whitespace is ever changed: indentation is normalized to the
+//! client's preferred unit, runs of spaces between tokens are collapsed, and
+//! trailing whitespace is removed. Lines whose indentation is not meaningful
+//! to the compiler, such as preprocessor directives, continuation lines, and
+//! the insides of multi-line strings and comments, keep their indentation.
+
+use lsp_types::{FormattingOptions, Position, Range, TextEdit};
+
+use dm::lexer::{LocatedToken, Token, Punctuation};
+
+/// What the lexer tells us about a single line.
+#[derive(Default, Clone)]
+struct LineInfo {
+    /// Whether a token begins the line, as opposed to a comment or nothing.
+    code: bool,
+    /// Whether the line is a preprocessor directive.
+    directive: bool,
+    /// Whether the line begins inside parentheses or after a `\`, where
+    /// indentation is not significant.
+    continued: bool,
+    /// Whether the line begins inside a multi-line string or comment.
+    inside: bool,
+    /// Whether the line ends inside a multi-line string.
+    in_string: bool,
+}
+
+/// A line of the input, without its line terminator.
+struct Line<'a> {
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    /// Convert a byte offset within this line to an LSP position.
+    fn position(&self, line: usize, offset: usize) -> Position {
+        let character = self.text[..offset].chars().map(|ch| ch.len_utf16() as u64).sum();
+        Position::new(line as u64, character)
+    }
+
+    fn edit(&self, line: usize, start: usize, end: usize, new_text: String) -> TextEdit {
+        TextEdit {
+            range: Range::new(self.position(line, start), self.position(line, end)),
+            new_text,
+        }
+    }
+}
+
+/// Compute the edits which normalize the whitespace of an entire document.
+///
+/// The edits are returned in order and never overlap.
+pub fn format_document(text: &str, options: &FormattingOptions) -> Vec<TextEdit> {
+    let lines = split_lines(text);
+    let mut info = vec![LineInfo::default(); lines.len()];
+
+    let tokens = crate::lex_scratch(text);
+
+    // Edits to the spacing between tokens, by line.
+    let mut spacing: Vec<Vec<(usize, usize, &'static str)>> = vec![Vec::new(); lines.len()];
+    let mut parens = 0usize;
+    let mut prev: Option<&LocatedToken> = None;
+    for token in tokens.iter() {
+        let line = token.location.line as usize - 1;
+        if line >= lines.len() {
+            break;
+        }
+        let at_line_head = prev.map_or(true, is_newline);
+
+        // A block comment or a `\` continuation swallows newlines, so the
+        // lines it covers and the line it ends on are left alone.
+        let (first_covered, expected) = match prev {
+            None => (0, 0),
+            Some(prev) if is_newline(prev) => (prev.location.line as usize, prev.location.line as usize),
+            Some(prev) => (prev.end.line as usize, prev.end.line as usize - 1),
+        };
+        if line > expected {
+            for each in info[first_covered..=line].iter_mut() {
+                each.inside = true;
+            }
+        }
+
+        // Multi-line strings and doc comments.
+        let end_line = (token.end.line as usize - 1).min(lines.len() - 1);
+        if end_line > line && !is_newline(token) {
+            for each in info[line + 1..=end_line].iter_mut() {
+                each.inside = true;
+            }
+            if is_string(&token.token) {
+                for each in info[line..end_line].iter_mut() {
+                    each.in_string = true;
+                }
+            }
+        }
+
+        if at_line_head && !is_newline(token) {
+            info[line].code = true;
+            info[line].continued = parens > 0;
+            info[line].directive = token.token == Token::Punct(Punctuation::Hash);
+        }
+
+        if let Some(prev) = prev {
+            if !at_line_head && !is_newline(token) && !info[line].directive
+                && prev.end.line == token.location.line
+                && token.location.line == token.end.line
+            {
+                let start = prev.end.column as usize - 1;
+                let end = token.location.column as usize - 1;
+                if let Some(want) = spacing_between(&prev.token, &token.token, &lines[line].text[start..end]) {
+                    spacing[line].push((start, end, want));
+                }
+            }
+        }
+
+        match token.token {
+            Token::Punct(Punctuation::LParen) => parens += 1,
+            Token::Punct(Punctuation::RParen) => parens = parens.saturating_sub(1),
+            _ => {}
+        }
+        prev = Some(token);
+    }
+
+    let unit = if options.insert_spaces {
+        " ".repeat(options.tab_size as usize)
+    } else {
+        "\t".to_owned()
+    };
+    let trim_trailing = options.trim_trailing_whitespace != Some(false);
+
+    let mut edits = Vec::new();
+    let mut indents = vec![0usize];
+    for (i, (line, info)) in lines.iter().zip(info.iter()).enumerate() {
+        let content = line.text.trim_start_matches(&[' ', '\t'][..]);
+        let indent_len = line.text.len() - content.len();
+
+        if content.is_empty() {
+            // Blank line: drop any whitespace on it.
+            if trim_trailing && indent_len > 0 && !info.inside {
+                edits.push(line.edit(i, 0, indent_len, String::new()));
+            }
+            continue;
+        }
+
+        if !info.inside && !info.continued && !info.directive {
+            let level = if info.code {
+                indent_level(&mut indents, indent_len)
+            } else {
+                // Comment-only lines follow the code, but don't affect it.
+                comment_level(&indents, indent_len)
+            };
+            if let Some(level) = level {
+                let want = unit.repeat(level);
+                if line.text[..indent_len] != want[..] {
+                    edits.push(line.edit(i, 0, indent_len, want));
+                }
+            }
+        }
+
+        for &(start, end, want) in spacing[i].iter() {
+            edits.push(line.edit(i, start, end, want.to_owned()));
+        }
+
+        if trim_trailing && !info.in_string {
+            let trimmed = content.trim_end_matches(&[' ', '\t'][..]);
+            if trimmed.len() < content.len() {
+                let start = indent_len + trimmed.len();
+                edits.push(line.edit(i, start, line.text.len(), String::new()));
+            }
+        }
+    }
+    edits
+}
+
+/// Compute the edits which normalize the whitespace within a range.
+pub fn format_range(text: &str, range: Range, options: &FormattingOptions) -> Vec<TextEdit> {
+    let mut edits = format_document(text, options);
+    edits.retain(|edit| edit.range.start >= range.start && edit.range.end <= range.end);
+    edits
+}
+
+fn split_lines<'a>(text: &'a str) -> Vec<Line<'a>> {
+    text.split('\n').map(|each| Line { text: each.trim_end_matches('\r') }).collect()
+}
+
+fn is_newline(token: &LocatedToken) -> bool {
+    token.token == Token::Punct(Punctuation::Newline)
+}
+
+fn is_string(token: &Token) -> bool {
+    match *token {
+        Token::String(_) |
+        Token::InterpStringBegin(_) |
+        Token::InterpStringPart(_) |
+        Token::InterpStringEnd(_) => true,
+        _ => false,
+    }
+}
+
+/// Find the indentation level of a line of code with the given width of
+/// leading whitespace, as the compiler would see it.
+///
+/// Returns `None` if the line dedents to a width which doesn't match any
+/// enclosing line, in which case its indentation is left alone.
+fn indent_level(indents: &mut Vec<usize>, width: usize) -> Option<usize> {
+    let mut dedented = false;
+    while width < *indents.last().unwrap() {
+        indents.pop();
+        dedented = true;
+    }
+    let consistent = !(dedented && width > *indents.last().unwrap());
+    if width > *indents.last().unwrap() {
+        indents.push(width);
+    }
+    if consistent {
+        Some(indents.len() - 1)
+    } else {
+        None
+    }
+}
+
+/// Find the indentation level of a comment-only line with the given width of
+/// leading whitespace.
+fn comment_level(indents: &[usize], width: usize) -> Option<usize> {
+    if width > *indents.last().unwrap() {
+        Some(indents.len())
+    } else {
+        indents.iter().position(|&each| each == width)
+    }
+}
+
+/// Decide what the whitespace between two tokens on the same line should be.
+///
+/// Returns `None` if it should be left alone.
+fn spacing_between(prev: &Token, next: &Token, gap: &str) -> Option<&'static str> {
+    use self::Punctuation::*;
+
+    // Anything other than plain whitespace means a comment is in the way.
+    if !gap.bytes().all(|b| b == b' ' || b == b'\t') {
+        return None;
+    }
+    let want = match (prev, next) {
+        (_, Token::Punct(Comma)) |
+        (_, Token::Punct(Semicolon)) |
+        (_, Token::Punct(RParen)) |
+        (_, Token::Punct(RBracket)) |
+        (Token::Punct(LParen), _) |
+        (Token::Punct(LBracket), _) => "",
+        (Token::Punct(Comma), _) |
+        (Token::Punct(Semicolon), _) => " ",
+        // Otherwise, only collapse space which is already there.
+        _ if gap.is_empty() => return None,
+        _ => " ",
+    };
+    if gap == want {
+        None
+    } else {
+        Some(want)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tabs() -> FormattingOptions {
+        FormattingOptions {
+            tab_size: 4,
+            insert_spaces: false,
+            .. Default::default()
+        }
+    }
+
+    /// Apply edits to ASCII text, where LSP characters are bytes.
+    fn apply(text: &str, mut edits: Vec<TextEdit>) -> String {
+        let offset = |position: Position| {
+            let line_start: usize = text.split('\n').take(position.line as usize).map(|line| line.len() + 1).sum();
+            line_start + position.character as usize
+        };
+        let mut result = text.to_owned();
+        edits.reverse();
+        for edit in edits {
+            result.replace_range(offset(edit.range.start)..offset(edit.range.end), &edit.new_text);
+        }
+        result
+    }
+
+    fn format(text: &str) -> String {
+        apply(text, format_document(text, &tabs()))
+    }
+
+    #[test]
+    fn indentation() {
+        assert_eq!(
+            format("/obj\n  var/a = 1\n  proc/f()\n      return\n"),
+            "/obj\n\tvar/a = 1\n\tproc/f()\n\t\treturn\n",
+        );
+        let spaces = FormattingOptions { insert_spaces: true, .. tabs() };
+        let text = "/obj\n\tvar/a = 1\n";
+        assert_eq!(apply(text, format_document(text, &spaces)), "/obj\n    var/a = 1\n");
+    }
+
+    #[test]
+    fn spacing() {
+        assert_eq!(format("/proc/f()\n\tx  =  1 ;y = 2\n"), "/proc/f()\n\tx = 1; y = 2\n");
+        assert_eq!(format("/proc/f()\n\tg( a ,b )\n"), "/proc/f()\n\tg(a, b)\n");
+        assert_eq!(format("/proc/f()\n\tfor(;;)\n"), "/proc/f()\n\tfor(;;)\n");
+        assert_eq!(format("/proc/f()\n\treturn 1   \n"), "/proc/f()\n\treturn 1\n");
+    }
+
+    #[test]
+    fn multi_line_strings() {
+        let text = "/proc/f()\n\tvar/s = {\"abc   \n   def\"}\n\treturn s\n";
+        assert_eq!(format(text), text);
+    }
+
+    #[test]
+    fn continuations() {
+        let text = "/proc/f()\n\tvar/x = 1 + \\\n\t\t\t2\n\tvar/y = list(1,\n            2)\n";
+        assert_eq!(format(text), text);
+    }
+
+    #[test]
+    fn comment_only_lines() {
+        assert_eq!(
+            format("/proc/f()\n\t\t\t// deep\n  return\n// top\n"),
+            "/proc/f()\n\t// deep\n\treturn\n// top\n",
+        );
+        let text = "/proc/f()\n\t/* block\n\t     comment */\n\treturn\n";
+        assert_eq!(format(text), text);
+    }
+
+    #[test]
+    fn inconsistent_dedent() {
+        assert_eq!(
+            format("/obj\n    var/a\n        var/b\n  var/c\n"),
+            "/obj\n\tvar/a\n\t\tvar/b\n  var/c\n",
+        );
+    }
+
+    #[test]
+    fn range_filtering() {
+        let text = "/obj\n  var/a  =  1\n  var/b  =  2\n";
+        let range = Range::new(Position::new(1, 0), Position::new(2, 0));
+        assert_eq!(apply(text, format_range(text, range, &tabs())), "/obj\n\tvar/a = 1\n  var/b  =  2\n");
+    }
+
+    #[test]
+    fn spacing_between_tokens() {
+        use self::Punctuation::*;
+        let ident = || Token::Ident("a".to_owned(), false);
+        assert_eq!(spacing_between(&ident(), &Token::Punct(Comma), " "), Some(""));
+        assert_eq!(spacing_between(&Token::Punct(Comma), &ident(), ""), Some(" "));
+        assert_eq!(spacing_between(&Token::Punct(Semicolon), &ident(), ""), Some(" "));
+        assert_eq!(spacing_between(&Token::Punct(LParen), &ident(), "  "), Some(""));
+        assert_eq!(spacing_between(&ident(), &Token::Punct(Assign), "\t "), Some(" "));
+        assert_eq!(spacing_between(&ident(), &Token::Punct(Assign), ""), None);
+        assert_eq!(spacing_between(&ident(), &Token::Punct(Assign), " "), None);
+        assert_eq!(spacing_between(&ident(), &Token::Punct(Assign), " /* x */ "), None);
+    }
+
+    #[test]
+    fn indent_levels() {
+        let mut indents = vec![0];
+        assert_eq!(indent_level(&mut indents, 0), Some(0));
+        assert_eq!(indent_level(&mut indents, 4), Some(1));
+        assert_eq!(indent_level(&mut indents, 8), Some(2));
+        assert_eq!(indent_level(&mut indents, 4), Some(1));
+        assert_eq!(indent_level(&mut indents, 2), None);
+        assert_eq!(indents, vec![0, 2]);
+        assert_eq!(comment_level(&indents, 6), Some(2));
+        assert_eq!(comment_level(&indents, 1), None);
+    }
+}
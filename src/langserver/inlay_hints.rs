@@ -0,0 +1,119 @@
+//! Inline hints for parameter names and inferred local var types.
+
+use lsp_types::{Position, Range};
+
+use dm::{Context, Location};
+use dreamchecker::hints::{self, Hint};
+
+use crate::Engine;
+use crate::extras::{InlayHint, inlay_hint_kind};
+
+/// A hint found by analyzing a proc, kept until the next reparse.
+pub struct CachedHint {
+    location: Location,
+    label: String,
+    kind: u8,
+    /// For type hints, the name of the var the hint goes before.
+    var_name: Option<String>,
+}
+
+impl<'o> From<Hint<'o>> for CachedHint {
+    fn from(hint: Hint<'o>) -> CachedHint {
+        match hint {
+            Hint::Argument { location, parameter } => CachedHint {
+                location,
+                label: format!("{}:", parameter),
+                kind: inlay_hint_kind::PARAMETER,
+                var_name: None,
+            },
+            Hint::LocalType { location, name, ty } => CachedHint {
+                location,
+                label: format!("{}/", &ty.path[1..]),
+                kind: inlay_hint_kind::TYPE,
+                var_name: Some(name.to_owned()),
+            },
+        }
+    }
+}
+
+impl<'a> Engine<'a> {
+    pub fn inlay_hints(&mut self, url: &url::Url, range: Range) -> Result<Vec<InlayHint>, jsonrpc::Error> {
+        let (real_file_id, _, _) = self.get_annotations(url)?;
+        let text = self.docs.get_contents(url).map_err(crate::invalid_request)?.into_owned();
+        let lines: Vec<&str> = text.split('\n').collect();
+
+        // Only analyze the procs which overlap the range and haven't been
+        // analyzed since the last reparse.
+        let objtree = self.objtree.clone();
+        let procs = hints::procs_in_lines(&objtree, real_file_id, range.start.line as u32 + 1, range.end.line as u32 + 1);
+        let cache = self.inlay_hints.entry(real_file_id).or_default();
+        let missing: Vec<_> = procs.iter()
+            .filter(|proc| !cache.contains_key(&proc.get().location))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            // The full analysis already reported any errors in these procs.
+            let context = Context::default();
+            for (proc, found) in missing.iter().zip(hints::collect(&context, &objtree, &missing)) {
+                cache.insert(proc.get().location, found.into_iter().map(CachedHint::from).collect());
+            }
+        }
+
+        let mut results = Vec::new();
+        for hint in procs.iter().flat_map(|proc| cache[&proc.get().location].iter()) {
+            let mut location = hint.location;
+            if let Some(ref name) = hint.var_name {
+                // The hint goes where the type would have been written,
+                // right before the var's name.
+                let line = lines.get(location.line as usize - 1).cloned().unwrap_or("");
+                let column = match find_var_name(line, location.column as usize - 1, name) {
+                    Some(column) => column,
+                    None => continue,
+                };
+                location.column = column as u16 + 1;
+            }
+
+            let line = location.line - 1;
+            if (line as u64) < range.start.line || (line as u64) > range.end.line {
+                continue;
+            }
+            let line_text = match lines.get(line as usize) {
+                Some(text) => text,
+                None => continue,
+            };
+            // The document may have changed since it was last parsed.
+            let prefix = match line_text.get(..location.column as usize - 1) {
+                Some(prefix) => prefix,
+                None => continue,
+            };
+            let character: u64 = prefix.chars().map(|ch| ch.len_utf16() as u64).sum();
+
+            results.push(InlayHint {
+                position: Position::new(line as u64, character),
+                label: hint.label.clone(),
+                kind: hint.kind,
+                padding_left: false,
+                padding_right: hint.kind == inlay_hint_kind::PARAMETER,
+            });
+        }
+        results.sort_by_key(|hint| (hint.position.line, hint.position.character));
+        Ok(results)
+    }
+}
+
+/// Find the byte offset of the name of a var declared as `var/.../name` on
+/// the given line, at or after `start`.
+fn find_var_name(line: &str, start: usize, name: &str) -> Option<usize> {
+    let mut from = start;
+    while let Some(found) = line.get(from..)?.find(name) {
+        let begin = from + found;
+        let end = begin + name.len();
+        let after_slash = line[..begin].ends_with('/');
+        let whole_word = !line[end..].starts_with(|ch: char| ch.is_alphanumeric() || ch == '_');
+        if after_slash && whole_word {
+            return Some(begin);
+        }
+        from = end;
+    }
+    None
+}
@@ -3,6 +3,7 @@
 //! JSON-RPC over stdin/stdout with Content-Length headers.
 
 use std::io::{self, BufRead, Write};
+use std::sync::mpsc;
 
 pub fn run_until_stdin_eof<F: FnMut(&str)>(mut f: F) {
     let stdin = io::stdin();
@@ -12,6 +13,22 @@ pub fn run_until_stdin_eof<F: FnMut(&str)>(mut f: F) {
     }
 }
 
+/// Read messages from stdin on another thread, so that the caller can wait
+/// for them with a timeout. The channel closes at EOF.
+pub fn spawn_stdin_reader() -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        while let Some(message) = read(&mut stdin).expect("JSON-RPC read error") {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
 pub fn run_with_read<R: BufRead, F: FnMut(&str)>(input: &mut R, mut f: F) {
     while let Some(message) = read(input).expect("JSON-RPC read error") {
         f(&message);
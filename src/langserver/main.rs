@@ -29,6 +29,15 @@ mod symbol_search;
 mod find_references;
 mod extras;
 mod completion;
+mod rename;
+mod formatting;
+mod code_actions;
+mod semantic_tokens;
+mod call_hierarchy;
+mod type_hierarchy;
+mod inlay_hints;
+mod document_ranges;
+mod code_lens;
 mod color;
 
 mod debugger;
@@ -37,7 +46,9 @@ use std::path::PathBuf;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::collections::hash_map::Entry;
 use std::sync::Arc;
+use std::sync::mpsc::RecvTimeoutError;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use url::Url;
 use jsonrpc::{Request, Call, Response, Output};
@@ -45,6 +56,7 @@ use lsp_types::MessageType;
 use petgraph::visit::IntoNodeReferences;
 
 use dm::FileId;
+use dm::preprocessor::DefineOverride;
 use dm::annotation::{Annotation, AnnotationTree};
 use dm::objtree::TypeRef;
 
@@ -72,26 +84,50 @@ fn main() {
         Err(e) => eprintln!("dir check failure: {}", e),
     }
 
+    let mut define_overrides = Vec::new();
     let mut args = std::env::args();
     let _ = args.next();  // skip executable name
-    if let Some(arg) = args.next() {
+    while let Some(arg) = args.next() {
         if arg == "--debugger" {
             return debugger::debugger_main(args);
         } else if arg == "--version" {
             return;
+        } else if let Some(result) = DefineOverride::parse_arg(&arg, &mut args) {
+            define_overrides.push(result.unwrap_or_else(|e| panic!("{}", e)));
         } else {
             panic!("unknown argument {:?}", arg);
         }
     }
 
     let context = dm::Context::default();
-    let mut engine = Engine::new(&context);
-    jrpc_io::run_until_stdin_eof(|message| engine.handle_input(message));
+    let mut engine = Engine::new(&context, define_overrides);
+    let messages = jrpc_io::spawn_stdin_reader();
+    loop {
+        let message = match engine.reparse_after {
+            Some(deadline) => match messages.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    engine.reparse_watched_files();
+                    continue;
+                },
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match messages.recv() {
+                Ok(message) => message,
+                Err(_) => break,
+            },
+        };
+        engine.handle_input(&message);
+    }
     engine.exit(0);
 }
 
 const VERSION: Option<jsonrpc::Version> = Some(jsonrpc::Version::V2);
 
+/// How long to wait after a change to a watched file before reparsing, as
+/// switching branches or running a generator changes many files at once.
+const WATCHED_FILES_DEBOUNCE: Duration = Duration::from_millis(500);
+
 #[derive(PartialEq)]
 enum InitStatus {
     Starting,
@@ -106,6 +142,7 @@ struct ClientCaps {
     related_info: bool,
     label_offset_support: bool,
     object_tree: bool,
+    watched_files_registration: bool,
 }
 
 impl ClientCaps {
@@ -128,6 +165,13 @@ impl ClientCaps {
                 }
             }
         }
+        if let Some(ref workspace) = caps.workspace {
+            if let Some(ref did_change_watched_files) = workspace.did_change_watched_files {
+                if let Some(dynamic_registration) = did_change_watched_files.dynamic_registration {
+                    this.watched_files_registration = dynamic_registration;
+                }
+            }
+        }
         if let Some(ref experimental) = caps.experimental {
             if let Some(ref dreammaker) = experimental.get("dreammaker") {
                 if let Some(ref object_tree) = dreammaker.get("objectTree") {
@@ -150,19 +194,27 @@ struct Engine<'a> {
     root: Option<Url>,
 
     context: &'a dm::Context,
+    define_overrides: Vec<DefineOverride>,
     defines: Option<dm::preprocessor::DefineHistory>,
     objtree: Arc<dm::objtree::ObjectTree>,
     references_table: Option<find_references::ReferencesTable>,
+    include_graph: Option<dm::include_graph::IncludeGraph>,
 
     annotations: HashMap<Url, (FileId, FileId, Rc<AnnotationTree>)>,
+    /// Inlay hints by file and by the location of the proc they're in.
+    inlay_hints: HashMap<FileId, HashMap<dm::Location, Vec<inlay_hints::CachedHint>>>,
     diagnostics_set: HashSet<Url>,
 
     client_caps: ClientCaps,
     extools_dll: Option<String>,
+    /// Whether to run DreamChecker when parsing the environment.
+    dreamchecker: bool,
+    watching_files: bool,
+    reparse_after: Option<Instant>,
 }
 
 impl<'a> Engine<'a> {
-    fn new(context: &'a dm::Context) -> Self {
+    fn new(context: &'a dm::Context, define_overrides: Vec<DefineOverride>) -> Self {
         Engine {
             docs: Default::default(),
 
@@ -172,15 +224,21 @@ impl<'a> Engine<'a> {
             root: None,
 
             context,
+            define_overrides,
             defines: None,
             objtree: Default::default(),
             references_table: None,
+            include_graph: None,
 
             annotations: Default::default(),
+            inlay_hints: Default::default(),
             diagnostics_set: Default::default(),
 
             client_caps: Default::default(),
             extools_dll: None,
+            dreamchecker: false,
+            watching_files: false,
+            reparse_after: None,
         }
     }
 
@@ -195,6 +253,21 @@ impl<'a> Engine<'a> {
         issue_notification::<T>(params)
     }
 
+    fn issue_request<T>(&self, id: &str, params: T::Params)
+    where
+        T: lsp_types::request::Request,
+        T::Params: serde::Serialize,
+    {
+        let params = serde_json::to_value(params).expect("request bad to_value");
+        let request = Request::Single(Call::MethodCall(jsonrpc::MethodCall {
+            jsonrpc: VERSION,
+            method: T::METHOD.to_owned(),
+            params: value_to_params(params),
+            id: jsonrpc::Id::Str(id.to_owned()),
+        }));
+        jrpc_io::write(serde_json::to_string(&request).expect("request bad to_string"))
+    }
+
     fn show_message<S>(&mut self, typ: MessageType, message: S) where
         S: Into<String>
     {
@@ -339,14 +412,20 @@ impl<'a> Engine<'a> {
             }
         };
 
+        pp.set_define_overrides(&self.define_overrides);
         {
             let mut parser = dm::parser::Parser::new(ctx, dm::indents::IndentProcessor::new(ctx, &mut pp));
             parser.enable_procs();
             self.objtree = Arc::new(parser.parse_object_tree());
         }
+        self.inlay_hints.clear();
         self.update_objtree();
         self.references_table = Some(find_references::ReferencesTable::new(&self.objtree));
-        // TODO: run dreamchecker here if enabled
+        if self.dreamchecker {
+            dreamchecker::check_var_defs(&self.objtree, ctx);
+            dreamchecker::run(ctx, &self.objtree);
+        }
+        self.include_graph = Some(pp.include_graph().clone());
         self.defines = Some(pp.finalize());
         self.issue_notification::<extras::WindowStatus>(Default::default());
         let elapsed = start.elapsed();
@@ -378,9 +457,10 @@ impl<'a> Engine<'a> {
             let diag = lsp_types::Diagnostic {
                 message: error.description().to_owned(),
                 severity: Some(convert_severity(error.severity())),
-                range: location_to_range(loc),
+                range: span_to_range(loc..error.end()),
                 source: component_to_source(error.component()),
                 related_information,
+                tags: diagnostic_tags(error),
                 .. Default::default()
             };
             map.entry(self.file_url(loc.file)?)
@@ -435,6 +515,54 @@ impl<'a> Engine<'a> {
         Ok(())
     }
 
+    /// Whether a change to a watched file on disk affects the parsed
+    /// environment. Changes to files the environment never read only
+    /// invalidate what was cached for them.
+    fn change_needs_reparse(&self, change: &lsp_types::FileEvent) -> bool {
+        let path = match url_to_path(&change.uri) {
+            Ok(path) => path,
+            Err(_) => return false,
+        };
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase());
+        let extension = extension.as_ref().map_or("", |ext| ext.as_str());
+
+        let graph = match self.include_graph {
+            Some(ref graph) => graph,
+            // With no environment loaded, only a new .dme could change that.
+            None => return extension == "dme",
+        };
+        let included = graph.environment().parent()
+            .and_then(|dir| path.strip_prefix(dir).ok())
+            .map_or(false, |relative| graph.find(relative).is_some());
+        // A newly created file may be one an `#include` failed to find.
+        let was_missing = change.typ == lsp_types::FileChangeType::Created && path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| self.context.errors().iter().any(|error| {
+                error.description().starts_with("failed to find #include") && error.description().contains(name)
+            }));
+
+        match extension {
+            "dme" => path == graph.environment() || included,
+            "dm" => included || was_missing,
+            // Map contents are not parsed, but whether they exist is.
+            "dmm" => change.typ != lsp_types::FileChangeType::Changed && (included || was_missing),
+            // Icons are never read.
+            _ => false,
+        }
+    }
+
+    /// Reparse the environment after the debounce following changes to
+    /// watched files has passed.
+    fn reparse_watched_files(&mut self) {
+        self.reparse_after = None;
+        eprintln!("reparsing after changes on disk...");
+        self.context.errors_mut().clear();
+        self.annotations.clear();
+        if let Err(e) = self.Initialized(lsp_types::InitializedParams {}) {
+            self.show_message(MessageType::Error, e.message);
+        }
+    }
+
     fn get_annotations(&mut self, url: &Url) -> Result<(FileId, FileId, Rc<AnnotationTree>), jsonrpc::Error> {
         Ok(match self.annotations.entry(url.to_owned()) {
             Entry::Occupied(o) => o.get().clone(),
@@ -466,6 +594,7 @@ impl<'a> Engine<'a> {
                         parser.parse_annotations_only(&mut annotations);
                     }
                     annotations.merge(preprocessor.take_annotations().unwrap());
+                    annotate_conditionals(&preprocessor, file_id, &mut annotations);
                     v.insert((real_file_id, file_id, Rc::new(annotations))).clone()
                 },
                 None => {
@@ -474,9 +603,11 @@ impl<'a> Engine<'a> {
 
                     let contents = self.docs.get_contents(url).map_err(invalid_request)?.into_owned();
                     let mut pp = dm::preprocessor::Preprocessor::from_buffer(&self.context, filename.clone().into(), contents);
+                    pp.set_define_overrides(&self.define_overrides);
                     let file_id = self.context.get_file(filename.as_ref()).expect("file didn't exist?");
                     // Clear old errors for this file. Hacky, but it will work for now.
                     self.context.errors_mut().retain(|error| error.location().file != file_id);
+                    self.context.clear_suppressed_lines(file_id);
 
                     pp.enable_annotations();
                     let mut annotations = AnnotationTree::default();
@@ -489,6 +620,8 @@ impl<'a> Engine<'a> {
                         // selective definition deletion/reintroduction is implemented.
                         self.objtree = Arc::new(parser.parse_object_tree());
                     }
+                    self.inlay_hints.clear();
+                    annotate_conditionals(&pp, file_id, &mut annotations);
                     pp.finalize();
                     dreamchecker::run(&self.context, &self.objtree);
 
@@ -519,9 +652,10 @@ impl<'a> Engine<'a> {
                         let diag = lsp_types::Diagnostic {
                             message: error.description().to_owned(),
                             severity: Some(convert_severity(error.severity())),
-                            range: location_to_range(loc),
+                            range: span_to_range(loc..error.end()),
                             source: component_to_source(error.component()),
                             related_information,
+                            tags: diagnostic_tags(error),
                             .. Default::default()
                         };
                         diagnostics.push(diag);
@@ -686,6 +820,17 @@ impl<'a> Engine<'a> {
     // Driver
 
     fn handle_input(&mut self, message: &str) {
+        // Responses to our own requests have no method, and would otherwise
+        // be answered as invalid calls.
+        if let Ok(serde_json::Value::Object(object)) = serde_json::from_str::<serde_json::Value>(message) {
+            if !object.contains_key("method") && (object.contains_key("result") || object.contains_key("error")) {
+                if let Some(error) = object.get("error") {
+                    eprintln!("client returned error: {}", error);
+                }
+                return;
+            }
+        }
+
         let mut outputs: Vec<Output> = match serde_json::from_str(message) {
             Ok(Request::Single(call)) => self.handle_call(call).into_iter().collect(),
             Ok(Request::Batch(calls)) => calls.into_iter().flat_map(|call| self.handle_call(call)).collect(),
@@ -778,7 +923,7 @@ impl<'a> Engine<'a> {
 handle_method_call! {
     // ------------------------------------------------------------------------
     // basic setup
-    on Initialize(&mut self, init) {
+    on InitializeExtended(&mut self, init) {
         if self.status != InitStatus::Starting {
             return Err(invalid_request(""))
         }
@@ -797,6 +942,12 @@ handle_method_call! {
             eprintln!("preparing single file mode");
         }
 
+        if let Some(ref options) = init.initialization_options {
+            if let Some(dreamchecker) = options["dreamchecker"].as_bool() {
+                self.dreamchecker = dreamchecker;
+            }
+        }
+
         // Extract relevant client capabilities.
         self.client_caps = ClientCaps::parse(&init.capabilities);
         let debug = format!("{:?}", self.client_caps);
@@ -806,31 +957,53 @@ handle_method_call! {
             eprintln!("client capabilities: {}", debug);
         }
 
-        InitializeResult {
-            capabilities: ServerCapabilities {
-                definition_provider: Some(true),
-                workspace_symbol_provider: Some(true),
-                hover_provider: Some(true),
-                document_symbol_provider: Some(true),
-                references_provider: Some(true),
-                type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
-                text_document_sync: Some(TextDocumentSyncCapability::Options(TextDocumentSyncOptions {
-                    open_close: Some(true),
-                    change: Some(TextDocumentSyncKind::Incremental),
+        extras::InitializeExtendedResult {
+            capabilities: extras::ExtendedServerCapabilities {
+                base: ServerCapabilities {
+                    definition_provider: Some(true),
+                    workspace_symbol_provider: Some(true),
+                    hover_provider: Some(true),
+                    document_symbol_provider: Some(true),
+                    references_provider: Some(true),
+                    rename_provider: Some(RenameProviderCapability::Options(RenameOptions {
+                        prepare_provider: Some(true),
+                        work_done_progress_options: Default::default(),
+                    })),
+                    document_formatting_provider: Some(true),
+                    document_range_formatting_provider: Some(true),
+                    code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                        code_action_kinds: Some(vec![code_action_kind::QUICKFIX.to_owned()]),
+                        work_done_progress_options: Default::default(),
+                    })),
+                    type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
+                    text_document_sync: Some(TextDocumentSyncCapability::Options(TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::Incremental),
+                        .. Default::default()
+                    })),
+                    completion_provider: Some(CompletionOptions {
+                        trigger_characters: Some(vec![".".to_owned(), ":".to_owned(), "/".to_owned()]),
+                        resolve_provider: None,
+                        work_done_progress_options: Default::default(),
+                    }),
+                    signature_help_provider: Some(SignatureHelpOptions {
+                        trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
+                        retrigger_characters: None,
+                        work_done_progress_options: Default::default(),
+                    }),
+                    color_provider: Some(ColorProviderCapability::Simple(true)),
+                    document_highlight_provider: Some(true),
+                    code_lens_provider: Some(CodeLensOptions {
+                        resolve_provider: Some(true),
+                    }),
+                    folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
                     .. Default::default()
-                })),
-                completion_provider: Some(CompletionOptions {
-                    trigger_characters: Some(vec![".".to_owned(), ":".to_owned(), "/".to_owned()]),
-                    resolve_provider: None,
-                    work_done_progress_options: Default::default(),
-                }),
-                signature_help_provider: Some(SignatureHelpOptions {
-                    trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
-                    retrigger_characters: None,
-                    work_done_progress_options: Default::default(),
-                }),
-                color_provider: Some(ColorProviderCapability::Simple(true)),
-                .. Default::default()
+                },
+                semantic_tokens_provider: Some(semantic_tokens::options()),
+                call_hierarchy_provider: Some(true),
+                type_hierarchy_provider: Some(true),
+                inlay_hint_provider: Some(true),
+                selection_range_provider: Some(true),
             },
             server_info: Some(ServerInfo {
                 name: "dm-langserver".to_owned(),
@@ -1380,6 +1553,162 @@ handle_method_call! {
         }
     }
 
+    on PrepareRenameRequest(&mut self, params) {
+        match self.rename_target(&params)? {
+            Some((_, name, range)) => Some(PrepareRenameResponse::RangeWithPlaceholder {
+                range,
+                placeholder: name,
+            }),
+            None => None,
+        }
+    }
+
+    on Rename(&mut self, params) {
+        let (symbol, name, _) = match self.rename_target(&params.text_document_position)? {
+            Some(target) => target,
+            None => return Err(invalid_request("nothing to rename here")),
+        };
+        if !rename::is_valid_name(&params.new_name) {
+            return Err(invalid_request(format!("not a valid name: {}", params.new_name)));
+        }
+        self.check_rename_conflict(&symbol, &name, &params.new_name).map_err(invalid_request)?;
+        let uri = &params.text_document_position.text_document.uri;
+        let changes = self.rename_symbol(uri, &symbol, &name, &params.new_name)?;
+        Some(WorkspaceEdit {
+            changes: Some(changes),
+            .. Default::default()
+        })
+    }
+
+    on Formatting(&mut self, params) {
+        let content = self.docs.get_contents(&params.text_document.uri).map_err(invalid_request)?;
+        let edits = formatting::format_document(&content, &params.options);
+        if edits.is_empty() {
+            None
+        } else {
+            Some(edits)
+        }
+    }
+
+    on RangeFormatting(&mut self, params) {
+        let content = self.docs.get_contents(&params.text_document.uri).map_err(invalid_request)?;
+        let edits = formatting::format_range(&content, params.range, &params.options);
+        if edits.is_empty() {
+            None
+        } else {
+            Some(edits)
+        }
+    }
+
+    on CodeActionRequest(&mut self, params) {
+        let actions = self.code_actions(&params)?;
+        if actions.is_empty() {
+            None
+        } else {
+            Some(actions)
+        }
+    }
+
+    on SemanticTokensFull(&mut self, params) {
+        Some(extras::SemanticTokens {
+            data: self.semantic_tokens(&params.text_document.uri, None)?,
+        })
+    }
+
+    on SemanticTokensRange(&mut self, params) {
+        let lines = params.range.start.line as u32..params.range.end.line as u32 + 1;
+        Some(extras::SemanticTokens {
+            data: self.semantic_tokens(&params.text_document.uri, Some(lines))?,
+        })
+    }
+
+    on CallHierarchyPrepare(&mut self, params) {
+        self.prepare_call_hierarchy(&params)?.map(|item| vec![item])
+    }
+
+    on CallHierarchyIncomingCalls(&mut self, params) {
+        let calls = self.incoming_calls(&params.item)?;
+        if calls.is_empty() {
+            None
+        } else {
+            Some(calls)
+        }
+    }
+
+    on CallHierarchyOutgoingCalls(&mut self, params) {
+        let calls = self.outgoing_calls(&params.item)?;
+        if calls.is_empty() {
+            None
+        } else {
+            Some(calls)
+        }
+    }
+
+    on TypeHierarchyPrepare(&mut self, params) {
+        self.prepare_type_hierarchy(&params)?.map(|item| vec![item])
+    }
+
+    on TypeHierarchySupertypes(&mut self, params) {
+        let types = self.supertypes(&params.item)?;
+        if types.is_empty() {
+            None
+        } else {
+            Some(types)
+        }
+    }
+
+    on TypeHierarchySubtypes(&mut self, params) {
+        let types = self.subtypes(&params.item)?;
+        if types.is_empty() {
+            None
+        } else {
+            Some(types)
+        }
+    }
+
+    on Overrides(&mut self, params) {
+        self.find_overrides(&params)?
+    }
+
+    on InlayHintRequest(&mut self, params) {
+        let hints = self.inlay_hints(&params.text_document.uri, params.range)?;
+        if hints.is_empty() {
+            None
+        } else {
+            Some(hints)
+        }
+    }
+
+    on DocumentHighlightRequest(&mut self, params) {
+        let highlights = self.document_highlights(&params)?;
+        if highlights.is_empty() {
+            None
+        } else {
+            Some(highlights)
+        }
+    }
+
+    on FoldingRangeRequest(&mut self, params) {
+        Some(self.folding_ranges(&params.text_document.uri)?)
+    }
+
+    on SelectionRangeRequest(&mut self, params) {
+        Some(self.selection_ranges(&params.text_document.uri, &params.positions)?)
+    }
+
+    on CodeLensRequest(&mut self, params) {
+        let lenses = self.code_lenses(&params.text_document.uri)?;
+        if lenses.is_empty() {
+            None
+        } else {
+            Some(lenses)
+        }
+    }
+
+    on CodeLensResolve(&mut self, lens) {
+        self.resolve_code_lens(lens)?
+    }
+
     on Completion(&mut self, params) {
         let (_, file_id, annotations) = self.get_annotations(&params.text_document_position.text_document.uri)?;
         let location = dm::Location {
@@ -1724,10 +2053,28 @@ handle_notification! {
         } else {
             self.show_status("single file mode");
         }
+
+        if !self.watching_files && self.root.is_some() && self.client_caps.watched_files_registration {
+            self.watching_files = true;
+            let options = lsp_types::DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![lsp_types::FileSystemWatcher {
+                    glob_pattern: "**/*.{dm,dme,dmm,dmi}".to_owned(),
+                    kind: None,
+                }],
+            };
+            self.issue_request::<lsp_types::request::RegisterCapability>("watch-files", lsp_types::RegistrationParams {
+                registrations: vec![lsp_types::Registration {
+                    id: "watch-files".to_owned(),
+                    method: <lsp_types::notification::DidChangeWatchedFiles as lsp_types::notification::Notification>::METHOD.to_owned(),
+                    register_options: Some(serde_json::to_value(options).expect("options bad to_value")),
+                }],
+            });
+        }
     }
 
     on Reparse(&mut self, _p) {
         eprintln!("reparsing by request...");
+        self.reparse_after = None;
         self.context.errors_mut().clear();
         return self.Initialized(_p);
     }
@@ -1750,10 +2097,25 @@ handle_notification! {
         self.annotations.remove(&url);
     }
 
+    on DidChangeWatchedFiles(&mut self, params) {
+        let mut reparse = false;
+        for change in params.changes.iter() {
+            self.annotations.remove(&change.uri);
+            reparse |= self.change_needs_reparse(change);
+        }
+        if reparse {
+            self.reparse_after = Some(Instant::now() + WATCHED_FILES_DEBOUNCE);
+        }
+    }
+
     on DidChangeConfiguration(&mut self, params) {
         if let Some(extools_dll) = params.settings["dreammaker"]["extoolsDLL"].as_str() {
             self.extools_dll = Some(extools_dll.to_owned());
         }
+        // Takes effect at the next reparse.
+        if let Some(dreamchecker) = params.settings["dreammaker"]["dreamchecker"].as_bool() {
+            self.dreamchecker = dreamchecker;
+        }
     }
 }
 
@@ -1845,6 +2207,31 @@ fn span_to_range(range: std::ops::Range<dm::Location>) -> lsp_types::Range {
     lsp_types::Range::new(location_to_position(range.start), location_to_position(range.end))
 }
 
+/// Lex a document's text on its own, without spaces and tabs.
+///
+/// The document was already lexed when it was parsed, so this uses a scratch
+/// context in order not to report its errors twice.
+fn lex_scratch(text: &str) -> Vec<dm::lexer::LocatedToken> {
+    let context = dm::Context::default();
+    dm::lexer::Lexer::new(&context, FileId::default(), text.bytes().map(Ok))
+        .filter(|token| match token.token {
+            dm::lexer::Token::Punct(dm::lexer::Punctuation::Space) |
+            dm::lexer::Token::Punct(dm::lexer::Punctuation::Tab) => false,
+            _ => true,
+        })
+        .collect()
+}
+
+/// Record the `#if` branches of a file, which the preprocessor remembers
+/// separately from its other annotations.
+fn annotate_conditionals(preprocessor: &dm::preprocessor::Preprocessor, file_id: FileId, annotations: &mut AnnotationTree) {
+    for (range, &active) in preprocessor.ifdef_history().iter() {
+        if range.start.file == file_id {
+            annotations.insert(range.start..range.end, Annotation::Conditional(active));
+        }
+    }
+}
+
 fn issue_notification<T>(params: T::Params)
 where
     T: lsp_types::notification::Notification,
@@ -1862,3 +2249,11 @@ where
 fn component_to_source(component: dm::Component) -> Option<String> {
     Some(component.name().unwrap_or("dm-langserver").to_owned())
 }
+
+fn diagnostic_tags(error: &dm::DMError) -> Option<Vec<lsp_types::DiagnosticTag>> {
+    if error.is_unnecessary() {
+        Some(vec![lsp_types::DiagnosticTag::Unnecessary])
+    } else {
+        None
+    }
+}
@@ -0,0 +1,697 @@
+//! Supporting functions for renaming symbols.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use lsp_types::{TextDocumentPositionParams, TextEdit};
+use url::Url;
+
+use dm::{FileId, Location};
+use dm::annotation::{Annotation, AnnotationTree};
+use dm::ast::VarType;
+use dm::lexer::{Lexer, Token};
+use dm::objtree::{ObjectTree, SymbolId, TypeRef};
+
+use crate::{Engine, UnscopedVar, invalid_request, location_to_position};
+
+/// A symbol which might be renamed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+    /// A type, by its path.
+    Type(String),
+    /// A type var and all its overrides, by its declaration.
+    Var(SymbolId),
+    /// A proc and all its overrides, by its declaration.
+    Proc(SymbolId),
+    /// A local var, by the start of its scope.
+    Local(Location),
+    /// A proc parameter, which can't be renamed.
+    Parameter,
+    /// A macro, by the location of its definition.
+    Macro(Location),
+}
+
+/// What a name in a file refers to, before it is narrowed to a `Symbol`.
+pub enum Target<'o> {
+    Type(TypeRef<'o>),
+    /// A type var, looked up on the given type.
+    Var(TypeRef<'o>),
+    /// A proc, looked up on the given type.
+    Proc(TypeRef<'o>),
+    /// A local var, by the start of its scope, and its declared type.
+    Local(Location, &'o VarType),
+    Parameter,
+    /// A macro, by the location of its definition.
+    Macro(Location),
+}
+
+/// A name found in a file and what it refers to.
+pub struct Resolved<'o> {
+    pub target: Target<'o>,
+    pub name: String,
+    /// Where the name starts.
+    pub start: Location,
+    /// Whether this is where the name is declared, rather than used.
+    pub declaration: bool,
+}
+
+/// Path segments which are keywords rather than type names.
+pub const PATH_KEYWORDS: &[&str] = &["var", "proc", "verb", "static", "global", "const", "tmp", "final", "list"];
+
+/// Words which the parser treats as statements, operators, or special forms,
+/// and so can't name a symbol.
+const STATEMENT_KEYWORDS: &[&str] = &[
+    "if", "else", "for", "while", "do", "switch", "return", "break", "continue",
+    "goto", "del", "spawn", "set", "in", "to", "step", "as", "try", "catch",
+    "throw", "new", "null", "call", "input", "locate", "pick",
+];
+
+impl<'a> Engine<'a> {
+    /// Find the symbol to be renamed at the given position and the range of
+    /// its name there, or explain why it can't be renamed.
+    pub fn rename_target(&mut self, params: &TextDocumentPositionParams) -> Result<Option<(Symbol, String, lsp_types::Range)>, jsonrpc::Error> {
+        let (real_file_id, file_id, annotations) = self.get_annotations(&params.text_document.uri)?;
+        let location = Location {
+            file: file_id,
+            line: params.position.line as u32 + 1,
+            column: params.position.character as u16 + 1,
+        };
+        let contents = self.docs.get_contents(&params.text_document.uri).map_err(invalid_request)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        let (symbol, name, start) = match self.find_symbol_at(&annotations, &lines, location) {
+            Some(found) => found,
+            // macros named in directives and in other macros have no annotations
+            None => match self.find_macro_at(&lines, location, real_file_id) {
+                Some(found) => found,
+                None => return Ok(None),
+            },
+        };
+        self.check_rename(&symbol, &name).map_err(invalid_request)?;
+
+        let symbol = match symbol {
+            // macros defined in this file are located in its reparse
+            Symbol::Macro(mut definition) if definition.file == file_id => {
+                definition.file = real_file_id;
+                Symbol::Macro(definition)
+            },
+            other => other,
+        };
+        let range = rename_edit(start, &name, &name).range;
+        Ok(Some((symbol, name, range)))
+    }
+
+    /// Rename a symbol in every file which mentions it.
+    pub fn rename_symbol(&mut self, url: &Url, symbol: &Symbol, name: &str, new_name: &str) -> Result<HashMap<Url, Vec<TextEdit>>, jsonrpc::Error> {
+        if let Symbol::Macro(definition) = *symbol {
+            return self.rename_macro(definition, name, new_name);
+        }
+
+        let mut urls = vec![url.to_owned()];
+        if let Symbol::Local(_) = *symbol {
+            // locals are only visible in the current file
+        } else {
+            for file in self.rename_files(symbol, name) {
+                let file_url = self.file_url(file)?;
+                if !urls.contains(&file_url) {
+                    urls.push(file_url);
+                }
+            }
+        }
+
+        let mut changes = HashMap::new();
+        for url in urls {
+            let (_, _, annotations) = self.get_annotations(&url)?;
+            let text = self.docs.get_contents(&url).map_err(invalid_request)?;
+            let edits = self.rename_in_file(&annotations, &text, symbol, name, new_name);
+            if !edits.is_empty() {
+                changes.insert(url, edits);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Find a macro named by the identifier at the given location.
+    fn find_macro_at(&self, lines: &[&str], location: Location, real_file_id: FileId) -> Option<(Symbol, String, Location)> {
+        let line = lines.get(location.line.checked_sub(1)? as usize)?;
+        let column = location.column.checked_sub(1)? as usize;
+        if column > line.len() || !line.is_char_boundary(column) {
+            return None;
+        }
+        let start = line[..column].rfind(|ch: char| !is_ident(ch)).map_or(0, |i| i + 1);
+        let start = Location {
+            file: real_file_id,
+            line: location.line,
+            column: start as u16 + 1,
+        };
+        let name = word_at(lines, start);
+        if name.is_empty() {
+            return None;
+        }
+        let defines = self.defines.as_ref()?;
+        for (range, &(ref define_name, _)) in defines.range(interval_tree::range(start, start)) {
+            if define_name == name {
+                return Some((Symbol::Macro(range.start), name.to_owned(), start));
+            }
+        }
+        None
+    }
+
+    /// Find the symbol whose name is written at the given location in a file,
+    /// as for `find_rename_symbol`.
+    pub fn find_symbol_at(&self, annotations: &AnnotationTree, lines: &[&str], location: Location) -> Option<(Symbol, String, Location)> {
+        let found = self.find_rename_symbol(annotations, location)?;
+        // The location may be on code which came from a macro, whose
+        // annotations name something other than what is written there.
+        if word_at(lines, found.2) != found.1 {
+            return None;
+        }
+        Some(found)
+    }
+
+    /// Find the symbol whose name starts at or surrounds the given location,
+    /// returning it, its name, and where that name starts.
+    pub fn find_rename_symbol(&self, annotations: &AnnotationTree, location: Location) -> Option<(Symbol, String, Location)> {
+        let resolved = self.resolve_name(annotations, location)?;
+        let symbol = match resolved.target {
+            Target::Type(ty) => Symbol::Type(ty.path.clone()),
+            Target::Var(ty) => Symbol::Var(ty.get_var_declaration(&resolved.name)?.id),
+            Target::Proc(ty) => Symbol::Proc(ty.get_proc_declaration(&resolved.name)?.id),
+            Target::Local(scope, _) => Symbol::Local(scope),
+            Target::Parameter => Symbol::Parameter,
+            Target::Macro(definition) => Symbol::Macro(definition),
+        };
+        Some((symbol, resolved.name, resolved.start))
+    }
+
+    /// Find what the name which starts at or surrounds the given location
+    /// refers to.
+    pub fn resolve_name<'o>(&'o self, annotations: &'o AnnotationTree, location: Location) -> Option<Resolved<'o>> {
+        let iter = annotations.get_location(location);
+
+        let mut in_seq = None;
+        let mut tree_path = None;
+        let mut type_path = None;
+        let mut entry = None;
+        let mut in_proc_header = false;
+        let mut proc_body = None;
+        let mut expression: Option<(Location, &Annotation)> = None;
+        let mut macro_use = None;
+        for (span, annotation) in iter.clone() {
+            match annotation {
+                Annotation::MacroUse(name, definition) => macro_use = Some((Target::Macro(*definition), name, span.start, false)),
+                Annotation::MacroDefinition(name) => macro_use = Some((Target::Macro(span.start), name, span.start, true)),
+                Annotation::UnscopedCall(_) |
+                Annotation::UnscopedVar(_) |
+                Annotation::ScopedCall(..) |
+                Annotation::ScopedVar(..)
+                    // the innermost expression is the one the name belongs to
+                    if expression.map_or(true, |(start, _)| span.start >= start) =>
+                {
+                    expression = Some((span.start, annotation));
+                },
+                Annotation::InSequence(idx) => in_seq = Some((span.start, *idx)),
+                Annotation::TreePath(absolute, parts) => tree_path = Some((span.start, *absolute, parts)),
+                Annotation::TypePath(parts) => type_path = Some(parts),
+                Annotation::Variable(path) => entry = Some((span.start, path, false)),
+                Annotation::ProcHeader(path, _) => {
+                    in_proc_header = true;
+                    entry = Some((span.start, path, true));
+                },
+                Annotation::ProcBody(..) => proc_body = Some(span),
+                _ => {}
+            }
+        }
+        let resolved = |target, name: &str, start, declaration| Some(Resolved {
+            target,
+            name: name.to_owned(),
+            start,
+            declaration,
+        });
+
+        // Macros are expanded before anything else sees the code.
+        if let Some((target, name, start, declaration)) = macro_use {
+            return resolved(target, name, start, declaration);
+        }
+
+        if let Some((start, annotation)) = expression {
+            let (target, name) = match annotation {
+                Annotation::UnscopedCall(proc_name) => {
+                    let (ty, _) = self.find_type_context(&iter);
+                    (Target::Proc(ty.unwrap_or(self.objtree.root())), proc_name)
+                },
+                Annotation::UnscopedVar(var_name) => {
+                    let (ty, proc_name) = self.find_type_context(&iter);
+                    let target = match self.find_unscoped_var(&iter, ty, proc_name, var_name) {
+                        UnscopedVar::Parameter { .. } => Target::Parameter,
+                        UnscopedVar::Variable { ty, .. } => Target::Var(ty),
+                        UnscopedVar::Local { loc, var_type } => Target::Local(loc, var_type),
+                        UnscopedVar::None => return None,
+                    };
+                    (target, var_name)
+                },
+                Annotation::ScopedCall(priors, proc_name) => (Target::Proc(self.find_scoped_type(&iter, priors)?), proc_name),
+                Annotation::ScopedVar(priors, var_name) => (Target::Var(self.find_scoped_type(&iter, priors)?), var_name),
+                _ => return None,
+            };
+            return resolved(target, name, start, false);
+        }
+
+        let (start, idx) = in_seq?;
+
+        if let Some(parts) = type_path {
+            // '/datum/bar' or '/datum/proc/foo'
+            let name = &parts.get(idx)?.1;
+            if PATH_KEYWORDS.contains(&name.as_str()) {
+                return None;
+            }
+            let result = self.follow_type_path(&iter, parts)?;
+            return match result.proc {
+                Some(_) => resolved(Target::Proc(result.ty), name, start, false),
+                None if result.decl.is_none() && result.ty.name == *name => resolved(Target::Type(result.ty), name, start, false),
+                None => None,
+            };
+        }
+
+        let (path_start, absolute, parts) = tree_path?;
+        let name = parts.get(idx)?;
+        if PATH_KEYWORDS.contains(&name.as_str()) {
+            return None;
+        }
+        // The path of a var or proc entry starts where the entry does.
+        let entry = entry.filter(|&(entry_start, _, _)| entry_start == path_start);
+
+        if idx + 1 == parts.len() {
+            if let Some((_, path, is_proc)) = entry {
+                // the var or proc being declared or overridden
+                let ty = self.declaring_type(path, if is_proc { &["proc", "verb"] } else { &["var"] });
+                return if is_proc {
+                    let declared_here = ty.get().procs.get(name).map_or(false, |proc| proc.declaration.is_some());
+                    resolved(Target::Proc(ty), name, start, declared_here)
+                } else {
+                    let declared_here = ty.get().vars.get(name).map_or(false, |var| var.declaration.is_some());
+                    resolved(Target::Var(ty), name, start, declared_here)
+                };
+            } else if in_proc_header {
+                return resolved(Target::Parameter, name, start, true);
+            } else if let Some(body) = proc_body {
+                // the local var being declared, whose scope starts after
+                // its declaration
+                let mut scope = None;
+                for (span, annotation) in annotations.get_range_raw(interval_tree::range(path_start, body.end)) {
+                    if let Annotation::LocalVarScope(var_type, var_name) = annotation {
+                        if var_name == name && span.start >= path_start && scope.map_or(true, |(s, _)| span.start < s) {
+                            scope = Some((span.start, var_type));
+                        }
+                    }
+                }
+                let (scope_start, var_type) = scope?;
+                return resolved(Target::Local(scope_start, var_type), name, start, true);
+            }
+        }
+
+        // a segment of a type path
+        let ty = if entry.is_some() || !(in_proc_header || proc_body.is_some()) {
+            let path = crate::completion::combine_tree_path(&iter, absolute, parts);
+            self.objtree.type_by_path(path.filter(|part| !PATH_KEYWORDS.contains(part)))?
+        } else {
+            // the type of a parameter or local var, which is always absolute
+            self.objtree.type_by_path(parts[..=idx].iter().filter(|part| !PATH_KEYWORDS.contains(&part.as_str())))?
+        };
+        if ty.is_root() || ty.name != *name {
+            return None;
+        }
+        resolved(Target::Type(ty), name, start, false)
+    }
+
+    /// Follow the type portion of a var or proc entry's path.
+    pub fn declaring_type(&self, path: &[String], keywords: &[&str]) -> TypeRef {
+        let mut current = self.objtree.root();
+        for part in path[..path.len() - 1].iter() {
+            if keywords.contains(&part.as_str()) {
+                break;
+            }
+            match current.child(part) {
+                Some(child) => current = child,
+                None => break,
+            }
+        }
+        current
+    }
+
+    /// Explain why the given symbol can't be renamed, if it can't.
+    pub fn check_rename(&self, symbol: &Symbol, name: &str) -> Result<(), String> {
+        let builtin = match *symbol {
+            // Code which mentions a builtin type takes over its location, so
+            // look for it among the builtins alone.
+            Symbol::Type(ref path) => self.objtree.find(path).is_none()
+                || ObjectTree::with_builtins_for(self.context.byond_version()).find(path).is_some(),
+            Symbol::Var(_) | Symbol::Proc(_) => {
+                if self.references_table.as_ref().map_or(false, |table| table.is_approximate(name)) {
+                    return Err(format!("cannot rename '{}': some uses of it through ':' can't be resolved", name));
+                }
+                self.symbol_declaration(symbol, name).map_or(true, |(_, location)| location.is_builtins())
+            },
+            Symbol::Local(_) => false,
+            Symbol::Parameter => return Err(format!("cannot rename parameter '{}', which callers may pass by name", name)),
+            Symbol::Macro(location) => location.is_builtins(),
+        };
+        if builtin {
+            Err(format!("cannot rename built-in '{}'", name))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that the new name won't collide with an existing symbol.
+    pub fn check_rename_conflict(&self, symbol: &Symbol, name: &str, new_name: &str) -> Result<(), String> {
+        let conflict = match *symbol {
+            Symbol::Type(ref path) => self.objtree.find(path)
+                .and_then(|ty| ty.parent_path())
+                .map_or(false, |parent| parent.child(new_name).is_some()),
+            // Subtypes of the declaring type see the renamed symbol too, so
+            // theirs would collide as well as those of its parents.
+            Symbol::Var(_) => self.symbol_declaration(symbol, name).map_or(false, |(ty, _)| {
+                let mut found = ty.get_var_declaration(new_name).is_some();
+                ty.recurse(&mut |ty| found |= ty.vars.contains_key(new_name));
+                found
+            }),
+            Symbol::Proc(_) => self.symbol_declaration(symbol, name).map_or(false, |(ty, _)| {
+                let mut found = ty.get_proc_declaration(new_name).is_some();
+                ty.recurse(&mut |ty| found |= ty.procs.contains_key(new_name));
+                found
+            }),
+            _ => false,
+        };
+        if conflict {
+            Err(format!("cannot rename '{}': '{}' already exists", name, new_name))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Find the type which declares a var or proc, and where it does so.
+    pub fn symbol_declaration(&self, symbol: &Symbol, name: &str) -> Option<(TypeRef, Location)> {
+        let mut result = None;
+        self.objtree.root().recurse(&mut |ty| {
+            match *symbol {
+                Symbol::Var(id) => if let Some(decl) = ty.vars.get(name).and_then(|var| var.declaration.as_ref()) {
+                    if decl.id == id {
+                        result = Some((ty, decl.location));
+                    }
+                },
+                Symbol::Proc(id) => if let Some(decl) = ty.procs.get(name).and_then(|proc| proc.declaration.as_ref()) {
+                    if decl.id == id {
+                        result = Some((ty, decl.location));
+                    }
+                },
+                _ => {}
+            }
+        });
+        result
+    }
+
+    /// List the files which might mention a type, var, or proc.
+    pub fn rename_files(&self, symbol: &Symbol, name: &str) -> BTreeSet<FileId> {
+        let mut ids = Vec::new();
+        let mut locations = Vec::new();
+        match *symbol {
+            Symbol::Type(ref path) => if let Some(ty) = self.objtree.find(path) {
+                // every subtype's path includes this type's name
+                ty.recurse(&mut |ty| {
+                    ids.push(ty.id);
+                    locations.push(ty.location);
+                    locations.extend(ty.vars.values().map(|var| var.value.location));
+                    locations.extend(ty.procs.values().flat_map(|proc| proc.value.iter().map(|value| value.location)));
+                });
+            },
+            Symbol::Var(id) => {
+                ids.push(id);
+                self.objtree.root().recurse(&mut |ty| {
+                    if let Some(var) = ty.vars.get(name) {
+                        if ty.get_var_declaration(name).map_or(false, |decl| decl.id == id) {
+                            locations.push(var.value.location);
+                        }
+                    }
+                });
+            },
+            Symbol::Proc(id) => {
+                ids.push(id);
+                self.objtree.root().recurse(&mut |ty| {
+                    if let Some(proc) = ty.procs.get(name) {
+                        if ty.get_proc_declaration(name).map_or(false, |decl| decl.id == id) {
+                            locations.extend(proc.value.iter().map(|value| value.location));
+                        }
+                    }
+                });
+            },
+            _ => {}
+        }
+        if let Some(ref table) = self.references_table {
+            for id in ids {
+                locations.extend(table.find_references(id, true));
+            }
+        }
+        locations.into_iter()
+            .filter(|location| !location.is_builtins())
+            .map(|location| location.file)
+            .collect()
+    }
+
+    /// Find every mention of a type, var, or proc within one file.
+    pub fn rename_in_file(&self, annotations: &AnnotationTree, text: &str, symbol: &Symbol, name: &str, new_name: &str) -> Vec<TextEdit> {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut seen = HashSet::new();
+        let mut edits = Vec::new();
+        for (span, annotation) in annotations.iter() {
+            match annotation {
+                Annotation::InSequence(_) |
+                Annotation::UnscopedCall(_) |
+                Annotation::UnscopedVar(_) |
+                Annotation::ScopedCall(..) |
+                Annotation::ScopedVar(..) => {}
+                _ => continue,
+            }
+            // skip code which came from a macro expansion
+            if word_at(&lines, span.start) != name || !seen.insert(span.start) {
+                continue;
+            }
+            match self.find_rename_symbol(annotations, span.start) {
+                Some((ref found, _, start)) if found == symbol && start == span.start => {
+                    edits.push(rename_edit(start, name, new_name));
+                }
+                _ => {}
+            }
+        }
+        edits
+    }
+
+    /// Find every mention of a macro, including in other macros' definitions
+    /// and in `#ifdef`s.
+    pub fn rename_macro(&self, definition: Location, name: &str, new_name: &str) -> Result<HashMap<Url, Vec<TextEdit>>, jsonrpc::Error> {
+        let defines = match self.defines {
+            Some(ref defines) => defines,
+            None => return Err(invalid_request("no preprocessor history")),
+        };
+        let mut files = vec![definition.file];
+        if let Some(ref include_graph) = self.include_graph {
+            files.push(include_graph.root());
+            files.extend(include_graph.inclusions().iter().filter_map(|inclusion| inclusion.file));
+        }
+
+        // Lexing again would report any errors a second time.
+        let context = self.context.clone_file_list();
+        let mut changes = HashMap::new();
+        for file in files.into_iter().collect::<BTreeSet<_>>() {
+            let url = self.file_url(file)?;
+            let text = match self.docs.get_contents(&url) {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            if !text.contains(name) {
+                continue;
+            }
+            let mut edits = Vec::new();
+            for token in Lexer::new(&context, file, text.bytes().map(Ok)) {
+                match token.token {
+                    Token::Ident(ref ident, _) if ident == name => {}
+                    _ => continue,
+                }
+                // check that this is the same macro and not an earlier or
+                // later one with the same name
+                let location = token.location;
+                if defines.range(interval_tree::range(location, location))
+                    .any(|(range, &(ref define_name, _))| define_name == name && range.start == definition)
+                {
+                    edits.push(rename_edit(location, name, new_name));
+                }
+            }
+            if !edits.is_empty() {
+                changes.insert(url, edits);
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// Check whether a proposed new name is a valid identifier and not a keyword.
+pub fn is_valid_name(name: &str) -> bool {
+    if PATH_KEYWORDS.contains(&name) || STATEMENT_KEYWORDS.contains(&name) {
+        return false;
+    }
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first == '_' || first.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(is_ident)
+}
+
+/// The identifier which starts at the given location in a file's lines, if
+/// any.
+pub fn word_at<'t>(lines: &[&'t str], location: Location) -> &'t str {
+    let line = match lines.get(location.line.saturating_sub(1) as usize) {
+        Some(&line) => line,
+        None => return "",
+    };
+    let start = location.column.saturating_sub(1) as usize;
+    if start > line.len() || !line.is_char_boundary(start) {
+        return "";
+    }
+    let rest = &line[start..];
+    let end = rest.find(|ch: char| !is_ident(ch)).unwrap_or(rest.len());
+    &rest[..end]
+}
+
+fn is_ident(ch: char) -> bool {
+    ch == '_' || ch.is_ascii_alphanumeric()
+}
+
+fn rename_edit(start: Location, name: &str, new_name: &str) -> TextEdit {
+    let start = location_to_position(start);
+    let mut end = start;
+    end.character += name.len() as u64;
+    TextEdit {
+        range: lsp_types::Range::new(start, end),
+        new_text: new_name.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dm::Context;
+
+    use super::*;
+
+    const CODE: &str = r#"
+/obj/item
+    var/power = 1
+    proc/zap(strength)
+        var/charge = power
+        charge += strength
+        return charge
+
+/obj/item/widget
+    power = 2
+    zap(strength)
+        var/charge = 2
+        return ..() + charge
+
+/obj/item/gadget
+    var/fuel
+    proc/refuel()
+
+/proc/use_item(obj/item/I)
+    I.zap(I.power)
+    I.name = "used"
+"#;
+
+    /// Parse `CODE` into an engine, as the language server would.
+    fn parse(context: &Context) -> (Engine, AnnotationTree, FileId) {
+        let mut engine = Engine::new(context, Vec::new());
+        let mut pp = dm::preprocessor::Preprocessor::from_buffer(context, "rename.dm".into(), CODE);
+        let mut annotations = AnnotationTree::default();
+        {
+            let indent = dm::indents::IndentProcessor::new(context, &mut pp);
+            let mut parser = dm::parser::Parser::new(context, indent);
+            parser.annotate_to(&mut annotations);
+            engine.objtree = Arc::new(parser.parse_object_tree());
+        }
+        engine.references_table = Some(crate::find_references::ReferencesTable::new(&engine.objtree));
+        let file_id = context.get_file("rename.dm".as_ref()).unwrap();
+        (engine, annotations, file_id)
+    }
+
+    /// Rename the symbol at the start of the first occurrence of `at` on a
+    /// line, the way the `Rename` request does, and return the new code.
+    fn rename(line: u32, at: &str, new_name: &str) -> Result<String, String> {
+        let context = Context::default();
+        let (engine, annotations, file) = parse(&context);
+        let column = CODE.lines().nth(line as usize - 1).unwrap().find(at).unwrap() as u16 + 1;
+        let location = Location { file, line, column };
+        let (symbol, name, _) = engine.find_rename_symbol(&annotations, location).ok_or("nothing to rename")?;
+        engine.check_rename(&symbol, &name)?;
+        if !is_valid_name(new_name) {
+            return Err(format!("not a valid name: {}", new_name));
+        }
+        engine.check_rename_conflict(&symbol, &name, new_name)?;
+
+        let mut lines: Vec<String> = CODE.lines().map(ToOwned::to_owned).collect();
+        let mut edits = engine.rename_in_file(&annotations, CODE, &symbol, &name, new_name);
+        edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+        for edit in edits.iter().rev() {
+            let start = edit.range.start;
+            lines[start.line as usize].replace_range(start.character as usize..edit.range.end.character as usize, &edit.new_text);
+        }
+        Ok(lines.join("\n") + "\n")
+    }
+
+    #[test]
+    fn rename_local() {
+        let renamed = rename(5, "charge", "energy").unwrap();
+        assert!(renamed.contains("        var/energy = power\n        energy += strength\n        return energy\n"));
+        // the override's local of the same name is a different var
+        assert!(renamed.contains("        var/charge = 2\n        return ..() + charge\n"));
+    }
+
+    #[test]
+    fn rename_proc_overrides() {
+        let renamed = rename(20, "zap", "shock").unwrap();
+        assert!(renamed.contains("    proc/shock(strength)\n"));
+        assert!(renamed.contains("\n    shock(strength)\n"));
+        assert!(renamed.contains("    I.shock(I.power)\n"));
+        assert!(!renamed.contains("zap"));
+    }
+
+    #[test]
+    fn rename_var() {
+        let renamed = rename(3, "power", "output").unwrap();
+        assert!(renamed.contains("    var/output = 1\n"));
+        assert!(renamed.contains("        var/charge = output\n"));
+        assert!(renamed.contains("\n    output = 2\n"));
+        assert!(renamed.contains("    I.zap(I.output)\n"));
+        assert!(!renamed.contains("power"));
+    }
+
+    #[test]
+    fn rename_rejected() {
+        assert!(rename(3, "power", "2fast").is_err());
+        assert!(rename(3, "power", "proc").is_err());
+        assert!(rename(3, "power", "while").is_err());
+        // already defined on a subtype
+        assert!(rename(16, "fuel", "power").is_err());
+        assert!(rename(2, "obj", "thing").is_err());
+        assert!(rename(2, "item", "thing").is_ok());
+        assert!(rename(21, "name", "title").is_err());
+        assert!(rename(4, "strength", "force").is_err());
+    }
+
+    #[test]
+    fn valid_names() {
+        assert!(is_valid_name("_private2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("has space"));
+        assert!(!is_valid_name("var"));
+        assert!(!is_valid_name("return"));
+    }
+}
@@ -0,0 +1,209 @@
+//! Semantic highlighting of identifiers, from the annotation tree.
+
+use dm::{FileId, Location};
+use dm::annotation::AnnotationTree;
+use dm::ast::VarType;
+use dm::docs::DocCollection;
+use dm::lexer::Token;
+use dm::objtree::{ObjectTree, TypeRef};
+
+use crate::Engine;
+use crate::extras::{SemanticTokensLegend, SemanticTokensOptions};
+use crate::rename::Target;
+
+/// The kinds of identifier which are highlighted, in legend order.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TokenType {
+    Type,
+    Proc,
+    Verb,
+    Var,
+    Local,
+    Parameter,
+    Macro,
+}
+
+/// Standard token types, which clients already have colors for.
+const TOKEN_TYPES: &[&str] = &[
+    "type",
+    "function",
+    "method",
+    "property",
+    "variable",
+    "parameter",
+    "macro",
+];
+
+// Modifier bits, in legend order.
+pub const DECLARATION: u32 = 1 << 0;
+pub const STATIC: u32 = 1 << 1;
+pub const BUILTIN: u32 = 1 << 2;
+pub const DEPRECATED: u32 = 1 << 3;
+pub const CONST: u32 = 1 << 4;
+
+const TOKEN_MODIFIERS: &[&str] = &[
+    "declaration",
+    "static",
+    "defaultLibrary",
+    "deprecated",
+    "readonly",
+];
+
+/// The semantic tokens capability advertised to the client.
+pub fn options() -> SemanticTokensOptions {
+    SemanticTokensOptions {
+        legend: SemanticTokensLegend {
+            token_types: TOKEN_TYPES.iter().map(|&s| s.to_owned()).collect(),
+            token_modifiers: TOKEN_MODIFIERS.iter().map(|&s| s.to_owned()).collect(),
+        },
+        range: true,
+        full: true,
+    }
+}
+
+impl<'a> Engine<'a> {
+    /// Classify the identifiers in a document, optionally only those on the
+    /// given lines, and encode them for the client.
+    pub fn semantic_tokens(&mut self, url: &url::Url, lines: Option<std::ops::Range<u32>>) -> Result<Vec<u32>, jsonrpc::Error> {
+        let (real_file_id, file_id, annotations) = self.get_annotations(url)?;
+        let text = self.docs.get_contents(url).map_err(crate::invalid_request)?.into_owned();
+        let text_lines: Vec<&str> = text.split('\n').collect();
+
+        // Code which mentions a builtin type takes over its location.
+        let builtins = ObjectTree::with_builtins_for(self.context.byond_version());
+        let mut data = Vec::new();
+        let mut previous = (0, 0);
+        for token in crate::lex_scratch(&text) {
+            let name = match token.token {
+                Token::Ident(ref name, _) => name,
+                _ => continue,
+            };
+            let line = token.location.line - 1;
+            if lines.as_ref().map_or(false, |lines| !lines.contains(&line)) {
+                continue;
+            }
+            let location = Location { file: file_id, ..token.location };
+            let (token_type, modifiers) = match self.classify_identifier(&annotations, location, name, real_file_id, &builtins) {
+                Some(found) => found,
+                None => continue,
+            };
+
+            // Positions are in UTF-16 code units.
+            let line_text = text_lines.get(line as usize).cloned().unwrap_or("");
+            let start = utf16_len(line_text.get(..token.location.column as usize - 1).unwrap_or(""));
+            let length = utf16_len(name);
+            let delta_start = if line == previous.0 { start - previous.1 } else { start };
+            data.extend_from_slice(&[line - previous.0, delta_start, length, token_type as u32, modifiers]);
+            previous = (line, start);
+        }
+        Ok(data)
+    }
+
+    /// Decide how the identifier at the given location should be
+    /// highlighted, if it means anything.
+    pub fn classify_identifier(
+        &self,
+        annotations: &AnnotationTree,
+        location: Location,
+        name: &str,
+        real_file_id: FileId,
+        builtins: &ObjectTree,
+    ) -> Option<(TokenType, u32)> {
+        let resolved = self.resolve_name(annotations, location)?;
+        if resolved.start != location || resolved.name != name {
+            return None;
+        }
+        let declaration = if resolved.declaration { DECLARATION } else { 0 };
+        Some(match resolved.target {
+            Target::Type(ty) => type_token(ty, location, real_file_id, builtins),
+            Target::Var(ty) => {
+                let (token_type, modifiers) = var_token(ty, name)?;
+                (token_type, modifiers | declaration)
+            },
+            Target::Proc(ty) => {
+                let (token_type, modifiers) = proc_token(ty, name)?;
+                (token_type, modifiers | declaration)
+            },
+            Target::Local(_, var_type) => local_token(var_type, declaration),
+            Target::Parameter => (TokenType::Parameter, declaration),
+            Target::Macro(definition) if definition.is_builtins() => (TokenType::Macro, BUILTIN),
+            Target::Macro(_) => (TokenType::Macro, declaration),
+        })
+    }
+}
+
+fn type_token(ty: TypeRef, location: Location, real_file_id: FileId, builtins: &ObjectTree) -> (TokenType, u32) {
+    let mut modifiers = 0;
+    if builtins.find(&ty.path).is_some() {
+        modifiers |= BUILTIN;
+    } else if ty.location.file == real_file_id && ty.location.line == location.line {
+        // types are located at the start of the path which first mentions them
+        modifiers |= DECLARATION;
+    }
+    if is_deprecated(&ty.docs) {
+        modifiers |= DEPRECATED;
+    }
+    (TokenType::Type, modifiers)
+}
+
+fn proc_token(ty: TypeRef, name: &str) -> Option<(TokenType, u32)> {
+    let decl = ty.get_proc_declaration(name)?;
+    let token_type = if decl.kind == dm::ast::ProcDeclKind::Verb { TokenType::Verb } else { TokenType::Proc };
+    let mut modifiers = 0;
+    if decl.location.is_builtins() {
+        modifiers |= BUILTIN;
+    }
+    // deprecated if it or anything it overrides is documented so
+    let mut next = ty.get_proc(name);
+    while let Some(proc) = next {
+        if is_deprecated(&proc.get().docs) {
+            modifiers |= DEPRECATED;
+            break;
+        }
+        next = proc.parent_proc();
+    }
+    Some((token_type, modifiers))
+}
+
+fn var_token(ty: TypeRef, name: &str) -> Option<(TokenType, u32)> {
+    let decl = ty.get_var_declaration(name)?;
+    let (_, mut modifiers) = local_token(&decl.var_type, 0);
+    if decl.location.is_builtins() {
+        modifiers |= BUILTIN;
+    }
+    let mut next = Some(ty);
+    while let Some(ty) = next {
+        if ty.get().vars.get(name).map_or(false, |var| is_deprecated(&var.value.docs)) {
+            modifiers |= DEPRECATED;
+            break;
+        }
+        next = ty.parent_type();
+    }
+    Some((TokenType::Var, modifiers))
+}
+
+fn local_token(var_type: &VarType, mut modifiers: u32) -> (TokenType, u32) {
+    if var_type.is_static {
+        modifiers |= STATIC;
+    }
+    if var_type.is_const {
+        modifiers |= CONST;
+    }
+    (TokenType::Local, modifiers)
+}
+
+/// Check whether documentation marks its subject deprecated, by a line
+/// starting with "Deprecated" or containing `@deprecated`.
+fn is_deprecated(docs: &DocCollection) -> bool {
+    if docs.is_empty() {
+        return false;
+    }
+    docs.text().lines().any(|line| {
+        let line = line.trim_start().to_lowercase();
+        line.starts_with("deprecated") || line.contains("@deprecated")
+    })
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.chars().map(|ch| ch.len_utf16() as u32).sum()
+}
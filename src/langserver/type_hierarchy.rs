@@ -0,0 +1,168 @@
+//! Supporting functions for the type hierarchy and override listings.
+
+use lsp_types::{SymbolKind, TextDocumentPositionParams};
+
+use dm::Location;
+use dm::annotation::Annotation;
+use dm::objtree::TypeRef;
+
+use crate::{Engine, invalid_request};
+use crate::extras::{Override, OverridesResult, TypeHierarchyItem};
+use crate::rename::{PATH_KEYWORDS, Symbol};
+
+impl<'a> Engine<'a> {
+    /// Create the type hierarchy item for the type which is named at the
+    /// given position, or whose definition the position is within.
+    pub fn prepare_type_hierarchy(&mut self, params: &TextDocumentPositionParams) -> Result<Option<TypeHierarchyItem>, jsonrpc::Error> {
+        let (_, file_id, annotations) = self.get_annotations(&params.text_document.uri)?;
+        let location = Location {
+            file: file_id,
+            line: params.position.line as u32 + 1,
+            column: params.position.character as u16 + 1,
+        };
+        let iter = annotations.get_location(location);
+
+        let mut found = None;
+        for (_, annotation) in iter.clone() {
+            match annotation {
+                Annotation::TreePath(absolute, parts) => {
+                    let path = crate::completion::combine_tree_path(&iter, *absolute, parts);
+                    found = self.objtree.type_by_path(path.filter(|part| !PATH_KEYWORDS.contains(part)));
+                },
+                Annotation::TypePath(parts) => {
+                    // 'datum/bar', but not '/datum/proc/foo'
+                    if let Some(result) = self.follow_type_path(&iter, parts) {
+                        if result.decl.is_none() && result.proc.is_none() {
+                            found = Some(result.ty);
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+        if found.is_none() {
+            found = self.find_type_context(&iter).0;
+        }
+
+        match found {
+            Some(ty) if !ty.is_root() => Ok(Some(self.type_hierarchy_item(ty)?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn type_hierarchy_item(&self, ty: TypeRef) -> Result<TypeHierarchyItem, jsonrpc::Error> {
+        let location = self.convert_location(ty.location, &[&ty.path])?;
+        Ok(TypeHierarchyItem {
+            name: ty.name.to_owned(),
+            kind: SymbolKind::Class,
+            detail: Some(ty.path.to_owned()),
+            uri: location.uri,
+            range: location.range,
+            selection_range: location.range,
+            data: Some(serde_json::Value::String(ty.path.to_owned())),
+        })
+    }
+
+    /// Find the type a type hierarchy item was created for.
+    fn type_hierarchy_type(&self, item: &TypeHierarchyItem) -> Result<TypeRef, jsonrpc::Error> {
+        let path = match item.data {
+            Some(serde_json::Value::String(ref path)) => path,
+            _ => return Err(invalid_request("type hierarchy item is missing its data")),
+        };
+        self.objtree.find(path).ok_or_else(|| invalid_request(format!("{} no longer exists", path)))
+    }
+
+    /// Find the parent type of a type hierarchy item.
+    pub fn supertypes(&self, item: &TypeHierarchyItem) -> Result<Vec<TypeHierarchyItem>, jsonrpc::Error> {
+        let ty = self.type_hierarchy_type(item)?;
+        let mut results = Vec::new();
+        if let Some(parent) = ty.parent_type_without_root() {
+            results.push(self.type_hierarchy_item(parent)?);
+        }
+        Ok(results)
+    }
+
+    /// Find the types whose parent type is a type hierarchy item.
+    ///
+    /// These are usually its child paths, but `parent_type` can make any
+    /// type a subtype of any other.
+    pub fn subtypes(&self, item: &TypeHierarchyItem) -> Result<Vec<TypeHierarchyItem>, jsonrpc::Error> {
+        let ty = self.type_hierarchy_type(item)?;
+        let mut subtypes = Vec::new();
+        self.objtree.root().recurse(&mut |each| {
+            if each.parent_type().map_or(false, |parent| parent.path == ty.path) && !each.is_root() {
+                subtypes.push(each);
+            }
+        });
+        subtypes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut results = Vec::new();
+        for each in subtypes {
+            results.push(self.type_hierarchy_item(each)?);
+        }
+        Ok(results)
+    }
+
+    /// List every type which declares or overrides the var or proc at the
+    /// given position, with its value or parameters there.
+    pub fn find_overrides(&mut self, params: &TextDocumentPositionParams) -> Result<Option<OverridesResult>, jsonrpc::Error> {
+        let (_, file_id, annotations) = self.get_annotations(&params.text_document.uri)?;
+        let location = Location {
+            file: file_id,
+            line: params.position.line as u32 + 1,
+            column: params.position.character as u16 + 1,
+        };
+        let (symbol, name) = match self.find_rename_symbol(&annotations, location) {
+            Some((symbol @ Symbol::Var(_), name, _)) |
+            Some((symbol @ Symbol::Proc(_), name, _)) => (symbol, name),
+            _ => return Ok(None),
+        };
+        let declaring_type = match self.symbol_declaration(&symbol, &name) {
+            Some((ty, _)) => ty,
+            None => return Ok(None),
+        };
+
+        let mut found = Vec::new();
+        self.objtree.root().recurse(&mut |ty| {
+            if ty.is_subtype_of(&declaring_type) {
+                found.push(ty);
+            }
+        });
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut overrides = Vec::new();
+        for ty in found {
+            match symbol {
+                Symbol::Var(_) => if let Some(var) = ty.get().vars.get(&name) {
+                    overrides.push(Override {
+                        type_path: ty.pretty_path().to_owned(),
+                        location: self.convert_location(var.value.location, &[&ty.path, "/var/", &name])?,
+                        declaration: var.declaration.is_some(),
+                        value: var.value.constant.as_ref().map(ToString::to_string),
+                    });
+                },
+                Symbol::Proc(_) => if let Some(proc) = ty.get().procs.get(&name) {
+                    // the last definition on a type is the one which is used
+                    let value = proc.value.last().unwrap();
+                    let parameters: Vec<String> = value.parameters.iter().map(ToString::to_string).collect();
+                    overrides.push(Override {
+                        type_path: ty.pretty_path().to_owned(),
+                        location: self.convert_location(value.location, &[&ty.path, "/proc/", &name])?,
+                        declaration: proc.declaration.is_some(),
+                        value: Some(format!("{}({})", name, parameters.join(", "))),
+                    });
+                },
+                _ => {}
+            }
+        }
+
+        Ok(Some(OverridesResult {
+            kind: match symbol {
+                Symbol::Var(_) => SymbolKind::Field,
+                _ => SymbolKind::Method,
+            },
+            name,
+            overrides,
+        }))
+    }
+}
@@ -3,6 +3,10 @@
 
 extern crate dreammaker as dm;
 
+extern crate serde;
+extern crate serde_json;
+#[macro_use] extern crate serde_derive;
+
 #[cfg(feature="png")] extern crate png;
 extern crate lodepng;
 extern crate inflate;
@@ -19,5 +23,6 @@ mod icon_cache;
 pub mod minimap;
 pub mod render_passes;
 pub mod dmi;
+pub mod objtree_json;
 
 pub use icon_cache::IconCache;
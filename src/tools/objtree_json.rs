@@ -0,0 +1,175 @@
+//! Export of the object tree to JSON, for web tools and wikis.
+//!
+//! The output lists every type in path order. Vars and procs appear in the
+//! order they were declared, so the same code always produces the same JSON.
+
+use std::io;
+
+use serde_json;
+
+use dm::Context;
+use dm::ast::{FormatTreePath, VarType};
+use dm::objtree::{ObjectTree, TypeRef, TypeVar, TypeProc, ProcValue};
+
+/// The version of the export format, bumped on incompatible changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Debug)]
+pub struct Tree {
+    pub version: u32,
+    pub types: Vec<Type>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+    pub column: u16,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Type {
+    pub path: String,
+    pub parent: Option<String>,
+    pub location: Option<Location>,
+    pub docs: Option<String>,
+    pub vars: Vec<Var>,
+    pub procs: Vec<Proc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Var {
+    pub name: String,
+    /// Whether this type declares the var, rather than overriding it.
+    pub is_declaration: bool,
+    pub is_static: bool,
+    pub is_const: bool,
+    pub is_tmp: bool,
+    pub is_final: bool,
+    pub type_path: Option<String>,
+    /// The value as DM code, if it could be evaluated as a constant.
+    pub value: Option<String>,
+    pub location: Option<Location>,
+    pub docs: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Proc {
+    pub name: String,
+    /// "proc" or "verb" if this type declares the proc, otherwise absent.
+    pub kind: Option<&'static str>,
+    pub parameters: Vec<Parameter>,
+    pub location: Option<Location>,
+    pub docs: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub type_path: Option<String>,
+    /// The default value as DM code, if it could be evaluated as a constant.
+    pub default: Option<String>,
+}
+
+/// Build the exportable representation of the entire object tree.
+pub fn export(context: &Context, objtree: &ObjectTree) -> Tree {
+    let mut types = Vec::new();
+    types.push(export_type(context, objtree.root()));
+    for path in objtree.types.keys() {
+        types.push(export_type(context, objtree.expect(path)));
+    }
+    Tree {
+        version: FORMAT_VERSION,
+        types,
+    }
+}
+
+/// Write the entire object tree as JSON.
+pub fn write_json<W: io::Write>(context: &Context, objtree: &ObjectTree, pretty: bool, output: W) -> io::Result<()> {
+    let tree = export(context, objtree);
+    if pretty {
+        serde_json::to_writer_pretty(output, &tree)?;
+    } else {
+        serde_json::to_writer(output, &tree)?;
+    }
+    Ok(())
+}
+
+fn export_type(context: &Context, ty: TypeRef) -> Type {
+    Type {
+        path: ty.path.clone(),
+        parent: ty.parent_type().map(|parent| parent.path.clone()),
+        location: location(context, ty.location),
+        docs: docs(&ty.docs),
+        vars: ty.vars.iter().map(|(name, var)| export_var(context, name, var)).collect(),
+        procs: ty.procs.iter().flat_map(|(name, type_proc)| export_procs(context, name, type_proc)).collect(),
+    }
+}
+
+fn export_var(context: &Context, name: &str, var: &TypeVar) -> Var {
+    let declaration = var.declaration.as_ref();
+    let override_type = VarType::default();
+    let var_type = declaration.map_or(&override_type, |decl| &decl.var_type);
+    Var {
+        name: name.to_owned(),
+        is_declaration: declaration.is_some(),
+        is_static: var_type.is_static,
+        is_const: var_type.is_const,
+        is_tmp: var_type.is_tmp,
+        is_final: var_type.is_final,
+        type_path: tree_path(&var_type.type_path),
+        value: var.value.constant.as_ref().map(ToString::to_string),
+        location: location(context, var.value.location),
+        docs: docs(&var.value.docs),
+    }
+}
+
+fn export_procs<'a>(context: &'a Context, name: &'a str, type_proc: &'a TypeProc) -> impl Iterator<Item=Proc> + 'a {
+    let kind = type_proc.declaration.as_ref().map(|decl| decl.kind.name());
+    type_proc.value.iter().enumerate().map(move |(i, value)| {
+        export_proc(context, name, if i == 0 { kind } else { None }, value)
+    })
+}
+
+fn export_proc(context: &Context, name: &str, kind: Option<&'static str>, value: &ProcValue) -> Proc {
+    Proc {
+        name: name.to_owned(),
+        kind,
+        parameters: value.parameters.iter().map(|param| Parameter {
+            name: param.name.clone(),
+            type_path: tree_path(&param.var_type.type_path),
+            default: param.default.as_ref()
+                .and_then(|expr| expr.clone().simple_evaluate(param.location).ok())
+                .map(|constant| constant.to_string()),
+        }).collect(),
+        location: location(context, value.location),
+        docs: docs(&value.docs),
+    }
+}
+
+fn location(context: &Context, location: dm::Location) -> Option<Location> {
+    if location.is_builtins() {
+        return None;
+    }
+    Some(Location {
+        file: context.file_path(location.file).display().to_string(),
+        line: location.line,
+        column: location.column,
+    })
+}
+
+fn docs(docs: &dm::docs::DocCollection) -> Option<String> {
+    if docs.is_empty() {
+        None
+    } else {
+        Some(docs.text())
+    }
+}
+
+fn tree_path(path: &[String]) -> Option<String> {
+    if path.is_empty() {
+        None
+    } else {
+        Some(FormatTreePath(path).to_string())
+    }
+}
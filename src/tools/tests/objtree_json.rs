@@ -0,0 +1,44 @@
+extern crate dreammaker as dm;
+extern crate dmm_tools;
+
+use dm::preprocessor::Preprocessor;
+use dm::indents::IndentProcessor;
+use dm::parser::Parser;
+use dmm_tools::objtree_json;
+
+const CODE: &str = r#"
+/obj/item
+    var/w_class = 2
+    proc/attack(mob/M, force = 5)
+/obj/item/large
+    w_class = 4
+    attack(M)
+"#;
+
+#[test]
+fn export_types() {
+    let context = dm::Context::default();
+    let pp = Preprocessor::from_buffer(&context, "objtree_json.rs".into(), CODE);
+    let tree = Parser::new(&context, IndentProcessor::new(&context, pp)).parse_object_tree();
+    let export = objtree_json::export(&context, &tree);
+
+    let item = export.types.iter().find(|ty| ty.path == "/obj/item").expect("missing /obj/item");
+    assert_eq!(item.parent.as_deref(), Some("/obj"));
+    assert_eq!(item.vars[0].name, "w_class");
+    assert!(item.vars[0].is_declaration);
+    assert_eq!(item.vars[0].value.as_deref(), Some("2"));
+    assert_eq!(item.procs[0].kind, Some("proc"));
+    assert_eq!(item.procs[0].parameters[0].type_path.as_deref(), Some("/mob"));
+    assert_eq!(item.procs[0].parameters[1].default.as_deref(), Some("5"));
+
+    let large = export.types.iter().find(|ty| ty.path == "/obj/item/large").expect("missing /obj/item/large");
+    assert!(!large.vars[0].is_declaration);
+    assert_eq!(large.vars[0].value.as_deref(), Some("4"));
+    assert_eq!(large.procs[0].kind, None);
+
+    // Types are listed in path order, so the output is stable.
+    let paths: Vec<&str> = export.types.iter().map(|ty| ty.path.as_str()).collect();
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(paths, sorted);
+}